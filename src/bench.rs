@@ -0,0 +1,53 @@
+use rand::SeedableRng;
+use crate::save::{Game, GameRng};
+
+/// Plays `turns` turns of a copy of `config` without ever trading, using a random number
+/// generator seeded with `seed`, and returns the game at the end. The same arguments
+/// always give the same game, which makes it useful for timing the market model.
+///
+/// The copy is made through the game's JSON, so a custom `Game::market` isn't carried
+/// over and the copy uses the default market.
+pub fn run_turns(config: &Game, turns: u32, seed: u64) -> Game {
+    // Safe unwrap because a game always turns into JSON it can be read back from
+    let mut game = Game::from_json(&config.to_json().unwrap()).unwrap();
+    game.seed = seed;
+    let mut rng = GameRng::seed_from_u64(seed);
+
+    for _ in 0..turns {
+        game.ensure_market(&mut rng);
+        game.tick(&mut rng);
+        game.net_worth_cached();
+    }
+
+    game.store_rng(&rng);
+    game
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Stock;
+
+    fn config() -> Game {
+        let stocks = (0..5).map(|id| Stock::new(id, format!("Stock {}", id), 1000, 50)).collect();
+        Game::new(i64::MAX, 100, stocks)
+    }
+
+    #[test]
+    fn runs_the_requested_number_of_turns() {
+        let config = config();
+        let game = run_turns(&config, 40, 7);
+        assert_eq!(game.turn, config.turn + 40);
+        assert_eq!(game.seed, 7);
+        assert_eq!(config.turn, 0);
+        assert_eq!(game.player.net_worth(&game.stocks), game.net_worth_cached());
+    }
+
+    #[test]
+    fn the_same_seed_plays_the_same_game() {
+        let config = config();
+        let json = |game: Game| game.to_json().unwrap();
+        assert_eq!(json(run_turns(&config, 100, 3)), json(run_turns(&config, 100, 3)));
+        assert_ne!(json(run_turns(&config, 100, 3)), json(run_turns(&config, 100, 4)));
+    }
+}
@@ -1,26 +1,172 @@
-use std::collections::HashMap;
-use std::cmp::Ordering;
+#![allow(clippy::result_unit_err)]
+
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet, VecDeque};
+use std::cmp::{Ordering, Reverse};
+use std::convert::TryFrom;
 use std::hash::{Hash, Hasher};
 use std::fmt::{self, Display, Formatter};
-use rand::Rng;
-use serde::{Serialize, Deserialize};
+use rand::{Rng, RngCore};
+use rand::distributions::WeightedIndex;
+use serde::{Serialize, Serializer, Deserialize};
 
+pub mod bench;
 pub mod save;
 
+/// How many past values a stock remembers.
+pub const HISTORY_LEN: usize = 20;
+
+/// How many turns make up a year. A turn is a week.
+pub const TURNS_PER_YEAR: i64 = 52;
+
+/// Stocks younger than this many turns count as new.
+pub const NEW_STOCK_TURNS: u32 = 5;
+
+/// How a stock's value moves each turn.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Default)]
+pub enum StockBehavior {
+    /// Moves randomly, based on the stock's variation and drift.
+    #[default]
+    RandomWalk,
+    /// Follows a fixed series of prices, holding at the last one once it runs out.
+    /// `index` is the position of the current value in `prices`.
+    Scripted { prices: Vec<i64>, index: usize },
+}
+
+/// Decides how stock values move each turn. Swap a game's model to change how the
+/// market behaves. Models are `Send` and `Sync` so a game holding one can still be moved
+/// to or shared with another thread.
+pub trait MarketModel: Send + Sync {
+    /// Moves every stock's value on by a turn.
+    fn step(&mut self, stocks: &mut [Stock], rng: &mut dyn RngCore);
+
+    /// Scales how far stocks can move this turn, in basis points of their usual range,
+    /// such as for a difficulty ramp. Models that don't support scaling can ignore it.
+    fn set_volatility_scale(&mut self, _scale_bps: i64) {}
+}
+
+/// The usual market: every stock varies on its own with `Stock::vary`.
+pub struct DefaultMarket {
+    scale_bps: i64,
+}
+
+impl Default for DefaultMarket {
+    fn default() -> Self { Self { scale_bps: 10000 } }
+}
+
+impl MarketModel for DefaultMarket {
+    fn step(&mut self, stocks: &mut [Stock], mut rng: &mut dyn RngCore) {
+        for s in stocks.iter_mut() { s.vary_scaled(&mut rng, self.scale_bps); }
+    }
+
+    fn set_volatility_scale(&mut self, scale_bps: i64) { self.scale_bps = scale_bps; }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Stock {
+    /// The expected return over a year of turns, in basis points.
+    #[serde(default)]
+    annual_drift_bps: i64,
+    #[serde(default)]
+    behavior: StockBehavior,
     direction: i64,
+    /// Drift too small to have moved the value yet, in 1/(10000 * `TURNS_PER_YEAR`) units
+    /// of value, carried over to the next turn.
+    #[serde(default)]
+    drift_remainder: i64,
+    #[serde(default)]
+    history: VecDeque<i64>,
     id: i64,
     initial_value: i64,
+    #[serde(default)]
+    halted: bool,
+    #[serde(default)]
+    low_turns: u32,
     name: String,
+    /// How many turns the stock has been on the market. Stocks from saves that didn't
+    /// track this are treated as no longer new.
+    #[serde(default = "default_turns_alive")]
+    turns_alive: u32,
     value: i64,
     variation: i64,
 }
 
+fn default_turns_alive() -> u32 { NEW_STOCK_TURNS }
+
 impl Stock {
     /// Generates a new stock.
     pub fn new(id: i64, name: String, value: i64, variation: i64) -> Self {
-        Self { direction: 0, id, initial_value: value, name, value, variation }
+        let mut history = VecDeque::with_capacity(HISTORY_LEN);
+        history.push_back(value);
+        Self {
+            annual_drift_bps: 0, behavior: StockBehavior::RandomWalk, direction: 0,
+            drift_remainder: 0, halted: false, history, id, initial_value: value, low_turns: 0,
+            name, turns_alive: 0, value, variation,
+        }
+    }
+
+    /// Creates a stock with every part given explicitly, such as a stock that's already
+    /// mid-trend. Most callers want `new` instead.
+    pub fn from_parts(id: i64, name: String, value: i64, initial_value: i64, variation: i64,
+                      direction: i64) -> Self {
+        Self { direction, initial_value, ..Self::new(id, name, value, variation) }
+    }
+
+    /// Sets the stock's current value. Values below 1 are raised to 1.
+    pub fn with_value(mut self, value: i64) -> Self {
+        self.value = value.max(1);
+        if let Some(last) = self.history.back_mut() { *last = self.value; }
+        self
+    }
+
+    /// Sets how much the stock can move each turn. Negative variations are treated as 0.
+    pub fn with_variation(mut self, variation: i64) -> Self {
+        self.variation = variation.max(0);
+        self
+    }
+
+    /// Gives the stock an expected return over a year of turns, in basis points, so it
+    /// trends up (or down, if negative) over time.
+    pub fn with_annual_drift(mut self, annual_drift_bps: i64) -> Self {
+        self.annual_drift_bps = annual_drift_bps;
+        self
+    }
+
+    /// Makes the stock follow `prices` instead of moving randomly. The stock's value
+    /// becomes the first price. Does nothing if `prices` is empty.
+    pub fn with_price_series(mut self, prices: Vec<i64>) -> Self {
+        if let Some(&first) = prices.first() {
+            self.value = first;
+            self.initial_value = first;
+            self.history.clear();
+            self.record_value();
+            self.behavior = StockBehavior::Scripted { prices, index: 0 };
+        }
+        self
+    }
+
+    /// Getter for how the stock's value moves.
+    pub fn behavior(&self) -> &StockBehavior { &self.behavior }
+
+    /// Getter for the stock's expected return over a year of turns, in basis points.
+    pub fn annual_drift_bps(&self) -> i64 { self.annual_drift_bps }
+
+    /// How much the value changed over the last turn, in basis points of the previous
+    /// value. 0 if there's no previous value to compare to.
+    pub fn last_change_bps(&self) -> i64 {
+        let mut recent = self.history.iter().rev();
+        match (recent.next(), recent.next()) {
+            (Some(cur), Some(prev)) if *prev > 0 => (cur - prev) * 10000 / prev,
+            _ => 0,
+        }
+    }
+
+    /// How much money it takes to go from holding `current_shares` to `target_shares` at
+    /// the current value, with a fee of `fee_bps` basis points added to each share
+    /// (rounded up). 0 if `current_shares` already reaches the target.
+    pub fn cost_to_reach(&self, current_shares: i64, target_shares: i64, fee_bps: i64) -> i64 {
+        let shares = (target_shares - current_shares).max(0);
+        let price = self.value + RoundingMode::Ceil.divide(self.value * fee_bps, 10000);
+        shares * price
     }
 
     /// Getter for the current value of the stock.
@@ -32,19 +178,155 @@ impl Stock {
     /// Getter for the stock's id
     pub fn id(&self) -> i64 { self.id }
 
-    /// Varies the value of the stock.
-    pub fn vary(&mut self) {
-        let random = rand::thread_rng().gen_range(-self.variation..=self.variation);
+    /// Varies the value of the stock. On top of the random move, the value grows by a
+    /// turn's share of the stock's drift.
+    pub fn vary<R: Rng>(&mut self, rng: &mut R) {
+        self.vary_scaled(rng, 10000);
+    }
+
+    /// Like `vary`, but the random move's range is scaled by `scale_bps` basis points of
+    /// the stock's variation. Scripted stocks aren't affected by the scale.
+    pub fn vary_scaled<R: Rng>(&mut self, rng: &mut R, scale_bps: i64) {
+        if let StockBehavior::Scripted { prices, index } = &mut self.behavior {
+            if prices.is_empty() { return; }
+            *index = (*index + 1).min(prices.len() - 1);
+            self.value = prices[*index];
+            self.record_value();
+            return;
+        }
+
+        let variation = self.variation * scale_bps / 10000;
+        let random = rng.gen_range(-variation..=variation);
         // ((x * 3) / 5) == x * 0.6, but no need to cast twice
         self.direction = ((self.direction * 3)/5) + random;
-        self.value += self.direction;
+        self.value += self.direction + self.take_drift();
+        self.record_value();
+    }
+
+    /// This turn's drift. The fraction of a unit of value left over is kept for later
+    /// turns, so drift adds up even on cheap stocks.
+    fn take_drift(&mut self) -> i64 {
+        let scale = 10000 * TURNS_PER_YEAR;
+        let total = self.value * self.annual_drift_bps + self.drift_remainder;
+        self.drift_remainder = total % scale;
+        total / scale
+    }
+
+    fn record_value(&mut self) {
+        if self.history.len() == HISTORY_LEN { self.history.pop_front(); }
+        self.history.push_back(self.value);
+    }
+
+    /// The most recent values of the stock, oldest first. Includes the current value.
+    pub fn history(&self) -> &VecDeque<i64> { &self.history }
+
+    /// The standard deviation of the stock's recent per-turn returns. Returns `None` if
+    /// the history is too short (fewer than two returns).
+    pub fn realized_volatility(&self) -> Option<f64> {
+        let returns: Vec<f64> = self.history.iter().zip(self.history.iter().skip(1))
+            .filter(|(prev, _)| **prev > 0)
+            .map(|(prev, cur)| (cur - prev) as f64 / *prev as f64)
+            .collect();
+        if returns.len() < 2 { return None; }
+
+        let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+        let variance = returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>()
+            / (returns.len() - 1) as f64;
+        Some(variance.sqrt())
+    }
+
+    /// Splits every share of the stock into `ratio` shares, dividing its value to match,
+    /// and returns whether it did. Values are rounded down, so a tiny amount of worth can be
+    /// lost, but a stock worth less than `ratio` isn't split, as its shares would be worth
+    /// more afterwards. Past values never go below 1, and a stock that moved keeps moving by
+    /// at least 1. Ratios below 2 do nothing.
+    pub fn split(&mut self, ratio: i64) -> bool {
+        if ratio < 2 || self.value < ratio { return false; }
+        self.value = (self.value / ratio).max(1);
+        self.initial_value = (self.initial_value / ratio).max(1);
+        self.direction /= ratio;
+        if self.variation > 0 { self.variation = (self.variation / ratio).max(1); }
+        for v in self.history.iter_mut() { *v = (*v / ratio).max(1); }
+        if let StockBehavior::Scripted { prices, .. } = &mut self.behavior {
+            for p in prices.iter_mut() { *p = (*p / ratio).max(1); }
+        }
+        true
+    }
+
+    /// Pushes the stock's momentum by `bias_bps` basis points of its value, so its next
+    /// moves lean up (or down, if negative). Any bias pushes by at least 1, so cheap stocks
+    /// still feel it.
+    pub fn bias(&mut self, bias_bps: i64) {
+        let push = self.value * bias_bps / 10000;
+        self.direction += if push == 0 { bias_bps.signum() } else { push };
+    }
+
+    /// Changes the value by `change_bps` of itself, as part of this turn's move.
+    pub fn shock(&mut self, change_bps: i64) {
+        self.value += self.value * change_bps / 10000;
+        if let Some(last) = self.history.back_mut() { *last = self.value; }
+    }
+
+    /// Whether the stock has gone bankrupt, which happens when its value falls to 0 or
+    /// below.
+    pub fn is_bankrupt(&self) -> bool { self.value <= 0 }
+
+    /// Raises the value to 1 if it's below that, such as in an edited save. Returns whether
+    /// the value changed.
+    pub fn clamp_value(&mut self) -> bool {
+        if self.value >= 1 { return false; }
+        self.value = 1;
+        true
+    }
+
+    /// Whether trading in the stock is halted for this turn.
+    pub fn is_halted(&self) -> bool { self.halted }
+
+    /// Halts trading in the stock if its last move was bigger than `threshold_bps` of its
+    /// previous value, and lifts any halt otherwise.
+    pub fn update_halt(&mut self, threshold_bps: i64) {
+        let mut recent = self.history.iter().rev();
+        self.halted = match (recent.next(), recent.next()) {
+            (Some(cur), Some(prev)) if *prev > 0 => {
+                (cur - prev).abs() * 10000 > threshold_bps * prev
+            }
+            _ => false,
+        };
+    }
+
+    /// How many turns in a row the stock has ended below the delisting threshold.
+    pub fn low_turns(&self) -> u32 { self.low_turns }
+
+    /// Counts another turn below `threshold`, or starts the count over if the stock is at
+    /// or above it.
+    pub fn update_low_turns(&mut self, threshold: i64) {
+        if self.value < threshold {
+            self.low_turns += 1;
+        } else {
+            self.low_turns = 0;
+        }
     }
 
+    /// How many turns the stock has been on the market.
+    pub fn turns_alive(&self) -> u32 { self.turns_alive }
+
+    /// Whether the stock has been on the market for fewer than `NEW_STOCK_TURNS` turns.
+    pub fn is_new(&self) -> bool { self.turns_alive < NEW_STOCK_TURNS }
+
+    /// Counts another turn on the market.
+    pub fn age(&mut self) { self.turns_alive = self.turns_alive.saturating_add(1); }
+
     /// Resets the value and balance of the stock. Used when the stock value reaches or 
     /// is less than 0.
     pub fn reset(&mut self) { 
         self.value = self.initial_value;
         self.direction = 0;
+        self.drift_remainder = 0;
+        self.halted = false;
+        self.low_turns = 0;
+        if let StockBehavior::Scripted { index, .. } = &mut self.behavior { *index = 0; }
+        self.history.clear();
+        self.record_value();
     }
 }
 
@@ -81,116 +363,2107 @@ impl Display for Stock {
     }
 }
 
-pub fn generate_name() -> String {
-    let first_names = [
-        "Trading", "Rainbow", "Cake", "Power", "Mining", "Spacecraft", "Cargo", "Crab", 
-        "Dining", "Computer", "Game", "Security", "Block", "Micro", "Time",
-    ];
-    let last_names = [
-        "Incorporated", "Enterprise", "Solutions", "Company", "Operations", "Factory",
-        "Agency", "Firm", "Chain", "Box", "Store", "Market",
-    ];
+/// The change in net worth between two turns, both absolute and as a percentage of the
+/// earlier net worth. The percentage is 0 if the earlier net worth was 0.
+pub fn turn_pl(prev: i64, current: i64) -> (i64, f64) {
+    let change = current - prev;
+    let percent = if prev == 0 { 0.0 } else { change as f64 / prev.abs() as f64 * 100.0 };
+    (change, percent)
+}
+
+/// Formats a percentage with a sign and `decimals` decimal places, like "+12.34%". Values
+/// that aren't finite are shown as "n/a".
+pub fn format_percent(value: f64, decimals: usize) -> String {
+    if !value.is_finite() { return "n/a".to_string(); }
+    // Adding 0.0 turns -0.0 into 0.0, so zero is never shown as negative
+    format!("{:+.*}%", decimals, value + 0.0)
+}
+
+/// How money calculations round amounts that don't come out whole.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RoundingMode {
+    /// Toward zero.
+    #[default]
+    Truncate,
+    /// To the nearest whole amount, halves away from zero.
+    Round,
+    /// Up.
+    Ceil,
+}
+
+impl RoundingMode {
+    /// Divides `numerator` by a positive `denominator`, rounding the result.
+    pub fn divide(self, numerator: i64, denominator: i64) -> i64 {
+        match self {
+            RoundingMode::Truncate => numerator / denominator,
+            RoundingMode::Round if numerator >= 0 => (numerator + denominator / 2) / denominator,
+            RoundingMode::Round => (numerator - denominator / 2) / denominator,
+            RoundingMode::Ceil => -(-numerator).div_euclid(denominator),
+        }
+    }
+}
+
+/// How many turns of `per_turn` it takes to make `cost`, rounding up. Returns `None` if
+/// `per_turn` isn't positive or it would take more than `u32::MAX` turns.
+fn turns_to_save(cost: i64, per_turn: i64) -> Option<u32> {
+    if per_turn <= 0 { return None; }
+    if cost <= 0 { return Some(0); }
+    u32::try_from(cost / per_turn + i64::from(cost % per_turn != 0)).ok()
+}
+
+/// `bps` basis points of `amount`, rounded with `mode`.
+pub fn apply_bps(amount: i64, bps: i64, mode: RoundingMode) -> i64 {
+    mode.divide(amount * bps, 10000)
+}
+
+/// The combined value of every stock over the turns they all have history for, oldest
+/// first. Empty without stocks.
+pub fn market_index_history(stocks: &[Stock]) -> Vec<i64> {
+    let len = stocks.iter().map(|s| s.history.len()).min().unwrap_or(0);
+    (0..len).map(|i| {
+        stocks.iter().map(|s| s.history[s.history.len() - len + i]).sum()
+    }).collect()
+}
+
+/// The per-turn returns of a series of values. Turns starting from 0 or less are skipped.
+fn returns(values: &[i64]) -> Vec<f64> {
+    values.windows(2)
+        .filter(|w| w[0] > 0)
+        .map(|w| (w[1] - w[0]) as f64 / w[0] as f64)
+        .collect()
+}
+
+/// How a player changed between two points, from `Player::diff`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PlayerDiff {
+    pub balance: i64,
+    pub income: i64,
+    /// The change in shares of each stock whose holding changed, as stock id and change
+    /// pairs sorted by id.
+    pub shares: Vec<(i64, i64)>,
+}
+
+/// The parts of a player's net worth, from `Player::net_worth_components`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct NetWorthComponents {
+    /// The player's balance, including any locked money.
+    pub cash: i64,
+    /// What the player's shares are worth.
+    pub long_value: i64,
+}
+
+impl NetWorthComponents {
+    /// The net worth these parts add up to.
+    pub fn total(&self) -> i64 { self.cash + self.long_value }
+}
+
+/// One line of a holdings table.
+pub struct HoldingRow {
+    pub name: String,
+    pub shares: i64,
+    pub value: i64,
+    pub worth: i64,
+}
+
+/// Renders holdings as a plain text table with a header, the names left-aligned and the
+/// numbers right-aligned in columns as wide as their widest entry.
+pub fn render_holdings_table(rows: &[HoldingRow]) -> String {
+    let header = ["Stock", "Shares", "Value", "Worth"];
+    let cells: Vec<[String; 4]> = rows.iter()
+        .map(|r| [r.name.clone(), r.shares.to_string(), r.value.to_string(), r.worth.to_string()])
+        .collect();
+
+    let mut widths = header.map(|h| h.chars().count());
+    for row in &cells {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let mut result = format!("{:<w0$}  {:>w1$}  {:>w2$}  {:>w3$}\n", header[0], header[1],
+                             header[2], header[3], w0 = widths[0], w1 = widths[1],
+                             w2 = widths[2], w3 = widths[3]);
+    for [name, shares, value, worth] in &cells {
+        result.push_str(&format!("{:<w0$}  {:>w1$}  {:>w2$}  {:>w3$}\n", name, shares, value,
+                                 worth, w0 = widths[0], w1 = widths[1], w2 = widths[2],
+                                 w3 = widths[3]));
+    }
+    result
+}
+
+/// Renders the player's holdings and net worth as a Markdown table, for sharing.
+pub fn breakdown_markdown(player: &Player, stocks: &[Stock]) -> String {
+    let mut result = String::from("| Stock | Shares | Value | Worth |\n|---|---:|---:|---:|\n");
+
+    for s in sorted_by_id(stocks) {
+        let shares = player.stock_balance(s);
+        result.push_str(&format!("| {} | {} | {} | {} |\n", s.name().replace('|', "\\|"),
+                                 shares, s.value(), shares * s.value()));
+    }
+
+    result.push_str(&format!("\n**Balance:** {}  \n**Net worth:** {}\n", player.balance(),
+                             player.net_worth(stocks)));
+    result
+}
+
+/// The stocks ordered by id, which is the order they should be listed to the player in.
+pub fn sorted_by_id(stocks: &[Stock]) -> Vec<&Stock> {
+    let mut result: Vec<&Stock> = stocks.iter().collect();
+    result.sort();
+    result
+}
+
+/// The stocks in the order they should be listed to the player: watched stocks first,
+/// then by id.
+pub fn watched_first<'a>(stocks: &'a [Stock], player: &Player) -> Vec<&'a Stock> {
+    let mut result = sorted_by_id(stocks);
+    result.sort_by_key(|s| !player.is_watched(s.id()));
+    result
+}
+
+/// Keeps only the stocks the player holds shares of, in the order given.
+pub fn held_only<'a>(stocks: Vec<&'a Stock>, player: &Player) -> Vec<&'a Stock> {
+    stocks.into_iter().filter(|s| player.stock_balance(s) != 0).collect()
+}
+
+/// What stocks can be sorted by.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SortKey {
+    #[default]
+    Id,
+    Value,
+    Variation,
+    /// How many shares the player holds. Needs the player.
+    HoldingSize,
+    /// How much the value changed over the last turn, relative to the previous value.
+    PercentChange,
+}
+
+/// Sorts a list of stocks by a key. Ids sort ascending and everything else descending, so
+/// the biggest comes first. Ties keep their order. Without a player, `HoldingSize` leaves
+/// the stocks as they are.
+pub fn sort_stocks(stocks: &mut [&Stock], key: SortKey, player: Option<&Player>) {
+    match key {
+        SortKey::Id => stocks.sort_by_key(|s| s.id),
+        SortKey::Value => stocks.sort_by_key(|s| Reverse(s.value)),
+        SortKey::Variation => stocks.sort_by_key(|s| Reverse(s.variation)),
+        SortKey::HoldingSize => if let Some(player) = player {
+            stocks.sort_by_key(|s| Reverse(player.stock_balance(s)))
+        },
+        SortKey::PercentChange => stocks.sort_by_key(|s| Reverse(s.last_change_bps())),
+    }
+}
+
+/// Carves `fraction_bps` of the parent's value off into a new stock, which takes the
+/// parent's name with " Spin-off" added. One share of the parent plus one share of the
+/// spin-off are worth what a share of the parent was before, give or take rounding.
+pub fn spin_off(parent: &mut Stock, new_id: i64, fraction_bps: i64) -> Stock {
+    let carved = parent.value * fraction_bps / 10000;
+    let variation = (parent.variation * fraction_bps / 10000).max(1);
+    parent.value -= carved;
+    parent.initial_value = (parent.initial_value - parent.initial_value * fraction_bps / 10000)
+        .max(1);
+    parent.direction -= parent.direction * fraction_bps / 10000;
+    parent.variation = (parent.variation - variation).max(1);
+    for v in parent.history.iter_mut() { *v -= *v * fraction_bps / 10000; }
+
+    Stock::new(new_id, format!("{} Spin-off", parent.name), carved, variation)
+}
+
+/// The words `generate_name` starts names with.
+pub const FIRST_NAME_WORDS: &[&str] = &[
+    "Trading", "Rainbow", "Cake", "Power", "Mining", "Spacecraft", "Cargo", "Crab", 
+    "Dining", "Computer", "Game", "Security", "Block", "Micro", "Time",
+];
 
-    let first_name = first_names[rand::thread_rng().gen_range(0..first_names.len())];
-    let last_name = last_names[rand::thread_rng().gen_range(0..last_names.len())];
+/// The words `generate_name` ends names with.
+pub const LAST_NAME_WORDS: &[&str] = &[
+    "Incorporated", "Enterprise", "Solutions", "Company", "Operations", "Factory",
+    "Agency", "Firm", "Chain", "Box", "Store", "Market",
+];
+
+pub fn generate_name<R: Rng>(rng: &mut R) -> String {
+    let first_name = FIRST_NAME_WORDS[rng.gen_range(0..FIRST_NAME_WORDS.len())];
+    let last_name = LAST_NAME_WORDS[rng.gen_range(0..LAST_NAME_WORDS.len())];
 
     format!("{} {}", first_name, last_name)
 }
 
-pub fn generate_stock(id: i64, min_value: i64, max_value: i64, min_variation: i64, 
-                      max_variation: i64, name: String) -> Stock {
-    let value = rand::thread_rng().gen_range(min_value..=max_value);
-    let variation = rand::thread_rng().gen_range(min_variation..=max_variation);
+/// Like `generate_name`, but picks each word from a list of words and weights, so a word
+/// with twice the weight comes up twice as often. Returns `None` if either list is empty
+/// or has no weight.
+pub fn generate_weighted_name<R: Rng>(rng: &mut R, first_words: &[(String, u32)],
+                                      last_words: &[(String, u32)]) -> Option<String> {
+    let first = WeightedIndex::new(first_words.iter().map(|(_, w)| *w)).ok()?;
+    let last = WeightedIndex::new(last_words.iter().map(|(_, w)| *w)).ok()?;
 
-    Stock::new(id, name, value, variation)
+    Some(format!("{} {}", first_words[rng.sample(&first)].0, last_words[rng.sample(&last)].0))
 }
 
-#[derive(Serialize, Deserialize)]
+/// Words for stock names with how often each comes up, for `generate_weighted_name`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NameWords {
+    pub first: Vec<(String, u32)>,
+    pub last: Vec<(String, u32)>,
+}
+
+impl NameWords {
+    /// Generates a name from these words, or from `generate_name`'s if they can't make one.
+    pub fn generate<R: Rng>(&self, rng: &mut R) -> String {
+        generate_weighted_name(rng, &self.first, &self.last)
+            .unwrap_or_else(|| generate_name(rng))
+    }
+}
+
+/// How many times `generate_unique_name` tries for a fresh name before numbering one.
+pub const NAME_RETRIES: usize = 10;
+
+/// Like `generate_name`, but avoids the names of `stocks`. If every try is taken, the
+/// last name tried gets the lowest free number added, like "Cake Store 2".
+pub fn generate_unique_name<R: Rng>(rng: &mut R, stocks: &[Stock]) -> String {
+    generate_unique_name_with(rng, stocks, generate_name)
+}
+
+/// Like `generate_unique_name`, but with names from `generate`.
+pub fn generate_unique_name_with<R: Rng>(rng: &mut R, stocks: &[Stock],
+                                         mut generate: impl FnMut(&mut R) -> String) -> String {
+    let taken = |name: &str| stocks.iter().any(|s| s.name() == name);
+
+    let mut name = generate(rng);
+    let mut tries = 1;
+    while taken(&name) && tries < NAME_RETRIES {
+        name = generate(rng);
+        tries += 1;
+    }
+    if !taken(&name) { return name; }
+
+    (2..).map(|n| format!("{} {}", name, n)).find(|numbered| !taken(numbered)).unwrap()
+}
+
+/// The ranges new stocks get their value and variation from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StockRange {
+    pub min_value: i64,
+    pub max_value: i64,
+    pub min_variation: i64,
+    pub max_variation: i64,
+    /// The chance, in basis points, of a stock being generated as a penny stock instead:
+    /// cheap and very volatile, from `PENNY_STOCK_RANGE`.
+    #[serde(default)]
+    pub penny_stock_chance_bps: i64,
+}
+
+/// The range penny stocks are generated from.
+pub const PENNY_STOCK_RANGE: StockRange = StockRange {
+    min_value: 1, max_value: 5, min_variation: 5, max_variation: 20, penny_stock_chance_bps: 0,
+};
+
+impl StockRange {
+    /// Creates a range, swapping any bounds given in the wrong order.
+    pub fn new(min_value: i64, max_value: i64, min_variation: i64, max_variation: i64) -> Self {
+        Self {
+            min_value: min_value.min(max_value),
+            max_value: min_value.max(max_value),
+            min_variation: min_variation.min(max_variation),
+            max_variation: min_variation.max(max_variation),
+            penny_stock_chance_bps: 0,
+        }
+    }
+
+    /// Sets the chance of generating a penny stock, in basis points.
+    pub fn with_penny_stock_chance(mut self, chance_bps: i64) -> Self {
+        self.penny_stock_chance_bps = chance_bps;
+        self
+    }
+
+    /// Generates a stock within this range.
+    pub fn generate<R: Rng>(&self, rng: &mut R, id: i64, name: String) -> Stock {
+        self.generate_trending(rng, id, name, 0)
+    }
+
+    /// Generates a stock within this range that may already be trending, with a random
+    /// starting direction of up to `direction_bps` basis points of its variation either
+    /// way.
+    pub fn generate_trending<R: Rng>(&self, rng: &mut R, id: i64, name: String,
+                                     direction_bps: i64) -> Stock {
+        if self.penny_stock_chance_bps > 0
+            && rng.gen_range(0..10000) < self.penny_stock_chance_bps {
+            return PENNY_STOCK_RANGE.generate_trending(rng, id, name, direction_bps);
+        }
+        generate_stock(rng, id, self.min_value, self.max_value, self.min_variation,
+                       self.max_variation, direction_bps, name)
+    }
+}
+
+impl Default for StockRange {
+    fn default() -> Self { Self::new(10, 100, 10, 100) }
+}
+
+/// Generates a stock with a random value and variation. If `direction_bps` is positive,
+/// the stock also starts with a random direction of up to that many basis points of its
+/// variation either way; otherwise it starts with none.
+#[allow(clippy::too_many_arguments)]
+pub fn generate_stock<R: Rng>(rng: &mut R, id: i64, min_value: i64, max_value: i64,
+                              min_variation: i64, max_variation: i64, direction_bps: i64,
+                              name: String) -> Stock {
+    let value = rng.gen_range(min_value..=max_value);
+    let variation = rng.gen_range(min_variation..=max_variation);
+
+    let max_direction = variation * direction_bps.max(0) / 10000;
+    if max_direction == 0 { return Stock::new(id, name, value, variation); }
+    let direction = rng.gen_range(-max_direction..=max_direction);
+    Stock::from_parts(id, name, value, value, variation, direction)
+}
+
+/// Something that happens to the game between turns.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Event {
+    /// Scales the player's income for the next `turns` turns.
+    IncomeModifier { multiplier_bps: i64, turns: u32 },
+    /// Drops every stock by `drop_bps` of its value, followed next turn by a
+    /// `Recovery` of `recovery_bps`.
+    FlashCrash { drop_bps: i64, recovery_bps: i64 },
+    /// Raises every stock by `recovery_bps` of its value.
+    Recovery { recovery_bps: i64 },
+}
+
+/// Why money entered or left the player's balance from outside the market.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum CashFlowReason {
+    InitialBalance,
+    Income,
+    IncomeUpgrade,
+    AddStockCost,
+    ManualDeposit,
+    ManualWithdrawal,
+    ControlBonus,
+}
+
+/// Money entering (positive) or leaving (negative) the player's balance.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CashFlow {
+    pub amount: i64,
+    pub reason: CashFlowReason,
+}
+
+/// A buy or sell the player made.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TradeRecord {
+    pub turn: u32,
+    pub stock_id: i64,
+    pub price: i64,
+    /// Shares bought, or sold if negative.
+    pub amount: i64,
+}
+
+/// Why a trade couldn't go through.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TransactionError {
+    InsufficientFunds,
+    InsufficientStock,
+    UnknownStock(i64),
+    /// The player already took as many actions as they're allowed this turn.
+    ActionBudgetExceeded,
+    /// Some of the shares were bought too recently to be sold yet.
+    UnsettledShares,
+    /// The stock was already traded this turn.
+    Cooldown,
+    /// Trading in the stock is halted this turn.
+    Halted,
+    /// The amount isn't a whole number of the game's minimum trade size.
+    BelowMinTrade,
+    /// The market already has as many stocks as the game allows.
+    MarketFull,
+    /// The player is only watching the market for now.
+    Observing,
+    /// The amount of shares to trade was negative, or isn't allowed to be 0.
+    InvalidAmount,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Player {
     balance: i64,
     income: i64,
     initial_income: i64,
+    #[serde(serialize_with = "serialize_sorted_map")]
     stock_balances: HashMap<i64, i64>,
+    #[serde(default, serialize_with = "serialize_sorted_set")]
+    rebuy_after_bankruptcy: HashSet<i64>,
+    #[serde(default, serialize_with = "serialize_sorted_set")]
+    watchlist: HashSet<i64>,
+    /// What the player paid in total for the shares they still hold, by stock id.
+    #[serde(default, serialize_with = "serialize_sorted_map")]
+    cost_basis: HashMap<i64, i64>,
+    #[serde(default)]
+    realized_pl: i64,
+    /// Stock ids and how much money to spend on them every turn.
+    #[serde(default)]
+    recurring_buys: Vec<(i64, i64)>,
+    #[serde(default)]
+    cash_flows: Vec<CashFlow>,
+    /// Part of the balance set aside, which can't be spent until it's unlocked.
+    #[serde(default)]
+    locked_balance: i64,
+    /// Stock ids and the purchases of them that might not be settled yet, as the turn
+    /// they were bought on and how many shares.
+    #[serde(default, serialize_with = "serialize_sorted_map")]
+    unsettled_lots: HashMap<i64, Vec<(u32, i64)>>,
+    /// Every buy and sell the player made, oldest first.
+    #[serde(default)]
+    trade_log: Vec<TradeRecord>,
+    /// The money the player's trades have brought in, less what they've spent on trades.
+    #[serde(default)]
+    trade_cash: i64,
+}
+
+/// Writes a map with its keys in order, so saving the same state always gives the same
+/// file.
+fn serialize_sorted_map<K, V, S>(map: &HashMap<K, V>, serializer: S) -> Result<S::Ok, S::Error>
+    where K: Ord + Serialize, V: Serialize, S: Serializer {
+    map.iter().collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
+/// Like `serialize_sorted_map`, but for sets.
+fn serialize_sorted_set<T, S>(set: &HashSet<T>, serializer: S) -> Result<S::Ok, S::Error>
+    where T: Ord + Serialize, S: Serializer {
+    set.iter().collect::<BTreeSet<_>>().serialize(serializer)
 }
 
 impl Player {
     /// Generates a new `Player`.
     pub fn new(balance: i64, income: i64) -> Self {
-        Self { balance, income, initial_income: income, stock_balances: HashMap::new() }
+        Self {
+            balance, income, initial_income: income, stock_balances: HashMap::new(),
+            rebuy_after_bankruptcy: HashSet::new(), watchlist: HashSet::new(),
+            cost_basis: HashMap::new(), realized_pl: 0, recurring_buys: Vec::new(),
+            cash_flows: vec![
+                CashFlow { amount: balance, reason: CashFlowReason::InitialBalance },
+            ],
+            locked_balance: 0,
+            unsettled_lots: HashMap::new(),
+            trade_log: Vec::new(),
+            trade_cash: 0,
+        }
+    }
+
+    /// Puts the player back to how `Player::new` would make them, clearing their
+    /// holdings, locked money and logs.
+    pub fn reset(&mut self, balance: i64, income: i64) {
+        *self = Self::new(balance, income);
     }
 
-    /// Getter for the balance
+    /// Getter for the balance. This includes locked money, which still belongs to the
+    /// player; use `available_balance` for what can be spent.
     pub fn balance(&self) -> i64 { self.balance }
+
+    /// The part of the balance that isn't locked and can be spent.
+    pub fn available_balance(&self) -> i64 { self.balance - self.locked_balance }
     
     /// Gets the amount of stock a player owns
     pub fn stock_balance(&self, stock: &Stock) -> i64 {
         if let Some(b) = self.stock_balances.get(&stock.id()) {
-            return *b;
+            *b
         } else {
-            return 0;
+            0
+        }
+    }
+
+    /// How the player changed going from `self` to `other`, as `other` minus `self`. A
+    /// stock held by only one of the two counts as 0 shares in the other.
+    pub fn diff(&self, other: &Player) -> PlayerDiff {
+        let ids: BTreeSet<i64> = self.stock_balances.keys()
+            .chain(other.stock_balances.keys())
+            .copied()
+            .collect();
+        let shares = ids.into_iter()
+            .map(|id| {
+                let before = self.stock_balances.get(&id).copied().unwrap_or(0);
+                let after = other.stock_balances.get(&id).copied().unwrap_or(0);
+                (id, after - before)
+            })
+            .filter(|(_, change)| *change != 0)
+            .collect();
+
+        PlayerDiff {
+            balance: other.balance - self.balance,
+            income: other.income - self.income,
+            shares,
         }
     }
 
+    /// Every stock the player holds any shares of, as stock id and share count pairs
+    /// sorted by id. Lets a frontend go through all the holdings in one pass instead of
+    /// looking each stock up.
+    pub fn shares_snapshot(&self) -> Vec<(i64, i64)> {
+        let mut result: Vec<(i64, i64)> = self.stock_balances.iter()
+            .filter(|(_, held)| **held != 0)
+            .map(|(id, held)| (*id, *held))
+            .collect();
+        result.sort_unstable();
+        result
+    }
+
     /// Getter for the income
     pub fn income(&self) -> i64 { self.income }
 
     /// Purchases a stock. Returns `Err(())` if the player had too low of a balance.
     pub fn buy_stock(&mut self, stock: &Stock, amount: i64) -> Result<(), ()> {
-        let cost = stock.value() * amount;
-        if i64::from(self.balance) < cost { return Err(()) }
+        self.buy_stock_at(stock.id(), stock.value(), amount).map_err(|_| ())
+    }
+
+    /// Purchases a stock at the specified price rather than its current value. Will return
+    /// `TransactionError::InvalidAmount` for a negative amount; buying 0 shares does
+    /// nothing.
+    pub fn buy_stock_at(&mut self, stock_id: i64, price: i64, amount: i64)
+        -> Result<(), TransactionError> {
+        if amount < 0 { return Err(TransactionError::InvalidAmount) }
+        // Work out every change before making any, so a failed check can't leave the
+        // holdings and balance out of step
+        let cost = price * amount;
+        if self.available_balance() < cost { return Err(TransactionError::InsufficientFunds) }
+        let holding = self.stock_balances.get(&stock_id).copied().unwrap_or(0) + amount;
+        let basis = self.cost_basis.get(&stock_id).copied().unwrap_or(0) + cost;
+
         self.balance -= cost;
-        let stock_balance = self.stock_balance(stock);
-        self.stock_balances.insert(stock.id(), stock_balance + amount);
+        self.trade_cash -= cost;
+        self.stock_balances.insert(stock_id, holding);
+        self.cost_basis.insert(stock_id, basis);
         Ok(())
     }
 
     /// Sells a stock. Returns `Err(())` if the player doesn't have enough stock to sell.
     pub fn sell_stock(&mut self, stock: &Stock, amount: i64) -> Result<(), ()> {
-        let bal = self.stock_balance(stock);
-        if bal < amount { return Err(()) }
-        self.stock_balances.insert(stock.id(), bal - amount);
-        self.balance += stock.value() * amount;
-        Ok(())
+        self.sell_stock_at(stock.id(), stock.value(), amount).map_err(|_| ())
     }
 
-    /// Resets a stock balance back to 0.
-    pub fn reset_stock(&mut self, stock: &Stock) {
-        self.stock_balances.insert(stock.id(), 0);
+    /// Sells a stock at the specified price rather than its current value. Will return
+    /// `TransactionError::InvalidAmount` for a negative amount; selling 0 shares does
+    /// nothing.
+    pub fn sell_stock_at(&mut self, stock_id: i64, price: i64, amount: i64)
+        -> Result<(), TransactionError> {
+        if amount < 0 { return Err(TransactionError::InvalidAmount) }
+        // Like buying, every change is worked out before any is made
+        let bal = self.stock_balances.get(&stock_id).copied().unwrap_or(0);
+        if bal < amount { return Err(TransactionError::InsufficientStock) }
+        let proceeds = price * amount;
+        let basis = self.cost_basis.get(&stock_id).copied().unwrap_or(0);
+        let sold_basis = if bal == 0 { 0 } else { basis * amount / bal };
+
+        self.stock_balances.insert(stock_id, bal - amount);
+        self.cost_basis.insert(stock_id, basis - sold_basis);
+        self.realized_pl += proceeds - sold_basis;
+        self.balance += proceeds;
+        self.trade_cash += proceeds;
+        Ok(())
     }
 
-    /// Increment the balance by the player's income.
-    pub fn collect_income(&mut self) { self.balance += self.income }
+    /// Sells every share the player holds of a stock at `price`, clearing any unsettled
+    /// purchases of it too. Returns the cash raised.
+    pub fn liquidate_stock(&mut self, stock_id: i64, price: i64) -> i64 {
+        let held = self.stock_balances.get(&stock_id).copied().unwrap_or(0);
+        self.sell_stock_at(stock_id, price, held)
+            .expect("the player can always sell every share they hold");
+        self.unsettled_lots.remove(&stock_id);
+        held * price
+    }
 
-    /// Increases the income of the player by the initial income amount for the specified 
-    /// cost. Returns an Err(()) if the player didn't have enough money.
-    pub fn increase_income(&mut self, cost: i64) -> Result<(), ()> { 
-        if cost > self.balance { return Err(()); }
+    /// Sells as many whole shares as `money` buys at the stock's value, up to the shares
+    /// the player holds. Returns how many shares were sold.
+    pub fn sell_stock_value(&mut self, stock: &Stock, money: i64) -> Result<i64, TransactionError> {
+        let amount = self.shares_worth(stock.id(), stock.value(), money);
+        self.sell_stock_at(stock.id(), stock.value(), amount)?;
+        Ok(amount)
+    }
 
-        self.income += self.initial_income;
-        self.balance -= cost;
-        Ok(()) 
+    /// How many whole shares of a stock `money` is worth at `price`, up to the shares the
+    /// player holds. 0 if the price isn't positive.
+    pub fn shares_worth(&self, stock_id: i64, price: i64, money: i64) -> i64 {
+        if price <= 0 { return 0; }
+        let held = self.stock_balances.get(&stock_id).copied().unwrap_or(0);
+        (money / price).min(held).max(0)
     }
 
-    /// Returns the balance of the player plus the worth of the player's owned
-    /// stock.
-    pub fn net_worth(&self, stocks: &[Stock]) -> i64 {
-        let mut result = self.balance;
-        for s in stocks { result += s.value() * self.stock_balance(s) }
-        result
+    /// Sells as much of `amount` as the player holds. Returns the amount actually sold.
+    pub fn sell_up_to(&mut self, stock: &Stock, amount: i64) -> i64 {
+        let amount = amount.min(self.stock_balance(stock)).max(0);
+        // Can't fail since the amount is capped to the player's holdings
+        self.sell_stock(stock, amount).unwrap();
+        amount
     }
 
-    /// Remove an arbitrary amount of money from the player's balance. Should only be 
-    /// used when no other method applies (or when the Player struct has no other state
-    /// to manipulate).
-    pub fn withdraw(&mut self, amount: i64) -> Result<(), ()> {
-        if self.balance < amount { return Err(()); }
-        self.balance -= amount;
+    /// Trades so the available balance sits at about `target_cash`. Below the target,
+    /// holdings are sold in proportion to their worth, rounding up to whole shares.
+    /// Above it, the excess is spent on holdings in the same proportions, rounding down.
+    /// Holdings are handled largest first. Returns `Err(InsufficientStock)`, without
+    /// trading, if selling everything still wouldn't reach the target.
+    pub fn maintain_cash(&mut self, target_cash: i64, stocks: &[Stock])
+        -> Result<(), TransactionError> {
+        let mut holdings: Vec<(&Stock, i64)> = stocks.iter()
+            .filter(|s| self.stock_balance(s) > 0 && !s.is_bankrupt())
+            .map(|s| (s, s.value() * self.stock_balance(s)))
+            .collect();
+        holdings.sort_by_key(|&(s, worth)| (Reverse(worth), s.id()));
+        let total: i64 = holdings.iter().map(|&(_, worth)| worth).sum();
+        let cash = self.available_balance();
+
+        if cash < target_cash {
+            let shortfall = target_cash - cash;
+            if shortfall > total { return Err(TransactionError::InsufficientStock) }
+            for (stock, worth) in holdings {
+                let share = RoundingMode::Ceil.divide(shortfall * worth, total);
+                let amount = RoundingMode::Ceil.divide(share, stock.value());
+                self.sell_up_to(stock, amount);
+                if self.available_balance() >= target_cash { break; }
+            }
+        } else if total > 0 {
+            let excess = cash - target_cash;
+            for (stock, worth) in holdings {
+                let amount = excess * worth / total / stock.value();
+                self.buy_stock_at(stock.id(), stock.value(), amount)?;
+            }
+        }
         Ok(())
     }
 
-    /// Add an arbitrary amount of money to the player's balance. Should only be used
-    /// when no other method applies (or when the Player struct has no other state to 
-    /// manipulate).
-    pub fn deposit(&mut self, amount: i64) { self.balance += amount; }
-}
+    /// What the player paid in total for the shares of a stock they still hold.
+    pub fn cost_basis(&self, stock: &Stock) -> i64 {
+        self.cost_basis.get(&stock.id()).copied().unwrap_or(0)
+    }
+
+    /// What the player paid in total for every share they still hold.
+    pub fn cost_basis_total(&self) -> i64 {
+        self.cost_basis.values().sum()
+    }
+
+    /// What the player paid for their holdings as a fraction of what they're worth now: the
+    /// value-weighted average of each holding's basis over its value. Below 1 means the
+    /// portfolio as a whole is in profit. Returns `None` without holdings, or if they're
+    /// worth nothing.
+    pub fn portfolio_avg_cost(&self, stocks: &[Stock]) -> Option<f64> {
+        let held = stocks.iter().filter(|s| self.stock_balance(s) > 0);
+        let (basis, value) = held.fold((0, 0), |(basis, value), s| {
+            (basis + self.cost_basis(s), value + s.value() * self.stock_balance(s))
+        });
+        if value <= 0 { return None; }
+        Some(basis as f64 / value as f64)
+    }
+
+    /// The player's income as a share of their net worth. Returns 0 if the net worth isn't
+    /// positive.
+    pub fn income_to_net_worth(&self, stocks: &[Stock]) -> f64 {
+        let net_worth = self.net_worth(stocks);
+        if net_worth <= 0 { return 0.0; }
+        self.income as f64 / net_worth as f64
+    }
+
+    /// The balance plus what was paid for the shares still held. The gap between this and
+    /// `net_worth` is the unrealized profit.
+    pub fn book_value(&self) -> i64 {
+        self.balance + self.cost_basis_total()
+    }
+
+    /// How many shares of a stock the player would need to buy now, at its current value,
+    /// for their balance plus their shares of it to be worth `target_net_worth` once the
+    /// price reaches `future_price`. Other holdings aren't counted. Returns `None` if the
+    /// available balance can't buy enough shares.
+    pub fn shares_for_target(&self, stock: &Stock, future_price: i64, target_net_worth: i64)
+        -> Option<i64> {
+        let worth = self.balance + self.stock_balance(stock) * future_price;
+        if worth >= target_net_worth { return Some(0); }
+
+        let gain_per_share = future_price - stock.value();
+        if gain_per_share <= 0 { return None; }
+        let shares = (target_net_worth - worth + gain_per_share - 1) / gain_per_share;
+        if shares * stock.value() > self.available_balance() { return None; }
+        Some(shares)
+    }
+
+    /// How much the player's holdings move relative to the market, taking their current
+    /// holdings as held throughout `index_history` (such as from `market_index_history`).
+    /// Returns `None` without holdings, with fewer than two returns or if the market never
+    /// moved.
+    pub fn portfolio_beta(&self, stocks: &[Stock], index_history: &[i64]) -> Option<f64> {
+        let held: Vec<&Stock> = stocks.iter().filter(|s| self.stock_balance(s) > 0).collect();
+        let len = held.iter().map(|s| s.history.len()).chain(Some(index_history.len()))
+            .min()?;
+        if held.is_empty() { return None; }
+
+        let portfolio: Vec<i64> = (0..len).map(|i| {
+            held.iter()
+                .map(|s| s.history[s.history.len() - len + i] * self.stock_balance(s))
+                .sum()
+        }).collect();
+        let portfolio = returns(&portfolio);
+        let market = returns(&index_history[index_history.len() - len..]);
+        if market.len() < 2 || market.len() != portfolio.len() { return None; }
+
+        let n = market.len() as f64;
+        let market_mean = market.iter().sum::<f64>() / n;
+        let portfolio_mean = portfolio.iter().sum::<f64>() / n;
+        let covariance = market.iter().zip(&portfolio)
+            .map(|(m, p)| (m - market_mean) * (p - portfolio_mean))
+            .sum::<f64>();
+        let variance = market.iter().map(|m| (m - market_mean).powi(2)).sum::<f64>();
+        if variance == 0.0 { return None; }
+        Some(covariance / variance)
+    }
 
+    /// The stocks the player can afford at least one share of with their available
+    /// balance.
+    pub fn affordable_stocks<'a>(&self, stocks: &'a [Stock]) -> Vec<&'a Stock> {
+        stocks.iter().filter(|s| s.value() <= self.available_balance()).collect()
+    }
+
+    /// The id and unrealized profit of the held stock that has made the most money, or
+    /// `None` if the player holds no stock.
+    pub fn best_holding(&self, stocks: &[Stock]) -> Option<(i64, i64)> {
+        self.unrealized_pls(stocks).max_by_key(|&(_, pl)| pl)
+    }
+
+    /// Like `best_holding`, but for the stock that has lost the most money.
+    pub fn worst_holding(&self, stocks: &[Stock]) -> Option<(i64, i64)> {
+        self.unrealized_pls(stocks).min_by_key(|&(_, pl)| pl)
+    }
+
+    /// The ids and unrealized losses (as positive amounts) of the held stocks worth less
+    /// than the player paid for them.
+    pub fn loss_positions(&self, stocks: &[Stock]) -> Vec<(i64, i64)> {
+        self.unrealized_pls(stocks).filter(|&(_, pl)| pl < 0).map(|(id, pl)| (id, -pl)).collect()
+    }
+
+    fn unrealized_pls<'a>(&'a self, stocks: &'a [Stock])
+        -> impl Iterator<Item = (i64, i64)> + 'a {
+        stocks.iter()
+            .filter(move |s| self.stock_balance(s) > 0)
+            .map(move |s| (s.id(), s.value() * self.stock_balance(s) - self.cost_basis(s)))
+    }
+
+    /// The profit (or loss, if negative) the player has locked in by selling stock or
+    /// losing it to bankruptcy.
+    pub fn realized_pl(&self) -> i64 { self.realized_pl }
+
+    /// Multiplies the player's shares of a stock after it splits. The cost basis is
+    /// unchanged since the player paid the same for the shares.
+    pub fn apply_split(&mut self, stock_id: i64, ratio: i64) {
+        if let Some(b) = self.stock_balances.get_mut(&stock_id) { *b *= ratio; }
+        if let Some(lots) = self.unsettled_lots.get_mut(&stock_id) {
+            for (_, amount) in lots.iter_mut() { *amount *= ratio; }
+        }
+    }
+
+    /// Adds a trade to the player's trade log.
+    pub fn record_trade(&mut self, trade: TradeRecord) {
+        self.trade_log.push(trade);
+    }
+
+    /// The player's trades of a stock, oldest first.
+    pub fn trades_for(&self, stock_id: i64) -> Vec<TradeRecord> {
+        self.trade_log.iter().filter(|t| t.stock_id == stock_id).copied().collect()
+    }
+
+    /// Remembers that shares were bought on `turn`, so they can't be sold until they
+    /// settle.
+    pub fn record_purchase(&mut self, stock_id: i64, turn: u32, amount: i64) {
+        self.unsettled_lots.entry(stock_id).or_default().push((turn, amount));
+    }
+
+    /// How many shares of a stock the player can sell on `turn`, leaving out the ones
+    /// bought within the last `settlement_turns` turns.
+    pub fn settled_shares(&self, stock_id: i64, turn: u32, settlement_turns: u32) -> i64 {
+        let unsettled: i64 = self.unsettled_lots.get(&stock_id).into_iter().flatten()
+            .filter(|(bought, _)| turn < bought + settlement_turns)
+            .map(|(_, amount)| amount)
+            .sum();
+        let held = self.stock_balances.get(&stock_id).copied().unwrap_or(0);
+        (held - unsettled).max(0)
+    }
+
+    /// Forgets the purchases that have settled by `turn`.
+    pub fn settle(&mut self, turn: u32, settlement_turns: u32) {
+        for lots in self.unsettled_lots.values_mut() {
+            lots.retain(|(bought, _)| turn < bought + settlement_turns);
+        }
+        self.unsettled_lots.retain(|_, lots| !lots.is_empty());
+    }
+
+    /// Moves everything the player has in one stock id over to another, adding to
+    /// anything already held under the new id. For when a stock's id changes.
+    pub fn remap_holding(&mut self, old_id: i64, new_id: i64) {
+        if old_id == new_id { return; }
+
+        if let Some(held) = self.stock_balances.remove(&old_id) {
+            *self.stock_balances.entry(new_id).or_insert(0) += held;
+        }
+        if let Some(basis) = self.cost_basis.remove(&old_id) {
+            *self.cost_basis.entry(new_id).or_insert(0) += basis;
+        }
+        if let Some(lots) = self.unsettled_lots.remove(&old_id) {
+            self.unsettled_lots.entry(new_id).or_default().extend(lots);
+        }
+        if self.watchlist.remove(&old_id) { self.watchlist.insert(new_id); }
+        if self.rebuy_after_bankruptcy.remove(&old_id) {
+            self.rebuy_after_bankruptcy.insert(new_id);
+        }
+        for (id, _) in self.recurring_buys.iter_mut() {
+            if *id == old_id { *id = new_id; }
+        }
+    }
+
+    /// Forgets the player's holdings of stocks that aren't in `stocks`. Returns the ids of
+    /// the holdings dropped.
+    pub fn drop_unknown_holdings(&mut self, stocks: &[Stock]) -> Vec<i64> {
+        let unknown: Vec<i64> = self.stock_balances.keys()
+            .filter(|id| !stocks.iter().any(|s| s.id() == **id))
+            .copied()
+            .collect();
+        for id in &unknown {
+            self.stock_balances.remove(id);
+            self.cost_basis.remove(id);
+            self.unsettled_lots.remove(id);
+        }
+        unknown
+    }
+
+    /// Raises a negative income to 0. Returns whether the income changed.
+    pub fn clamp_income(&mut self) -> bool {
+        if self.income >= 0 { return false; }
+        self.income = 0;
+        true
+    }
+
+    /// Gives the player one share of a spin-off for every share they hold of its parent,
+    /// moving `fraction_bps` of the parent's cost basis over to the spin-off.
+    pub fn apply_spin_off(&mut self, parent_id: i64, new_id: i64, fraction_bps: i64) {
+        let held = self.stock_balances.get(&parent_id).copied().unwrap_or(0);
+        if held <= 0 { return; }
+        self.stock_balances.insert(new_id, held);
+
+        let basis = self.cost_basis.get(&parent_id).copied().unwrap_or(0);
+        let moved = basis * fraction_bps / 10000;
+        self.cost_basis.insert(parent_id, basis - moved);
+        self.cost_basis.insert(new_id, moved);
+    }
+
+    /// Applies a batch of trades in order, given as stock ids and amounts (positive to
+    /// buy, negative to sell). Either every trade goes through or none of them do.
+    pub fn apply_trades(&mut self, trades: &[(i64, i64)], stocks: &[Stock])
+        -> Result<(), TransactionError> {
+        let mut player = self.clone();
+
+        for (id, amount) in trades {
+            let stock = match stocks.iter().find(|s| s.id() == *id) {
+                Some(s) => s,
+                None => return Err(TransactionError::UnknownStock(*id)),
+            };
+
+            if *amount >= 0 {
+                player.buy_stock(stock, *amount)
+                    .map_err(|_| TransactionError::InsufficientFunds)?;
+            } else {
+                player.sell_stock(stock, -amount)
+                    .map_err(|_| TransactionError::InsufficientStock)?;
+            }
+        }
+
+        *self = player;
+        Ok(())
+    }
+
+    /// Resets a stock balance back to 0, losing whatever was paid for it.
+    pub fn reset_stock(&mut self, stock: &Stock) { self.reset_stock_by_id(stock.id()); }
+
+    /// Like `reset_stock`, for a stock given by its id.
+    pub fn reset_stock_by_id(&mut self, stock_id: i64) {
+        self.stock_balances.insert(stock_id, 0);
+        self.unsettled_lots.remove(&stock_id);
+        self.realized_pl -= self.cost_basis.get(&stock_id).copied().unwrap_or(0);
+        self.cost_basis.insert(stock_id, 0);
+    }
+
+    /// Whether the player wants to automatically rebuy a stock after it goes bankrupt.
+    pub fn rebuys_after_bankruptcy(&self, stock: &Stock) -> bool {
+        self.rebuy_after_bankruptcy.contains(&stock.id())
+    }
+
+    /// Toggles automatically rebuying a stock after it goes bankrupt. Returns whether the
+    /// flag is now set.
+    pub fn toggle_rebuy_after_bankruptcy(&mut self, stock: &Stock) -> bool {
+        if self.rebuy_after_bankruptcy.remove(&stock.id()) {
+            false
+        } else {
+            self.rebuy_after_bankruptcy.insert(stock.id());
+            true
+        }
+    }
+
+    /// How many turns of income it takes to save up `cost`, ignoring the current balance.
+    /// Returns `None` if the player has no income or it would take more than `u32::MAX`
+    /// turns.
+    pub fn turns_to_afford(&self, cost: i64) -> Option<u32> {
+        turns_to_save(cost, self.income)
+    }
+
+    /// How much income the player collects over a number of turns at their current income.
+    /// Saturates instead of overflowing.
+    pub fn income_over(&self, turns: u32) -> i64 {
+        self.income.saturating_mul(i64::from(turns))
+    }
+
+    /// Whether the stock is on the player's watchlist.
+    pub fn is_watched(&self, stock_id: i64) -> bool { self.watchlist.contains(&stock_id) }
+
+    /// Adds or removes a stock from the player's watchlist. Returns whether the stock is
+    /// now watched.
+    pub fn toggle_watch(&mut self, stock_id: i64) -> bool {
+        if self.watchlist.remove(&stock_id) {
+            false
+        } else {
+            self.watchlist.insert(stock_id);
+            true
+        }
+    }
+
+    /// The stock ids and amounts of money the player spends on them every turn.
+    pub fn recurring_buys(&self) -> &[(i64, i64)] { &self.recurring_buys }
+
+    /// Spends `money` on a stock every turn, replacing any recurring buy of that stock.
+    pub fn add_recurring_buy(&mut self, stock_id: i64, money: i64) {
+        self.remove_recurring_buy(stock_id);
+        self.recurring_buys.push((stock_id, money));
+    }
+
+    /// Stops buying a stock every turn.
+    pub fn remove_recurring_buy(&mut self, stock_id: i64) {
+        self.recurring_buys.retain(|(id, _)| *id != stock_id);
+    }
+
+    /// Drops the player's watchlist, rebuy and recurring buy settings for a stock that's
+    /// left the market.
+    pub fn forget_stock(&mut self, stock_id: i64) {
+        self.watchlist.remove(&stock_id);
+        self.rebuy_after_bankruptcy.remove(&stock_id);
+        self.remove_recurring_buy(stock_id);
+    }
+
+    /// Increment the balance by the player's income.
+    pub fn collect_income(&mut self) { self.deposit_for(self.income, CashFlowReason::Income); }
+
+    /// Whether the player can afford an income increase at the specified cost.
+    pub fn can_increase_income(&self, cost: i64) -> bool { cost <= self.available_balance() }
+
+    /// How much more spendable money the player needs to afford `cost`. 0 if they can
+    /// already afford it. Locked money doesn't count.
+    pub fn shortfall(&self, cost: i64) -> i64 { (cost - self.available_balance()).max(0) }
+
+    /// Increases the income of the player by the initial income amount for the specified 
+    /// cost. Returns an Err(()) if the player didn't have enough money.
+    pub fn increase_income(&mut self, cost: i64) -> Result<(), ()> { 
+        if !self.can_increase_income(cost) { return Err(()); }
+
+        self.withdraw_for(cost, CashFlowReason::IncomeUpgrade).map_err(|_| ())?;
+        self.income += self.initial_income;
+        Ok(()) 
+    }
+
+    /// How many turns an income increase costing `cost` takes to pay for itself. Returns
+    /// `None` if increases don't add any income.
+    pub fn income_upgrade_payback(&self, cost: i64) -> Option<u32> {
+        turns_to_save(cost, self.initial_income)
+    }
+
+    /// Makes each income increase add as much as the current income, instead of the
+    /// income the player started with. Useful when a save or scenario set a high income,
+    /// where upgrades adding the small starting income would hardly matter.
+    pub fn rebase_initial_income(&mut self) { self.initial_income = self.income; }
+
+    /// Returns the balance of the player plus the worth of the player's owned
+    /// stock. Locked money is counted, since it's still the player's.
+    pub fn net_worth(&self, stocks: &[Stock]) -> i64 {
+        self.net_worth_components(stocks).total()
+    }
+
+    /// The parts that make up the player's net worth.
+    pub fn net_worth_components(&self, stocks: &[Stock]) -> NetWorthComponents {
+        NetWorthComponents {
+            cash: self.balance,
+            long_value: stocks.iter().map(|s| s.value() * self.stock_balance(s)).sum(),
+        }
+    }
+
+    /// What the net worth would be if every stock moved by `shock_bps` basis points of its
+    /// value, such as -2000 for a 20% crash.
+    pub fn scenario_net_worth(&self, stocks: &[Stock], shock_bps: i64) -> i64 {
+        let mut result = self.balance;
+        for s in stocks {
+            result += (s.value() + s.value() * shock_bps / 10000) * self.stock_balance(s);
+        }
+        result
+    }
+
+    /// What the player would be left with after selling everything: the balance plus the
+    /// holdings sold at the bid price (half of `spread_bps` under their value), minus a
+    /// `fee_bps` fee on the proceeds. Both are rounded with `rounding`, like actual sales.
+    pub fn net_liquidation_value(&self, stocks: &[Stock], fee_bps: i64, spread_bps: i64,
+                                 rounding: RoundingMode) -> i64 {
+        let mut result = self.balance;
+        for s in stocks {
+            let bid = s.value() - rounding.divide(s.value() * spread_bps, 20000);
+            let proceeds = bid * self.stock_balance(s);
+            result += proceeds - apply_bps(proceeds, fee_bps, rounding);
+        }
+        result
+    }
+
+    /// Like `net_worth`, but values holdings with a map of stock ids to prices instead of
+    /// the stocks themselves. Holdings of stocks missing from the map are ignored.
+    pub fn net_worth_with_prices(&self, prices: &HashMap<i64, i64>) -> i64 {
+        self.balance + self.holdings_value_at(prices)
+    }
+
+    /// What the player's holdings would be worth at the prices in a map of stock ids to
+    /// prices. Holdings of stocks missing from the map are ignored.
+    pub fn holdings_value_at(&self, prices: &HashMap<i64, i64>) -> i64 {
+        let mut result = 0;
+        for (id, amount) in &self.stock_balances {
+            if let Some(price) = prices.get(id) { result += price * amount }
+        }
+        result
+    }
+
+    /// Remove an arbitrary amount of money from the player's balance. Should only be 
+    /// used when no other method applies (or when the Player struct has no other state
+    /// to manipulate). Returns the new balance, or `Err(InsufficientFunds)` if the
+    /// available balance is too low.
+    pub fn withdraw(&mut self, amount: i64) -> Result<i64, TransactionError> {
+        self.withdraw_for(amount, CashFlowReason::ManualWithdrawal)
+    }
+
+    /// Like `withdraw`, but records why the money was taken in the cash flow log.
+    pub fn withdraw_for(&mut self, amount: i64, reason: CashFlowReason)
+        -> Result<i64, TransactionError> {
+        if self.available_balance() < amount { return Err(TransactionError::InsufficientFunds) }
+        self.balance -= amount;
+        self.cash_flows.push(CashFlow { amount: -amount, reason });
+        Ok(self.balance)
+    }
+
+    /// Add an arbitrary amount of money to the player's balance. Should only be used
+    /// when no other method applies (or when the Player struct has no other state to 
+    /// manipulate). Returns the new balance.
+    pub fn deposit(&mut self, amount: i64) -> i64 {
+        self.deposit_for(amount, CashFlowReason::ManualDeposit)
+    }
+
+    /// Like `deposit`, but records why the money was given in the cash flow log.
+    pub fn deposit_for(&mut self, amount: i64, reason: CashFlowReason) -> i64 {
+        self.balance += amount;
+        self.cash_flows.push(CashFlow { amount, reason });
+        self.balance
+    }
+
+    /// Getter for the locked balance
+    pub fn locked_balance(&self) -> i64 { self.locked_balance }
+
+    /// Sets aside part of the balance so it can't be spent. Returns `Err(())` if the amount
+    /// is negative or the player doesn't have that much unlocked money.
+    pub fn lock(&mut self, amount: i64) -> Result<(), ()> {
+        if amount < 0 || self.available_balance() < amount { return Err(()); }
+        self.locked_balance += amount;
+        Ok(())
+    }
+
+    /// Makes locked money spendable again. Unlocking more than is locked unlocks
+    /// everything, and unlocking a negative amount does nothing.
+    pub fn unlock(&mut self, amount: i64) {
+        self.locked_balance -= amount.max(0).min(self.locked_balance);
+    }
+
+    /// Every time money entered or left the player's balance from outside the market,
+    /// oldest first.
+    pub fn cash_flows(&self) -> &[CashFlow] { &self.cash_flows }
+
+    /// Getter for the money the player's trades have brought in, less what they've spent
+    /// on trades.
+    pub fn trade_cash(&self) -> i64 { self.trade_cash }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn turns_to_afford_rounds_up() {
+        let player = Player::new(0, 100);
+        assert_eq!(player.turns_to_afford(300), Some(3));
+        assert_eq!(player.turns_to_afford(301), Some(4));
+        assert_eq!(player.turns_to_afford(0), Some(0));
+        assert_eq!(player.income_upgrade_payback(250), Some(3));
+    }
+
+    #[test]
+    fn turns_to_afford_guards_zero_income_and_huge_costs() {
+        assert_eq!(Player::new(0, 0).turns_to_afford(100), None);
+        assert_eq!(Player::new(0, -5).turns_to_afford(100), None);
+        assert_eq!(Player::new(0, 1).turns_to_afford(i64::MAX), None);
+        assert_eq!(Player::new(0, 1).turns_to_afford(i64::from(u32::MAX)), Some(u32::MAX));
+    }
+
+    fn rng() -> rand_chacha::ChaCha8Rng {
+        rand::SeedableRng::seed_from_u64(0)
+    }
+
+    /// Moves a stock on `turns` times.
+    fn step(stock: &mut Stock, turns: usize) {
+        let mut rng = rng();
+        for _ in 0..turns { stock.vary(&mut rng); }
+    }
+
+    #[test]
+    fn realized_volatility_of_known_returns() {
+        let mut stock = Stock::new(0, "A".to_string(), 100, 0)
+            .with_price_series(vec![100, 110, 99, 99]);
+        step(&mut stock, 1);
+        assert_eq!(stock.realized_volatility(), None);
+
+        step(&mut stock, 2);
+        // Returns of +10%, -10% and 0% have a sample standard deviation of 10%
+        assert!((stock.realized_volatility().unwrap() - 0.1).abs() < 1e-9);
+    }
+
+    #[test]
+    fn history_keeps_the_most_recent_values() {
+        let prices: Vec<i64> = (1..=30).collect();
+        let mut stock = Stock::new(0, "A".to_string(), 1, 0).with_price_series(prices);
+        step(&mut stock, 29);
+        assert_eq!(stock.history().len(), HISTORY_LEN);
+        assert_eq!(stock.history().front(), Some(&11));
+        assert_eq!(stock.history().back(), Some(&30));
+    }
+
+    #[test]
+    fn income_increase_needs_spendable_money() {
+        let mut player = Player::new(1000, 100);
+        assert!(player.can_increase_income(1000));
+        assert_eq!(player.shortfall(1000), 0);
+
+        player.lock(300).unwrap();
+        assert!(!player.can_increase_income(1000));
+        assert_eq!(player.shortfall(1000), 300);
+        assert!(player.increase_income(1000).is_err());
+
+        assert!(player.increase_income(700).is_ok());
+        assert_eq!((player.balance(), player.income()), (300, 200));
+    }
+
+    fn stock(id: i64, value: i64) -> Stock {
+        Stock::new(id, format!("Stock {}", id), value, 1)
+    }
+
+    fn ids<'a>(stocks: impl IntoIterator<Item = &'a Stock>) -> Vec<i64> {
+        stocks.into_iter().map(|s| s.id()).collect()
+    }
+
+    #[test]
+    fn stocks_are_listed_in_id_order() {
+        let stocks = vec![stock(3, 10), stock(0, 50), stock(2, 10), stock(1, 50)];
+        assert_eq!(ids(sorted_by_id(&stocks)), vec![0, 1, 2, 3]);
+        assert_eq!(ids(&stocks), vec![3, 0, 2, 1], "the market itself isn't reordered");
+    }
+
+    #[test]
+    fn trade_batches_are_all_or_nothing() {
+        let stocks = vec![stock(0, 10), stock(1, 20)];
+        let mut player = Player::new(100, 0);
+        player.apply_trades(&[(0, 4), (1, 3)], &stocks).unwrap();
+        assert_eq!((player.balance(), player.stock_balance(&stocks[0])), (0, 4));
+
+        assert_eq!(player.apply_trades(&[(0, -2), (1, -4)], &stocks),
+                   Err(TransactionError::InsufficientStock));
+        assert_eq!(player.apply_trades(&[(0, -2), (5, 1)], &stocks),
+                   Err(TransactionError::UnknownStock(5)));
+        assert_eq!(player.apply_trades(&[(0, -4), (1, 3)], &stocks),
+                   Err(TransactionError::InsufficientFunds));
+        assert_eq!((player.balance(), player.stock_balance(&stocks[0])), (0, 4));
+        assert_eq!(player.stock_balance(&stocks[1]), 3);
+
+        player.apply_trades(&[(0, -4), (1, 2)], &stocks).unwrap();
+        assert_eq!((player.balance(), player.stock_balance(&stocks[1])), (0, 5));
+    }
+
+    #[test]
+    fn empty_trade_batches_change_nothing() {
+        let stocks = [stock(0, 10)];
+        let mut player = Player::new(100, 0);
+        player.buy_stock(&stocks[0], 2).unwrap();
+        let before = serde_json::to_string(&player).unwrap();
+
+        assert_eq!(player.apply_trades(&[], &stocks), Ok(()));
+        assert_eq!(player.apply_trades(&[], &[]), Ok(()));
+        assert_eq!(serde_json::to_string(&player).unwrap(), before, "nothing was recorded");
+    }
+
+    #[test]
+    fn turn_pl_is_relative_to_the_earlier_net_worth() {
+        assert_eq!(turn_pl(1000, 1100), (100, 10.0));
+        assert_eq!(turn_pl(1000, 900), (-100, -10.0));
+        assert_eq!(turn_pl(-1000, -500), (500, 50.0));
+        assert_eq!(turn_pl(0, 500), (500, 0.0));
+    }
+
+    #[test]
+    fn watched_stocks_are_listed_first() {
+        let stocks = vec![stock(2, 10), stock(0, 10), stock(3, 10), stock(1, 10)];
+        let mut player = Player::new(0, 0);
+        assert!(player.toggle_watch(3));
+        assert!(player.toggle_watch(1));
+        assert_eq!(ids(watched_first(&stocks, &player)), vec![1, 3, 0, 2]);
+
+        assert!(!player.toggle_watch(3));
+        assert!(!player.is_watched(3));
+        assert_eq!(ids(watched_first(&stocks, &player)), vec![1, 0, 2, 3]);
+    }
+
+    #[test]
+    fn selling_realizes_profit_against_the_average_cost() {
+        let a = stock(0, 10);
+        let mut player = Player::new(1000, 0);
+        player.buy_stock_at(0, 10, 10).unwrap();
+        player.buy_stock_at(0, 20, 10).unwrap();
+        assert_eq!(player.cost_basis(&a), 300);
+
+        // Half the shares carry half the basis, 150, and sell for 250
+        player.sell_stock_at(0, 25, 10).unwrap();
+        assert_eq!(player.realized_pl(), 100);
+        assert_eq!(player.cost_basis(&a), 150);
+        assert_eq!(player.balance(), 950);
+
+        player.reset_stock(&a);
+        assert_eq!(player.realized_pl(), -50);
+        assert_eq!(player.cost_basis(&a), 0);
+    }
+
+    #[test]
+    fn from_parts_keeps_every_part() {
+        let mut stock = Stock::from_parts(4, "Cake Store".to_string(), 80, 120, 6, -3);
+        assert_eq!((stock.id(), stock.name(), stock.value()), (4, "Cake Store", 80));
+        assert_eq!((stock.initial_value, stock.variation, stock.direction), (120, 6, -3));
+        assert_eq!(stock.history().iter().copied().collect::<Vec<_>>(), vec![80]);
+
+        stock.reset();
+        assert_eq!((stock.value(), stock.direction), (120, 0));
+    }
+
+    #[test]
+    fn from_parts_stocks_keep_their_trend() {
+        // Without variation the move is just the trend, slowing to 3/5 of it each turn
+        let mut stock = Stock::from_parts(2, "Tea House".to_string(), 80, 120, 0, -10);
+        stock.vary(&mut rng());
+        assert_eq!((stock.value(), stock.direction), (74, -6));
+
+        let json = serde_json::to_string(&stock).unwrap();
+        let mut reloaded: Stock = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&reloaded).unwrap(), json);
+        assert_eq!((reloaded.id(), reloaded.name(), reloaded.initial_value), (2, "Tea House", 120));
+        reloaded.vary(&mut rng());
+        assert_eq!((reloaded.value(), reloaded.direction), (71, -3));
+    }
+
+    #[test]
+    fn net_worth_with_prices_uses_the_given_prices() {
+        let stocks = vec![stock(0, 10), stock(1, 20)];
+        let mut player = Player::new(100, 0);
+        player.buy_stock(&stocks[0], 2).unwrap();
+        player.buy_stock(&stocks[1], 3).unwrap();
+        assert_eq!(player.net_worth(&stocks), 100);
+
+        let prices: HashMap<i64, i64> = vec![(0, 50), (1, 1)].into_iter().collect();
+        assert_eq!(player.net_worth_with_prices(&prices), 20 + 100 + 3);
+        let partial: HashMap<i64, i64> = vec![(1, 30)].into_iter().collect();
+        assert_eq!(player.net_worth_with_prices(&partial), 20 + 90);
+        assert_eq!(player.holdings_value_at(&partial), 90);
+        assert_eq!(player.holdings_value_at(&HashMap::new()), 0);
+    }
+
+    #[test]
+    fn splitting_divides_the_value() {
+        let mut stock = Stock::new(0, "A".to_string(), 1000, 30);
+        stock.split(3);
+        assert_eq!((stock.value(), stock.variation, stock.initial_value), (333, 10, 333));
+    }
+
+    #[test]
+    fn splitting_never_makes_shares_worth_more() {
+        let mut stock = Stock::new(0, "A".to_string(), 3, 2);
+        assert!(!stock.split(5));
+        assert_eq!((stock.value(), stock.variation), (3, 2));
+
+        let mut cheap = Stock::new(0, "A".to_string(), 5, 2);
+        assert!(cheap.split(5));
+        assert_eq!((cheap.value(), cheap.variation), (1, 1));
+        assert!(!cheap.is_bankrupt());
+
+        let mut flat = Stock::new(0, "A".to_string(), 10, 0);
+        assert!(flat.split(5));
+        assert_eq!(flat.variation, 0);
+        assert!(!flat.split(0));
+        assert_eq!(flat.value(), 2);
+    }
+
+    #[test]
+    fn negative_trades_are_rejected() {
+        let a = stock(0, 10);
+        let mut player = Player::new(100, 0);
+        assert_eq!(player.buy_stock_at(0, 10, -10), Err(TransactionError::InvalidAmount));
+        assert_eq!(player.buy_stock(&a, -10), Err(()));
+        player.buy_stock(&a, 5).unwrap();
+        assert_eq!(player.sell_stock_at(0, 10, -5), Err(TransactionError::InvalidAmount));
+        assert_eq!(player.sell_stock(&a, -5), Err(()));
+        assert_eq!((player.balance(), player.stock_balance(&a)), (50, 5));
+    }
+
+    #[test]
+    fn trades_at_a_price_ignore_the_value() {
+        let a = stock(0, 10);
+        let mut player = Player::new(100, 0);
+        player.buy_stock_at(0, 4, 5).unwrap();
+        assert_eq!((player.balance(), player.cost_basis(&a)), (80, 20));
+        assert_eq!(player.buy_stock_at(0, 100, 1), Err(TransactionError::InsufficientFunds));
+        player.sell_stock_at(0, 30, 2).unwrap();
+        assert_eq!((player.balance(), player.stock_balance(&a)), (140, 3));
+        assert_eq!(player.sell_stock_at(0, 30, 4), Err(TransactionError::InsufficientStock));
+        player.buy_stock_at(0, 10, 0).unwrap();
+        assert_eq!(player.stock_balance(&a), 3);
+    }
+
+    #[test]
+    fn stock_ranges_generate_within_their_bounds() {
+        let range = StockRange::new(50, 20, 5, 1);
+        assert_eq!(range, StockRange::new(20, 50, 1, 5));
+
+        let mut rng = rng();
+        for id in 0..200 {
+            let s = range.generate(&mut rng, id, "A".to_string());
+            assert!((20..=50).contains(&s.value()));
+            assert!((1..=5).contains(&s.variation));
+        }
+    }
+
+    #[test]
+    fn trading_at_market_value_keeps_net_worth() {
+        let stocks = [stock(0, 30), stock(1, 7)];
+        let mut player = Player::new(1000, 0);
+        let before = player.net_worth(&stocks);
+        player.buy_stock(&stocks[0], 12).unwrap();
+        player.buy_stock(&stocks[1], 40).unwrap();
+        player.sell_stock(&stocks[0], 5).unwrap();
+        assert_eq!(player.net_worth(&stocks), before);
+    }
+
+    #[test]
+    fn selling_up_to_caps_at_the_holdings() {
+        let a = stock(0, 10);
+        let mut player = Player::new(100, 0);
+        player.buy_stock(&a, 4).unwrap();
+        assert_eq!(player.sell_up_to(&a, 10), 4);
+        assert_eq!(player.sell_up_to(&a, 3), 0);
+        assert_eq!(player.sell_up_to(&a, -2), 0);
+        assert_eq!(player.balance(), 100);
+
+        player.buy_stock(&a, 6).unwrap();
+        assert_eq!(player.sell_up_to(&a, 6), 6, "exactly the holding");
+        assert_eq!(player.stock_balance(&a), 0);
+        player.buy_stock(&a, 6).unwrap();
+        assert_eq!(player.sell_up_to(&a, 2), 2, "less than the holding");
+        assert_eq!((player.stock_balance(&a), player.balance()), (4, 60));
+    }
+
+    #[test]
+    fn big_moves_halt_the_stock_for_a_turn() {
+        let mut s = Stock::new(0, "A".to_string(), 100, 0)
+            .with_price_series(vec![100, 120, 125, 100]);
+        s.update_halt(2000);
+        assert!(!s.is_halted());
+        step(&mut s, 1);
+        s.update_halt(2000);
+        assert!(!s.is_halted(), "a move of exactly the threshold doesn't halt");
+        step(&mut s, 1);
+        s.update_halt(300);
+        assert!(s.is_halted());
+        step(&mut s, 1);
+        s.update_halt(2500);
+        assert!(!s.is_halted());
+        s.update_halt(1000);
+        assert!(s.is_halted());
+        s.reset();
+        assert!(!s.is_halted());
+    }
+
+    #[test]
+    fn breakdown_is_a_markdown_table() {
+        let stocks = [Stock::new(1, "B|C".to_string(), 20, 1), stock(0, 5)];
+        let mut player = Player::new(100, 0);
+        player.buy_stock(&stocks[0], 2).unwrap();
+        assert_eq!(breakdown_markdown(&player, &stocks),
+                   "| Stock | Shares | Value | Worth |\n|---|---:|---:|---:|\n\
+                    | Stock 0 | 0 | 5 | 0 |\n\
+                    | B\\|C | 2 | 20 | 40 |\n\
+                    \n**Balance:** 60  \n**Net worth:** 100\n");
+    }
+
+    #[test]
+    fn money_in_and_out_is_logged_with_a_reason() {
+        let mut player = Player::new(500, 100);
+        player.collect_income();
+        player.increase_income(200).unwrap();
+        player.deposit(50);
+        player.withdraw(30).unwrap();
+        assert!(player.withdraw(1000).is_err());
+
+        let flows: Vec<(i64, CashFlowReason)> =
+            player.cash_flows().iter().map(|f| (f.amount, f.reason)).collect();
+        assert_eq!(flows, vec![
+            (500, CashFlowReason::InitialBalance),
+            (100, CashFlowReason::Income),
+            (-200, CashFlowReason::IncomeUpgrade),
+            (50, CashFlowReason::ManualDeposit),
+            (-30, CashFlowReason::ManualWithdrawal),
+        ]);
+        assert_eq!(flows.iter().map(|f| f.0).sum::<i64>(), player.balance());
+    }
+
+    #[test]
+    fn locked_money_cant_be_spent() {
+        let a = stock(0, 10);
+        let mut player = Player::new(100, 0);
+        player.lock(70).unwrap();
+        assert!(player.lock(40).is_err());
+        assert_eq!((player.balance(), player.available_balance()), (100, 30));
+
+        assert!(player.buy_stock(&a, 4).is_err());
+        assert!(player.withdraw(31).is_err());
+        player.buy_stock(&a, 3).unwrap();
+        assert_eq!(player.available_balance(), 0);
+
+        player.unlock(20);
+        assert_eq!(player.locked_balance(), 50);
+        player.unlock(500);
+        assert_eq!((player.locked_balance(), player.available_balance()), (0, 70));
+    }
+
+    #[test]
+    fn locked_money_still_counts_towards_net_worth() {
+        let stocks = [stock(0, 10)];
+        let mut player = Player::new(100, 0);
+        player.buy_stock(&stocks[0], 2).unwrap();
+        player.lock(50).unwrap();
+        assert_eq!((player.balance(), player.available_balance()), (80, 30));
+        assert_eq!(player.net_worth(&stocks), 100);
+    }
+
+    #[test]
+    fn best_and_worst_holdings_by_unrealized_pl() {
+        let stocks = [stock(0, 10), stock(1, 20), stock(2, 5)];
+        let mut player = Player::new(100, 0);
+        assert_eq!((player.best_holding(&stocks), player.worst_holding(&stocks)), (None, None));
+
+        player.buy_stock_at(0, 5, 2).unwrap();
+        player.buy_stock_at(1, 30, 1).unwrap();
+        assert_eq!(player.best_holding(&stocks), Some((0, 10)));
+        assert_eq!(player.worst_holding(&stocks), Some((1, -10)));
+    }
+
+    #[test]
+    fn sorting_reorders_a_list_of_stocks() {
+        let stocks = [stock(2, 5), stock(0, 30), stock(1, 10)];
+        let mut player = Player::new(100, 0);
+        player.buy_stock(&stocks[0], 4).unwrap();
+        player.buy_stock(&stocks[2], 1).unwrap();
+        let sorted = |key, player| {
+            let mut list: Vec<&Stock> = stocks.iter().collect();
+            sort_stocks(&mut list, key, player);
+            ids(list)
+        };
+
+        assert_eq!(sorted(SortKey::Id, None), vec![0, 1, 2]);
+        assert_eq!(sorted(SortKey::Value, None), vec![0, 1, 2]);
+        assert_eq!(sorted(SortKey::HoldingSize, Some(&player)), vec![2, 1, 0]);
+        assert_eq!(sorted(SortKey::HoldingSize, None), vec![2, 0, 1]);
+        assert_eq!(ids(&stocks), vec![2, 0, 1]);
+    }
+
+    #[test]
+    fn drift_moves_the_value_a_turn_at_a_time() {
+        let mut up = Stock::new(0, "A".to_string(), 52_000, 0).with_annual_drift(10000);
+        step(&mut up, 1);
+        assert_eq!(up.value(), 53_000);
+
+        let mut down = Stock::new(1, "B".to_string(), 52_000, 0).with_annual_drift(-5200);
+        step(&mut down, 1);
+        assert_eq!(down.value(), 51_480);
+
+        let mut flat = stock(2, 52_000);
+        let mut drifting = stock(2, 52_000).with_annual_drift(0);
+        step(&mut flat, 5);
+        step(&mut drifting, 5);
+        assert_eq!(flat.value(), drifting.value());
+    }
+
+    #[test]
+    fn drift_adds_up_on_cheap_stocks() {
+        let mut up = Stock::new(0, "A".to_string(), 50, 0).with_annual_drift(10000);
+        step(&mut up, 1);
+        assert_eq!(up.value(), 50);
+        step(&mut up, TURNS_PER_YEAR as usize - 1);
+        assert!((120..=140).contains(&up.value()), "{}", up.value());
+
+        let mut down = Stock::new(1, "B".to_string(), 50, 0).with_annual_drift(-5000);
+        step(&mut down, TURNS_PER_YEAR as usize);
+        assert!((25..=35).contains(&down.value()), "{}", down.value());
+    }
+
+    #[test]
+    fn affordable_stocks_use_the_available_balance() {
+        let stocks = [stock(0, 50), stock(1, 20), stock(2, 30)];
+        let mut player = Player::new(60, 0);
+        assert_eq!(ids(player.affordable_stocks(&stocks)), vec![0, 1, 2]);
+        player.lock(35).unwrap();
+        assert_eq!(ids(player.affordable_stocks(&stocks)), vec![1]);
+    }
+
+    #[test]
+    fn holdings_table_columns_fit_their_widest_entry() {
+        let rows = [
+            HoldingRow { name: "Cake Mining".to_string(), shares: 3, value: 1200, worth: 3600 },
+            HoldingRow { name: "Crab".to_string(), shares: 150, value: 7, worth: 1050 },
+        ];
+        assert_eq!(render_holdings_table(&rows),
+                   "Stock        Shares  Value  Worth\n\
+                    Cake Mining       3   1200   3600\n\
+                    Crab            150      7   1050\n");
+        assert_eq!(render_holdings_table(&[]), "Stock  Shares  Value  Worth\n");
+    }
+
+    #[test]
+    fn shares_for_a_target_round_up() {
+        let a = stock(0, 10);
+        let mut player = Player::new(100, 0);
+        assert_eq!(player.shares_for_target(&a, 15, 50), Some(0));
+        assert_eq!(player.shares_for_target(&a, 15, 130), Some(6));
+        assert_eq!(player.shares_for_target(&a, 15, 131), Some(7));
+        assert_eq!(player.shares_for_target(&a, 15, 200), None);
+        assert_eq!(player.shares_for_target(&a, 10, 130), None);
+
+        player.buy_stock(&a, 2).unwrap();
+        assert_eq!(player.shares_for_target(&a, 15, 130), Some(4));
+    }
+
+    #[test]
+    fn liquidation_takes_off_the_spread_and_fees() {
+        let stocks = [stock(0, 100), stock(1, 40)];
+        let mut player = Player::new(1000, 0);
+        player.buy_stock(&stocks[0], 3).unwrap();
+        player.buy_stock(&stocks[1], 5).unwrap();
+
+        let truncate = RoundingMode::Truncate;
+        assert_eq!(player.net_liquidation_value(&stocks, 0, 0, truncate),
+                   player.net_worth(&stocks));
+        // Sold at 95 and 38, with 1% of each stock's proceeds taken off
+        assert_eq!(player.net_liquidation_value(&stocks, 100, 1000, truncate), 500 + 283 + 189);
+
+        // A spread of 0.75 on a value of 15, and a fee of 0.3 on 3 shares at the bid
+        let cheap = [stock(2, 15)];
+        let mut player = Player::new(45, 0);
+        player.buy_stock(&cheap[0], 3).unwrap();
+        assert_eq!(player.net_liquidation_value(&cheap, 100, 1000, truncate), 45);
+        assert_eq!(player.net_liquidation_value(&cheap, 100, 1000, RoundingMode::Round), 42);
+        assert_eq!(player.net_liquidation_value(&cheap, 100, 1000, RoundingMode::Ceil), 41);
+    }
+
+    #[test]
+    fn failed_trades_change_nothing() {
+        let mut player = Player::new(100, 0);
+        player.buy_stock_at(0, 10, 4).unwrap();
+        let before = serde_json::to_string(&player).unwrap();
+
+        assert!(player.buy_stock_at(0, 10, 7).is_err());
+        assert!(player.buy_stock_at(1, 61, 1).is_err());
+        assert!(player.sell_stock_at(0, 10, 5).is_err());
+        assert!(player.sell_stock_at(1, 10, 1).is_err());
+        assert_eq!(serde_json::to_string(&player).unwrap(), before);
+    }
+
+    #[test]
+    fn chained_setters_clamp_their_values() {
+        let s = stock(0, 10).with_value(25).with_variation(4);
+        assert_eq!((s.value(), s.variation, s.history().back()), (25, 4, Some(&25)));
+        let s = stock(0, 10).with_value(-3).with_variation(-1);
+        assert_eq!((s.value(), s.variation), (1, 0));
+    }
+
+    #[test]
+    fn beta_against_the_market_index() {
+        let scripted = |id, prices: &[i64]| {
+            let mut s = stock(id, prices[0]).with_price_series(prices.to_vec());
+            step(&mut s, prices.len() - 1);
+            s
+        };
+        let stocks = [scripted(0, &[100, 110, 99, 120]), scripted(1, &[50, 50])];
+        assert_eq!(market_index_history(&stocks), vec![149, 170]);
+        assert!(market_index_history(&[]).is_empty());
+
+        let alone = [scripted(0, &[100, 110, 99, 120])];
+        let index = market_index_history(&alone);
+        let mut player = Player::new(1000, 0);
+        assert_eq!(player.portfolio_beta(&alone, &index), None);
+        player.buy_stock(&alone[0], 2).unwrap();
+        let beta = player.portfolio_beta(&alone, &index).unwrap();
+        assert!((beta - 1.0).abs() < 1e-9);
+        assert_eq!(player.portfolio_beta(&alone, &index[2..]), None);
+
+        // The stock swings every turn while the market only moves in the second half, so
+        // the two don't move together at all
+        let swinging = [scripted(2, &[100, 110, 100, 110, 100])];
+        let mut player = Player::new(1000, 0);
+        player.buy_stock(&swinging[0], 2).unwrap();
+        let beta = player.portfolio_beta(&swinging, &[100, 100, 100, 200, 400]).unwrap();
+        assert!(beta.abs() < 1e-9);
+    }
+
+    #[test]
+    fn rounding_modes_divide() {
+        let divide = |mode: RoundingMode| {
+            [7, 5, -7, -5, 6].map(|n| mode.divide(n, 2))
+        };
+        assert_eq!(divide(RoundingMode::Truncate), [3, 2, -3, -2, 3]);
+        assert_eq!(divide(RoundingMode::Round), [4, 3, -4, -3, 3]);
+        assert_eq!(divide(RoundingMode::Ceil), [4, 3, -3, -2, 3]);
+        assert_eq!(apply_bps(333, 5000, RoundingMode::Round), 167);
+        assert_eq!(apply_bps(333, 5000, RoundingMode::default()), 166);
+    }
+
+    #[test]
+    fn remapping_moves_everything_to_the_new_id() {
+        let (old, new) = (stock(0, 10), stock(5, 10));
+        let mut player = Player::new(100, 0);
+        player.buy_stock(&old, 3).unwrap();
+        player.buy_stock(&new, 1).unwrap();
+        player.record_purchase(0, 1, 3);
+        player.toggle_watch(0);
+        player.toggle_rebuy_after_bankruptcy(&old);
+        player.add_recurring_buy(0, 20);
+
+        player.remap_holding(0, 5);
+        assert_eq!((player.stock_balance(&old), player.stock_balance(&new)), (0, 4));
+        assert_eq!((player.cost_basis(&old), player.cost_basis(&new)), (0, 40));
+        assert_eq!(player.settled_shares(5, 1, 2), 1);
+        assert!(player.is_watched(5) && !player.is_watched(0));
+        assert!(player.rebuys_after_bankruptcy(&new));
+        assert_eq!(player.recurring_buys(), &[(5, 20)]);
+    }
+
+    #[test]
+    fn loss_positions_are_the_holdings_below_cost() {
+        let mut player = Player::new(100, 0);
+        player.buy_stock(&stock(0, 10), 3).unwrap();
+        player.buy_stock(&stock(1, 10), 2).unwrap();
+        player.buy_stock(&stock(2, 10), 1).unwrap();
+        let stocks = [stock(0, 7), stock(1, 15), stock(2, 10)];
+        assert_eq!(player.loss_positions(&stocks), vec![(0, 9)]);
+        assert!(Player::new(100, 0).loss_positions(&stocks).is_empty());
+    }
+
+    #[test]
+    fn unique_names_skip_taken_ones_then_number_them() {
+        let first = generate_name(&mut rng());
+        let stocks = [Stock::new(0, first.clone(), 1, 0)];
+        assert_ne!(generate_unique_name(&mut rng(), &stocks), first);
+
+        let mut names = rng();
+        let mut stocks: Vec<Stock> = (0..NAME_RETRIES as i64)
+            .map(|id| Stock::new(id, generate_name(&mut names), 1, 0))
+            .collect();
+        let last = stocks.last().unwrap().name().to_string();
+        assert_eq!(generate_unique_name(&mut rng(), &stocks), format!("{} 2", last));
+        stocks.push(Stock::new(10, format!("{} 2", last), 1, 0));
+        assert_eq!(generate_unique_name(&mut rng(), &stocks), format!("{} 3", last));
+    }
+
+    #[test]
+    fn scripted_stocks_follow_their_series() {
+        let mut stock = Stock::new(0, "A".to_string(), 1, 5).with_price_series(vec![40, 60, 80]);
+        assert_eq!(stock.value(), 40);
+        step(&mut stock, 3);
+        assert_eq!(stock.value(), 80);
+        assert_eq!(stock.history().iter().copied().collect::<Vec<_>>(), [40, 60, 80, 80]);
+
+        stock.split(2);
+        assert_eq!(stock.behavior(), &StockBehavior::Scripted { prices: vec![20, 30, 40],
+                                                                index: 2 });
+        stock.reset();
+        step(&mut stock, 1);
+        assert_eq!(stock.value(), 30);
+
+        let plain = Stock::new(0, "B".to_string(), 10, 1).with_price_series(Vec::new());
+        assert_eq!(plain.behavior(), &StockBehavior::RandomWalk);
+    }
+
+    #[test]
+    fn maintain_cash_trades_in_proportion_to_holdings() {
+        let (a, b) = (stock(0, 10), stock(1, 20));
+        let mut player = Player::new(1000, 0);
+        player.buy_stock(&a, 30).unwrap();
+        player.buy_stock(&b, 10).unwrap();
+        let stocks = [a, b];
+        let state = |p: &Player| {
+            (p.balance(), p.stock_balance(&stocks[0]), p.stock_balance(&stocks[1]))
+        };
+
+        player.maintain_cash(600, &stocks).unwrap();
+        assert_eq!(state(&player), (600, 24, 8));
+        player.maintain_cash(400, &stocks).unwrap();
+        assert_eq!(state(&player), (400, 36, 12));
+        assert_eq!(player.maintain_cash(10_000, &stocks), Err(TransactionError::InsufficientStock));
+        assert_eq!(state(&player), (400, 36, 12));
+    }
+
+    #[test]
+    fn scaling_variation_changes_the_random_move() {
+        let mut still = Stock::new(0, "A".to_string(), 1000, 50);
+        let mut r = rng();
+        for _ in 0..10 { still.vary_scaled(&mut r, 0); }
+        assert_eq!(still.value(), 1000);
+
+        let (mut plain, mut scaled) = (stock(0, 1000).with_variation(50),
+                                       stock(0, 1000).with_variation(50));
+        step(&mut plain, 10);
+        let mut r = rng();
+        for _ in 0..10 { scaled.vary_scaled(&mut r, 10000); }
+        assert_eq!(plain.history(), scaled.history());
+    }
+
+    #[test]
+    fn book_value_counts_what_was_paid_for_holdings() {
+        let mut player = Player::new(200, 0);
+        player.buy_stock(&stock(0, 10), 3).unwrap();
+        player.buy_stock(&stock(1, 20), 2).unwrap();
+        assert_eq!((player.cost_basis_total(), player.book_value()), (70, 200));
+
+        player.sell_stock(&stock(0, 10), 1).unwrap();
+        assert_eq!((player.cost_basis_total(), player.book_value()), (60, 200));
+        let stocks = [stock(0, 15), stock(1, 20)];
+        assert_eq!(player.net_worth(&stocks) - player.book_value(), 10);
+    }
+
+    #[test]
+    fn deposits_and_withdrawals_return_the_new_balance() {
+        let mut player = Player::new(100, 0);
+        assert_eq!(player.deposit(50), 150);
+        assert_eq!(player.withdraw(30), Ok(120));
+        player.lock(100).unwrap();
+        assert_eq!(player.withdraw(30), Err(TransactionError::InsufficientFunds));
+        assert_eq!(player.balance(), 120);
+    }
+
+    #[test]
+    fn bias_leans_the_next_move() {
+        let mut up = Stock::new(0, "A".to_string(), 1000, 0);
+        up.bias(500);
+        step(&mut up, 1);
+        assert_eq!(up.value(), 1030);
+
+        let mut down = Stock::new(0, "B".to_string(), 1000, 0);
+        down.bias(-500);
+        step(&mut down, 1);
+        assert_eq!(down.value(), 970);
+    }
+
+    #[test]
+    fn bias_moves_cheap_stocks_too() {
+        let mut up = Stock::new(0, "A".to_string(), 50, 0);
+        up.bias(50);
+        assert_eq!(up.direction, 1);
+        let mut down = Stock::new(0, "B".to_string(), 50, 0);
+        down.bias(-50);
+        assert_eq!(down.direction, -1);
+        let mut still = Stock::new(0, "C".to_string(), 50, 0);
+        still.bias(0);
+        assert_eq!(still.direction, 0);
+    }
+
+    #[test]
+    fn income_to_net_worth_guards_non_positive_wealth() {
+        let mut player = Player::new(150, 50);
+        player.buy_stock(&stock(0, 10), 5).unwrap();
+        assert!((player.income_to_net_worth(&[stock(0, 10)]) - 50.0 / 150.0).abs() < 1e-9);
+        // Without the stock to price the shares, only the 100 cash is counted
+        assert!((player.income_to_net_worth(&[]) - 0.5).abs() < 1e-9);
+        assert_eq!(Player::new(0, 50).income_to_net_worth(&[]), 0.0);
+        assert_eq!(Player::new(-10, 50).income_to_net_worth(&[]), 0.0);
+    }
+
+    #[test]
+    fn stocks_stop_being_new_after_a_few_turns() {
+        let mut young = stock(0, 10);
+        assert!(young.is_new());
+        for _ in 0..NEW_STOCK_TURNS { young.age(); }
+        assert_eq!(young.turns_alive(), NEW_STOCK_TURNS);
+        assert!(!young.is_new());
+
+        let mut json: serde_json::Value = serde_json::to_value(stock(1, 10)).unwrap();
+        json.as_object_mut().unwrap().remove("turns_alive").unwrap();
+        let old: Stock = serde_json::from_value(json).unwrap();
+        assert!(!old.is_new());
+    }
+
+    #[test]
+    fn players_serialize_in_id_order() {
+        let mut player = Player::new(1000, 0);
+        for id in [7, 2, 11, 0, 5] {
+            player.buy_stock(&stock(id, 10), 1).unwrap();
+            player.toggle_watch(id);
+        }
+        let json = serde_json::to_string(&player).unwrap();
+        assert!(json.contains(r#""stock_balances":{"0":1,"2":1,"5":1,"7":1,"11":1}"#));
+        assert!(json.contains(r#""watchlist":[0,2,5,7,11]"#));
+
+        let reloaded: Player = serde_json::from_str(&json).unwrap();
+        assert_eq!(serde_json::to_string(&reloaded).unwrap(), json);
+    }
+
+    #[test]
+    fn scenarios_shock_every_holding() {
+        let mut player = Player::new(500, 0);
+        player.buy_stock(&stock(0, 10), 10).unwrap();
+        player.buy_stock(&stock(1, 50), 4).unwrap();
+        let stocks = [stock(0, 10), stock(1, 50), stock(2, 99)];
+        assert_eq!(player.scenario_net_worth(&stocks, 0), player.net_worth(&stocks));
+        assert_eq!(player.scenario_net_worth(&stocks, -2000), 200 + 80 + 160);
+        assert_eq!(player.scenario_net_worth(&stocks, 5000), 200 + 150 + 300);
+        assert_eq!(player.scenario_net_worth(&stocks, -10000), 200);
+    }
+
+    #[test]
+    fn held_only_keeps_held_stocks_in_order() {
+        let stocks = [stock(0, 10), stock(1, 10), stock(2, 10)];
+        let mut player = Player::new(100, 0);
+        player.buy_stock(&stocks[2], 1).unwrap();
+        player.buy_stock(&stocks[0], 1).unwrap();
+        let listed = vec![&stocks[2], &stocks[1], &stocks[0]];
+        assert_eq!(ids(held_only(listed, &player)), [2, 0]);
+        assert!(held_only(stocks.iter().collect(), &Player::new(0, 0)).is_empty());
+    }
+
+    #[test]
+    fn selling_by_value_sells_whole_shares_up_to_holdings() {
+        let mut player = Player::new(100, 0);
+        player.buy_stock(&stock(0, 10), 5).unwrap();
+        assert_eq!(player.shares_worth(0, 10, 35), 3);
+        assert_eq!(player.shares_worth(0, 10, 1000), 5);
+        assert_eq!((player.shares_worth(0, 0, 35), player.shares_worth(1, 10, 35)), (0, 0));
+
+        assert_eq!(player.sell_stock_value(&stock(0, 12), 30), Ok(2));
+        assert_eq!(player.balance(), 50 + 24);
+        assert_eq!(player.sell_stock_value(&stock(0, 12), 1000), Ok(3));
+        assert_eq!(player.stock_balance(&stock(0, 12)), 0);
+    }
+
+    #[test]
+    fn resetting_a_player_clears_everything() {
+        let mut player = Player::new(100, 10);
+        player.buy_stock(&stock(0, 10), 3).unwrap();
+        player.toggle_watch(0);
+        player.lock(20).unwrap();
+        player.increase_income(10).unwrap();
+
+        player.reset(500, 20);
+        let fresh = Player::new(500, 20);
+        assert_eq!(serde_json::to_string(&player).unwrap(),
+                   serde_json::to_string(&fresh).unwrap());
+    }
+
+    #[test]
+    fn percentages_are_signed_and_never_negative_zero() {
+        assert_eq!(format_percent(12.345, 2), "+12.35%");
+        assert_eq!(format_percent(-3.0, 0), "-3%");
+        assert_eq!(format_percent(-0.0, 1), "+0.0%");
+        assert_eq!(format_percent(f64::NAN, 2), "n/a");
+        assert_eq!(format_percent(f64::INFINITY, 2), "n/a");
+    }
+
+    #[test]
+    fn income_upgrades_pay_back_in_the_starting_income() {
+        let mut player = Player::new(1000, 100);
+        player.increase_income(0).unwrap();
+        assert_eq!(player.income(), 200);
+        assert_eq!(player.income_upgrade_payback(250), Some(3));
+        assert_eq!(player.income_upgrade_payback(0), Some(0));
+        assert_eq!(player.income_upgrade_payback(i64::MAX), None);
+        assert_eq!(Player::new(1000, 0).income_upgrade_payback(250), None);
+    }
+
+    #[test]
+    fn stocks_are_bankrupt_at_zero_or_below() {
+        let mut falling = Stock::new(0, "A".to_string(), 5, 0).with_price_series(vec![5, 1, 0, -3]);
+        let mut bankrupt = Vec::new();
+        for _ in 0..4 {
+            bankrupt.push(falling.is_bankrupt());
+            step(&mut falling, 1);
+        }
+        assert_eq!(bankrupt, [false, false, true, true]);
+        assert!(falling.clamp_value() && !falling.is_bankrupt());
+    }
+
+    #[test]
+    fn generated_stocks_can_start_trending() {
+        let mut rng = rng();
+        let flat = generate_stock(&mut rng, 0, 10, 100, 5, 20, 0, String::new());
+        assert_eq!(flat.direction, 0);
+
+        let stocks: Vec<_> = (0..50)
+            .map(|id| generate_stock(&mut rng, id, 10, 100, 5, 20, 5000, String::new()))
+            .collect();
+        assert!(stocks.iter().any(|stock| stock.direction != 0));
+        for stock in &stocks {
+            assert!(stock.direction.abs() <= stock.variation / 2);
+            assert_eq!(stock.initial_value, stock.value);
+        }
+    }
+
+    #[test]
+    fn share_snapshots_match_stock_balances() {
+        let stocks = vec![stock(0, 10), stock(1, 10), stock(2, 10)];
+        let mut player = Player::new(1000, 0);
+        player.buy_stock(&stocks[2], 3).unwrap();
+        player.buy_stock(&stocks[0], 1).unwrap();
+        player.buy_stock(&stocks[1], 2).unwrap();
+        player.sell_stock(&stocks[1], 2).unwrap();
+
+        let snapshot = player.shares_snapshot();
+        assert_eq!(snapshot, vec![(0, 1), (2, 3)]);
+        for stock in &stocks {
+            let held = snapshot.iter().find(|(id, _)| *id == stock.id()).map_or(0, |(_, n)| *n);
+            assert_eq!(held, player.stock_balance(stock));
+        }
+    }
+
+    #[test]
+    fn penny_stock_chance_picks_the_penny_profile() {
+        let mut rng = rng();
+        let is_penny = |stock: &Stock| {
+            (1..=5).contains(&stock.value) && (5..=20).contains(&stock.variation)
+        };
+        let range = StockRange::new(100, 200, 50, 60);
+        assert!((0..50).all(|id| !is_penny(&range.generate(&mut rng, id, String::new()))));
+        let range = range.with_penny_stock_chance(10000);
+        assert!((0..50).all(|id| is_penny(&range.generate(&mut rng, id, String::new()))));
+
+        let json = r#"{"min_value":1,"max_value":2,"min_variation":3,"max_variation":4}"#;
+        let loaded: StockRange = serde_json::from_str(json).unwrap();
+        assert_eq!(loaded.penny_stock_chance_bps, 0);
+    }
+
+    #[test]
+    fn net_worth_components_add_up_to_net_worth() {
+        let stocks = vec![stock(0, 10), stock(1, 25)];
+        let mut player = Player::new(200, 0);
+        player.buy_stock(&stocks[0], 3).unwrap();
+        player.buy_stock(&stocks[1], 4).unwrap();
+
+        let components = player.net_worth_components(&stocks);
+        assert_eq!(components, NetWorthComponents { cash: 70, long_value: 130 });
+        assert_eq!(components.total(), player.net_worth(&stocks));
+        assert_eq!(Player::new(5, 0).net_worth_components(&stocks).long_value, 0);
+    }
+
+    #[test]
+    fn cost_to_reach_prices_the_missing_shares() {
+        let target = stock(0, 100);
+        assert_eq!(target.cost_to_reach(2, 5, 0), 300);
+        assert_eq!(target.cost_to_reach(2, 5, 50), 303);
+        assert_eq!(target.cost_to_reach(0, 1, 10000), 200);
+        assert_eq!(target.cost_to_reach(5, 5, 50), 0);
+        assert_eq!(target.cost_to_reach(8, 5, 50), 0);
+    }
+
+    #[test]
+    fn player_diffs_show_what_changed() {
+        let stocks = [stock(0, 10), stock(1, 10), stock(2, 10)];
+        let mut before = Player::new(100, 5);
+        before.buy_stock(&stocks[0], 2).unwrap();
+        before.buy_stock(&stocks[1], 1).unwrap();
+        let mut after = before.clone();
+        after.sell_stock(&stocks[0], 2).unwrap();
+        after.buy_stock(&stocks[2], 3).unwrap();
+        after.increase_income(20).unwrap();
+
+        let diff = before.diff(&after);
+        assert_eq!(diff.balance, -30);
+        assert_eq!(diff.income, after.income() - 5);
+        assert_eq!(diff.shares, vec![(0, -2), (2, 3)]);
+        assert_eq!(after.diff(&before).shares, vec![(0, 2), (2, -3)]);
+        assert_eq!(before.diff(&before), PlayerDiff { balance: 0, income: 0, shares: vec![] });
+    }
+
+    #[test]
+    fn portfolio_avg_cost_weighs_holdings_by_value() {
+        let bought = [stock(0, 10), stock(1, 50)];
+        let mut player = Player::new(1000, 0);
+        assert_eq!(player.portfolio_avg_cost(&bought), None);
+        player.buy_stock(&bought[0], 4).unwrap();
+        player.buy_stock(&bought[1], 2).unwrap();
+        assert_eq!(player.portfolio_avg_cost(&bought), Some(1.0));
+
+        let later = [Stock::from_parts(0, String::new(), 20, 10, 1, 0),
+                     Stock::from_parts(1, String::new(), 40, 50, 1, 0)];
+        assert_eq!(player.portfolio_avg_cost(&later), Some(140.0 / 160.0));
+        let bust = [Stock::from_parts(0, String::new(), 0, 10, 1, 0),
+                    Stock::from_parts(1, String::new(), 0, 50, 1, 0)];
+        assert_eq!(player.portfolio_avg_cost(&bust), None);
+    }
+
+    #[test]
+    fn weighted_names_follow_their_weights() {
+        let mut rng = rng();
+        let words = NameWords {
+            first: vec![("Big".to_string(), 3), ("Small".to_string(), 1)],
+            last: vec![("Box".to_string(), 1)],
+        };
+        let big = (0..4000).filter(|_| words.generate(&mut rng) == "Big Box").count();
+        assert!((2800..3200).contains(&big), "{}", big);
+
+        assert_eq!(generate_weighted_name(&mut rng, &words.first, &[]), None);
+        let unweighted = [("Big".to_string(), 0)];
+        assert_eq!(generate_weighted_name(&mut rng, &unweighted, &words.last), None);
+        let fallback = NameWords { first: vec![], ..words };
+        assert_eq!(fallback.generate(&mut rng).split(' ').count(), 2);
+    }
+
+    #[test]
+    fn liquidating_sells_every_share_even_unsettled_ones() {
+        let held = stock(0, 10);
+        let mut player = Player::new(100, 0);
+        player.buy_stock(&held, 5).unwrap();
+        player.record_purchase(0, 3, 5);
+        assert_eq!(player.settled_shares(0, 3, 2), 0);
+
+        assert_eq!(player.liquidate_stock(0, 7), 35);
+        assert_eq!(player.stock_balance(&held), 0);
+        assert_eq!(player.balance(), 85);
+        player.buy_stock(&held, 1).unwrap();
+        assert_eq!(player.settled_shares(0, 3, 2), 1);
+        assert_eq!(player.liquidate_stock(9, 7), 0);
+    }
+
+    #[test]
+    fn negative_amounts_cant_be_locked_or_unlocked() {
+        let mut player = Player::new(100, 0);
+        assert_eq!(player.lock(-50), Err(()));
+        assert_eq!(player.locked_balance(), 0);
+        assert_eq!(player.available_balance(), 100);
+
+        player.lock(30).unwrap();
+        player.unlock(-50);
+        assert_eq!(player.locked_balance(), 30);
+        assert_eq!(player.available_balance(), 70);
+    }
+}
@@ -1,12 +1,36 @@
 use std::collections::HashMap;
+use std::env;
 use std::fs;
-use std::fmt::Display;
+use std::fmt::{self, Display};
 use std::hash::Hash;
-use std::io::{self, Write};
-use std::path::PathBuf;
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
 use std::process;
-use millionaire::{self, Player, Stock};
-use millionaire::save::{self, Error, Game};
+use std::sync::atomic::{AtomicBool, Ordering};
+use chrono::offset::Local;
+use millionaire::{self, HoldingRow, Player, SortKey, Stock, StockRange, TransactionError};
+use millionaire::save::{self, Error, Game, GameDiff, GameRng, TickOutcome, Verbosity,
+                      Action, ActionResult, Confirmation};
+use rand::SeedableRng;
+
+/// Whether output is colored. Set once when the game starts.
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// Wraps text in an ANSI escape code when color is on.
+fn paint(code: &str, text: String) -> String {
+    if COLOR.load(Ordering::Relaxed) { format!("\x1b[{}m{}\x1b[0m", code, text) } else { text }
+}
+
+fn bold(text: String) -> String { paint("1", text) }
+
+/// Colors text green if `amount` is a gain and red if it's a loss.
+fn gain_or_loss(amount: i64, text: String) -> String {
+    match amount {
+        a if a > 0 => paint("32", text),
+        a if a < 0 => paint("31", text),
+        _ => text,
+    }
+}
 
 fn double_check(prompt: &str, default: bool) -> Result<bool, io::Error> {
     print!("{} {} ", prompt, if default { "(Y/n)" } else { "(y/N)" });
@@ -42,20 +66,121 @@ fn number_input(prompt: &str) -> Result<usize, io::Error> {
     }
 }
 
+fn number_input_range(prompt: &str, min: usize, max: usize) -> Result<usize, io::Error> {
+    loop {
+        match check_range(number_input(prompt)?, min, max) {
+            Ok(choice) => return Ok(choice),
+            Err(problem) => println!("{}\n", problem),
+        }
+    }
+}
+
+/// How much the player asked to sell: a number of shares, or an amount of money's worth
+/// when the input starts with `$`.
+#[derive(Debug, PartialEq, Eq)]
+enum SellInput {
+    Shares(i64),
+    Money(i64),
+}
+
+/// Reads an amount to sell like `12` or `$500`, explaining why if it isn't one.
+fn parse_sell_input(input: &str) -> Result<SellInput, String> {
+    let input = input.trim();
+    let (money, number) = match input.strip_prefix('$') {
+        Some(rest) => (true, rest.trim()),
+        None => (false, input),
+    };
+
+    match number.parse::<i64>() {
+        Ok(n) if n >= 0 && money => Ok(SellInput::Money(n)),
+        Ok(n) if n >= 0 => Ok(SellInput::Shares(n)),
+        _ => Err(format!("`{}` was not a number of shares or a $ amount!", input)),
+    }
+}
+
+fn sell_input(prompt: &str) -> Result<SellInput, io::Error> {
+    loop {
+        print!("{}", prompt); io::stdout().flush()?;
+        let mut choice = String::new();
+        io::stdin().read_line(&mut choice)?;
+
+        match parse_sell_input(&choice) {
+            Ok(input) => return Ok(input),
+            Err(problem) => println!("{}\n", problem),
+        }
+    }
+}
+
+/// Checks a number is between `min` and `max`, inclusive, explaining why if it isn't.
+fn check_range(choice: usize, min: usize, max: usize) -> Result<usize, String> {
+    if (min..=max).contains(&choice) { return Ok(choice); }
+    Err(format!("`{}` must be between {} and {}!", choice, min, max))
+}
+
+/// How many entries long menus show at once.
+const MENU_PAGE_SIZE: usize = 15;
+
+/// One page of a menu: the range of entries shown on it and whether there are pages
+/// before and after it.
+struct Page {
+    start: usize,
+    end: usize,
+    has_prev: bool,
+    has_next: bool,
+}
+
+fn page(len: usize, page_size: usize, page: usize) -> Page {
+    let page_size = page_size.max(1);
+    let last_page = len.saturating_sub(1) / page_size;
+    let page = page.min(last_page);
+    let start = page * page_size;
+
+    Page {
+        start,
+        end: (start + page_size).min(len),
+        has_prev: page > 0,
+        has_next: page < last_page,
+    }
+}
+
 fn menu<T: Hash + Display>(options: &[T], cancel: bool) -> Result<Option<&T>, io::Error> {
+    paged_menu(options, cancel, usize::MAX)
+}
+
+/// Like `menu`, but only shows `page_size` entries at a time. Entries keep the same
+/// number on every page, and the numbers after the last entry move between pages.
+fn paged_menu<T: Hash + Display>(options: &[T], cancel: bool, page_size: usize)
+    -> Result<Option<&T>, io::Error> {
+    let next = options.len() + 1;
+    let prev = options.len() + 2;
+    let mut current = 0;
+
     loop {
         let mut map = HashMap::new();
+        let shown = page(options.len(), page_size, current);
 
-        for (idx, t) in options.iter().enumerate() {
+        for (idx, t) in options.iter().enumerate().take(shown.end).skip(shown.start) {
             let idx = idx + 1;
             map.insert(idx, t);
             println!("{}. {}", idx, t);
         }
 
+        if shown.has_next { println!("{}. Next page", next); }
+        if shown.has_prev { println!("{}. Previous page", prev); }
         if cancel { println!("0. Exit"); }
         let choice = number_input("Please choose an option: ")?;
         
         if cancel && choice == 0 { return Ok(None); }
+        if shown.has_next && choice == next {
+            current += 1;
+            println!();
+            continue;
+        }
+        if shown.has_prev && choice == prev {
+            current -= 1;
+            println!();
+            continue;
+        }
         return match map.get(&choice) {
             Some(t) => Ok(Some(*t)),
             None => {
@@ -66,6 +191,69 @@ fn menu<T: Hash + Display>(options: &[T], cancel: bool) -> Result<Option<&T>, io
     }
 }
 
+/// A menu entry with an optional note, like why it's currently unavailable.
+#[derive(Hash)]
+struct MenuOption<'a> {
+    label: &'a str,
+    note: Option<String>,
+}
+
+impl<'a> MenuOption<'a> {
+    fn new(label: &'a str) -> Self { Self { label, note: None } }
+}
+
+impl Display for MenuOption<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.note {
+            Some(note) => write!(f, "{} ({})", self.label, note),
+            None => write!(f, "{}", self.label),
+        }
+    }
+}
+
+/// A stock as listed to the player, marked if it's on their watchlist.
+#[derive(Hash)]
+struct StockEntry<'a> {
+    stock: &'a Stock,
+    watched: bool,
+    show_id: bool,
+}
+
+impl Display for StockEntry<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.watched { write!(f, "★ ")?; }
+        write!(f, "{}", self.stock)?;
+        if self.show_id { write!(f, " [#{}]", self.stock.id())?; }
+        if self.stock.is_halted() { write!(f, " (HALTED)")?; }
+        if self.stock.is_new() { write!(f, " (NEW)")?; }
+        Ok(())
+    }
+}
+
+/// Lists stocks sorted by `order`, with watched stocks first.
+fn stock_menu<'a>(stocks: &'a [Stock], player: &Player, show_ids: bool, order: SortKey)
+    -> Result<Option<&'a Stock>, io::Error> {
+    stock_ref_menu(stocks.iter().collect(), player, show_ids, order)
+}
+
+fn stock_ref_menu<'a>(ordered: Vec<&'a Stock>, player: &Player, show_ids: bool,
+                      order: SortKey) -> Result<Option<&'a Stock>, io::Error> {
+    let entries = stock_entries(ordered, player, show_ids, order);
+    Ok(paged_menu(&entries, true, MENU_PAGE_SIZE)?.map(|e| e.stock))
+}
+
+/// The entries of a stock menu in the order they're numbered, starting from 1.
+fn stock_entries<'a>(mut ordered: Vec<&'a Stock>, player: &Player, show_ids: bool,
+                     order: SortKey) -> Vec<StockEntry<'a>> {
+    millionaire::sort_stocks(&mut ordered, order, Some(player));
+    ordered.sort_by_key(|s| !player.is_watched(s.id()));
+    ordered.into_iter()
+        .map(|stock| StockEntry {
+            stock, watched: player.is_watched(stock.id()), show_id: show_ids,
+        })
+        .collect()
+}
+
 fn new_number(name: &str, default: Option<i32>) -> Result<i64, io::Error> {
     let suffix = match default {
         Some(s) => format!("(Default {}) ", s),
@@ -74,6 +262,15 @@ fn new_number(name: &str, default: Option<i32>) -> Result<i64, io::Error> {
     Ok(number_input(&format!("What will the new '{}' be? {}", name, suffix))? as i64)
 }
 
+fn new_range(name: &str) -> Result<StockRange, io::Error> {
+    Ok(StockRange::new(
+        new_number(&format!("{} minimum value", name), Some(10))?,
+        new_number(&format!("{} maximum value", name), Some(100))?,
+        new_number(&format!("{} minimum variation", name), Some(10))?,
+        new_number(&format!("{} maximum variation", name), Some(100))?,
+    ))
+}
+
 fn default_or_number(name: &str, default: &str) -> Result<Option<i64>, io::Error> {
     let options = ["New value", default];
     Ok(match *menu(&options, false)?.unwrap() {
@@ -82,136 +279,895 @@ fn default_or_number(name: &str, default: &str) -> Result<Option<i64>, io::Error
     })
 }
 
-fn net_worth_breakdown(player: &Player, stocks: &[Stock]) {
+fn net_worth_breakdown(game: &Game) {
+    let player = &game.player;
+    let stocks = &game.stocks;
     println!("---");
-    println!("Balance: {}", player.balance());
-    for s in stocks {
-        let value = s.value();
-        let stock_balance = player.stock_balance(s);
-        println!("Stock: '{}', Balance: {}, Value: {}, Worth: {}", s.name(), stock_balance,
-                 value, stock_balance * value);
-    }
-    println!("\nNet worth: {}", player.net_worth(stocks));
+    if player.locked_balance() > 0 {
+        println!("Balance: {} ({} available)", player.balance(), player.available_balance());
+    } else {
+        println!("Balance: {}", player.balance());
+    }
+    let turn_income = game.turn_income();
+    if turn_income != player.income() {
+        println!("Cash flow per turn: {} (income {})", turn_income, player.income());
+    } else {
+        println!("Cash flow per turn: {}", turn_income);
+    }
+    let mut listed = millionaire::watched_first(stocks, player);
+    let total = listed.len();
+    if game.hide_empty_holdings { listed = millionaire::held_only(listed, player); }
+    let rows: Vec<HoldingRow> = listed.into_iter()
+        .map(|s| {
+            let star = if player.is_watched(s.id()) { "★ " } else { "" };
+            let shares = player.stock_balance(s);
+            HoldingRow {
+                name: format!("{}{}", star, s.name()), shares, value: s.value(),
+                worth: shares * s.value(),
+            }
+        })
+        .collect();
+    print!("{}", millionaire::render_holdings_table(&rows));
+    if rows.len() < total {
+        println!("({} stocks you don't own aren't shown)", total - rows.len());
+    }
+    let net_worth = game.net_worth_cached();
+    let parts = player.net_worth_components(stocks);
+    println!("\n{} ({} cash + {} in stocks)", bold(format!("Net worth: {}", net_worth)),
+             parts.cash, parts.long_value);
+    let liquidation = player.net_liquidation_value(stocks, 0, game.spread_bps,
+                                                   game.rounding_mode);
+    if liquidation != net_worth {
+        println!("If you sold everything: {}", liquidation);
+    }
+    let crash = player.scenario_net_worth(stocks, -2000);
+    if crash != net_worth {
+        println!("If the market dropped 20%: {}", crash);
+    }
+    let book_value = player.book_value();
+    let unrealized = net_worth - book_value;
+    println!("Book value: {} (unrealized profit {})", book_value,
+             gain_or_loss(unrealized, format!("{:+}", unrealized)));
+    println!("Realized profit: {}", player.realized_pl());
+    let index = millionaire::market_index_history(stocks);
+    if let Some(beta) = player.portfolio_beta(stocks, &index) {
+        println!("Portfolio beta: {:.2}", beta);
+    }
+    let name_of = |id| stocks.iter().find(|s| s.id() == id).map_or("", |s| s.name());
+    if let (Some((best, best_pl)), Some((worst, worst_pl)))
+        = (player.best_holding(stocks), player.worst_holding(stocks)) {
+        println!("Best holding: '{}' ({})", name_of(best),
+                 gain_or_loss(best_pl, format!("{:+}", best_pl)));
+        println!("Worst holding: '{}' ({})", name_of(worst),
+                 gain_or_loss(worst_pl, format!("{:+}", worst_pl)));
+    }
+    if let Some(prev) = game.previous_net_worth {
+        let (change, percent) = millionaire::turn_pl(prev, net_worth);
+        let text = format!("{:+} ({})", change, millionaire::format_percent(percent, 2));
+        println!("Change since last turn: {}", gain_or_loss(change, text));
+    }
+    if game.sentiment_enabled {
+        println!("Market sentiment: {}", sentiment_gauge(game.sentiment));
+    }
+    match game.estimated_turns_to_goal() {
+        Some(0) => (),
+        Some(turns) => println!("Turns to reach the goal at this income: about {}", turns),
+        None => println!("You'll never reach the goal without any income."),
+    }
     println!("---");
 }
 
-fn run_game(mut game: Game, save_path: PathBuf) {
+/// Draws market sentiment as a bar that fills left of the middle when bearish and right
+/// of it when bullish.
+fn sentiment_gauge(sentiment: i64) -> String {
+    const HALF: i64 = 10;
+    let filled = sentiment * HALF / save::SENTIMENT_MAX;
+    let left: String = (-HALF..0).map(|i| if i >= filled { '-' } else { ' ' }).collect();
+    let right: String = (0..HALF).map(|i| if i < filled { '+' } else { ' ' }).collect();
+    let mood = match sentiment {
+        s if s > 0 => "bullish",
+        s if s < 0 => "bearish",
+        _ => "neutral",
+    };
+    format!("[{}|{}] {}", left, right, mood)
+}
+
+/// The variables the player can change before starting a game.
+#[derive(Clone, PartialEq)]
+struct Variables {
+    goal: i64,
+    income: i64,
+    initial_balance: Option<i64>,
+    add_stock_cost: i64,
+    starting_stocks: i64,
+    income_upgrade_cost: Option<i64>,
+    split_threshold: Option<i64>,
+    split_ratio: i64,
+    starting_stock_range: StockRange,
+    added_stock_range: StockRange,
+    confirm_end_turn: bool,
+    unique_stock_names: bool,
+    trending_starts: bool,
+    auto_upgrade_income: bool,
+    end_on_goal: bool,
+    autosave_interval: u32,
+    max_saves: Option<usize>,
+    slow_turn_minutes: Option<i64>,
+    observation_turns: u32,
+    min_trade: i64,
+    verbosity: Verbosity,
+    memorable_save_names: bool,
+}
+
+impl Default for Variables {
+    fn default() -> Self {
+        Self {
+            goal: 1_000_000,
+            income: 1000,
+            initial_balance: None,
+            add_stock_cost: save::DEFAULT_ADD_STOCK_COST,
+            starting_stocks: 3,
+            income_upgrade_cost: None,
+            split_threshold: None,
+            split_ratio: 2,
+            starting_stock_range: StockRange::default(),
+            added_stock_range: StockRange::default(),
+            confirm_end_turn: false,
+            unique_stock_names: true,
+            trending_starts: false,
+            auto_upgrade_income: false,
+            end_on_goal: true,
+            autosave_interval: 1,
+            max_saves: None,
+            slow_turn_minutes: None,
+            observation_turns: 0,
+            min_trade: 1,
+            verbosity: Verbosity::default(),
+            memorable_save_names: false,
+        }
+    }
+}
+
+impl Variables {
+    /// Where a new game should be saved.
+    fn new_save_path(&self, dir: Option<&Path>) -> Result<PathBuf, Error> {
+        if self.memorable_save_names {
+            // A separate random source, so naming the save doesn't change the game
+            save::make_named_path(dir, &save::generate_save_name(&mut rand::thread_rng()), None)
+        } else {
+            save::make_path(dir, None)
+        }
+    }
+
+    fn validate(&self) -> Result<(), Vec<String>> {
+        save::validate_variables(self.goal, self.income, self.initial_balance,
+                                 self.starting_stocks, self.split_threshold, self.split_ratio,
+                                 self.starting_stock_range, self.added_stock_range)
+    }
+
+    fn start_direction_bps(&self) -> i64 {
+        if self.trending_starts { save::TRENDING_START_BPS } else { 0 }
+    }
+
+    fn new_game(&self, stocks: Vec<Stock>, seed: u64) -> Game {
+        let mut game = Game::new(self.goal, self.income, stocks);
+        game.seed = seed;
+        game.add_stock_cost = self.add_stock_cost;
+        game.initial_balance = self.initial_balance;
+        game.reset_player();
+        if let Some(cost) = self.income_upgrade_cost {
+            game.income_upgrade_cost = cost;
+        }
+        game.split_threshold = self.split_threshold;
+        game.split_ratio = self.split_ratio;
+        game.starting_stock_range = self.starting_stock_range;
+        game.added_stock_range = self.added_stock_range;
+        game.confirm_end_turn = self.confirm_end_turn;
+        game.unique_stock_names = self.unique_stock_names;
+        game.start_direction_bps = self.start_direction_bps();
+        game.autosave_interval = self.autosave_interval;
+        game.max_saves = self.max_saves;
+        game.slow_turn_minutes = self.slow_turn_minutes;
+        game.auto_upgrade_income = self.auto_upgrade_income;
+        game.observation_turns = self.observation_turns;
+        game.min_trade = self.min_trade;
+        game.verbosity = self.verbosity;
+        game.end_on_goal = self.end_on_goal;
+        game
+    }
+}
+
+/// How many variable edits can be undone.
+const VARIABLE_UNDO_LEN: usize = 10;
+
+/// The values the variables had before each recent edit, newest last.
+#[derive(Default)]
+struct VariableHistory {
+    previous: Vec<Variables>,
+}
+
+impl VariableHistory {
+    /// Remembers `before` if an edit changed it into `after`, forgetting the oldest edit
+    /// past `VARIABLE_UNDO_LEN`.
+    fn record(&mut self, before: Variables, after: &Variables) {
+        if before == *after { return; }
+        self.previous.push(before);
+        if self.previous.len() > VARIABLE_UNDO_LEN {
+            self.previous.remove(0);
+        }
+    }
+
+    /// Restores the variables to before the last edit. Returns false if there's nothing
+    /// to undo.
+    fn undo(&mut self, vars: &mut Variables) -> bool {
+        match self.previous.pop() {
+            Some(previous) => { *vars = previous; true }
+            None => false,
+        }
+    }
+}
+
+fn seed_input(prompt: &str) -> Result<Option<u64>, io::Error> {
+    loop {
+        print!("{}", prompt); io::stdout().flush()?;
+        let mut choice = String::new();
+        io::stdin().read_line(&mut choice)?;
+        let choice = choice.trim();
+
+        if choice.is_empty() { return Ok(None); }
+        match choice.parse() {
+            Ok(seed) => return Ok(Some(seed)),
+            Err(_) => println!("`{}` was not a valid seed!\n", choice),
+        }
+    }
+}
+
+fn print_outcome(outcome: &TickOutcome, verbosity: Verbosity) {
+    for notification in save::filter_notifications(outcome.notifications(), verbosity) {
+        println!("{}", notification.text);
+    }
+}
+
+fn print_summary(game: &Game) {
+    let stats = game.summary_stats();
+    println!("Turns played: {}", stats.turns_played);
+    println!("Final net worth: {}", stats.final_net_worth);
+    println!("Peak net worth: {}", stats.peak_net_worth);
+    println!("Largest drop from a peak: {} ({})", stats.max_drawdown,
+             millionaire::format_percent(-stats.max_drawdown_bps as f64 / 100.0, 2));
+    println!("Realized profit: {}", stats.realized_pl);
+    println!("Trades made: {}", stats.trades);
+    if let (Some(best), Some(worst)) = (stats.best_trade, stats.worst_trade) {
+        println!("Best sale: {}", gain_or_loss(best, format!("{:+}", best)));
+        println!("Worst sale: {}", gain_or_loss(worst, format!("{:+}", worst)));
+    }
+    if stats.longest_turn_minutes > 0 {
+        println!("Longest turn: {} minutes", stats.longest_turn_minutes);
+    }
+    println!("{}", stats.share_line());
+}
+
+fn print_diff(diff: &GameDiff) {
+    println!("Balance: {:+}", diff.balance);
+    println!("Income: {:+}", diff.income);
+    println!("Net worth: {:+}", diff.net_worth);
+    for s in &diff.stocks {
+        match (s.shares, s.value) {
+            ((Some(shares_a), Some(shares_b)), (Some(value_a), Some(value_b))) => {
+                println!("Stock '{}': Shares: {:+}, Value: {:+}", s.name, shares_b - shares_a,
+                         value_b - value_a);
+            }
+            (_, (None, _)) => println!("Stock '{}' was added.", s.name),
+            _ => println!("Stock '{}' was removed.", s.name),
+        }
+    }
+}
+
+/// How many gainers and losers the market movers panel shows.
+const MARKET_MOVERS: usize = 3;
+
+fn run_game(mut game: Game, mut save_path: PathBuf, rng: &mut GameRng) {
     let mut run_game = true;
+    if env::args().any(|a| a == "--express") { game.express_mode = true; }
                 
     let options = ["Buy stocks", "Sell stocks", "Increase income",
-                    "Add a new stock", "Print net worth breakdown", 
-                    "End turn", "Quit game"];
+                    "Add a new stock", "Print net worth breakdown",
+                    "Copy breakdown (Markdown)", "Toggle rebuy after bankruptcy",
+                    "Toggle watchlist", "Toggle stock ids", "Toggle hints",
+                    "Toggle express mode", "Toggle empty holdings", "Sort stocks",
+                    "Plan a purchase", "Add a recurring buy", "Remove a recurring buy",
+                    "Add a net worth alert", "Remove a net worth alert",
+                    "View trade history", "Market movers", "Market ownership",
+                    "Add journal note", "View journal", "Rename this game",
+                    "Reset my portfolio", "End turn", "Quit game"];
 
-    while run_game {
-        save::save(&save_path, &game).unwrap();
+    let mut locked = match save::try_lock(&save_path) {
+        Ok(()) => true,
+        Err(Error::Locked(_)) => {
+            println!("This save looks like it's open in another window. Playing it in both \
+                      will make them overwrite each other's progress.");
+            if !double_check("Open it anyway?", false).expect("IO Error") { return; }
+            false
+        }
+        Err(_) => false,
+    };
+
+    if !game.news.is_empty() {
+        println!("Recent news:");
+        for headline in &game.news {
+            println!("  {}", headline);
+        }
+        println!();
+    }
+
+    for b in game.process_bankruptcies() {
+        println!("Stock '{}' went bankrupt!", b.name);
+    }
 
-        for s in game.stocks.iter_mut() {
-            if s.value() <= 0 {
-                println!("Stock '{}' went bankrupt!", s.name());
-                s.reset();
-                game.player.reset_stock(s);
+    if game.observation_turns > 0 {
+        println!("Watch the market for {} turns before you start trading.",
+                 game.observation_turns);
+        let mut observed = 0;
+        while let Some(bankruptcies) = game.observe_turn(rng) {
+            observed += 1;
+            println!("\nWatching, turn {}:", observed);
+            for b in bankruptcies {
+                println!("Stock '{}' went bankrupt!", b.name);
             }
+            for s in &game.stocks {
+                println!("  {}: {} ({})", s.name(), s.value(),
+                         millionaire::format_percent(s.last_change_bps() as f64 / 100.0, 2));
+            }
+        }
+        println!();
+    }
+
+    // The turn the save on disk is from. A new game isn't on disk yet, so it's saved
+    // straight away even if an autosave isn't due.
+    let mut saved_turn = None;
+    if save_path.exists() { saved_turn = Some(game.turn); }
+
+    while run_game {
+        let turn_started = Local::now();
+        if game.autosave_due() || saved_turn.is_none() {
+            game.store_rng(rng);
+            save::save(&save_path, &game).unwrap();
+            saved_turn = Some(game.turn);
         }
 
         let mut breakdown_printed = false;
-        if game.player.net_worth(&game.stocks) > game.goal {
-            net_worth_breakdown(&game.player, &game.stocks);
+        if game.has_won() && game.end_on_goal {
+            net_worth_breakdown(&game);
             println!("You win!");
+            print_summary(&game);
+            break;
+        }
+        if game.has_won() && !game.goal_reached {
+            game.goal_reached = true;
+            println!("You reached the goal! Keep playing to build even more wealth.");
+            print_summary(&game);
+        }
+        if game.is_bust() {
+            net_worth_breakdown(&game);
+            println!("You went bust!");
+            print_summary(&game);
             break;
         }
+        if let Some(name) = game.ensure_market(rng) {
+            println!("There were no stocks on the market, so '{}' was listed.", name);
+        }
+        if game.loss_circuit_tripped() {
+            net_worth_breakdown(&game);
+            println!("You lost a lot of money last turn. Take a moment to look over your \
+                      portfolio before carrying on.");
+            if !double_check("Keep playing?", true).expect("IO Error") { break; }
+        }
 
         loop {
             println!();
             if !breakdown_printed {
-                net_worth_breakdown(&game.player, &game.stocks);
+                println!("Seed: {}", game.seed);
+                net_worth_breakdown(&game);
+                if game.tutorial {
+                    for hint in save::tutorial_hints(&game) { println!("Tutorial: {}", hint); }
+                } else if game.show_hints {
+                    for hint in save::hints(&game) { println!("Hint: {}", hint); }
+                }
                 breakdown_printed = true;
             } else {
                 println!("Balance: {}\n", game.player.balance());
             }
+            if let Some(left) = game.actions_left() {
+                println!("Actions left this turn: {}", left);
+            }
 
-            let choice = *menu(&options, false).expect("IO error").unwrap();
+            let mut entries: Vec<MenuOption> = options.iter().map(|o| MenuOption::new(o)).collect();
+            for e in entries.iter_mut() {
+                if e.label == "Increase income"
+                    && !game.player.can_increase_income(game.income_upgrade_cost) {
+                    e.note = Some(format!("need ${} more",
+                                          game.player.shortfall(game.income_upgrade_cost)));
+                }
+            }
+
+            let choice = menu(&entries, false).expect("IO error").unwrap().label;
             println!();
-                    
+
             match choice {
                 "Buy stocks" => {
-                    if let Some(stock) = menu(&game.stocks, true).expect("IO error") {
+                    let filters = ["All stocks", "Only stocks I can afford"];
+                    let stocks = if *menu(&filters, false).expect("IO Error").unwrap()
+                        == filters[0] {
+                        game.stocks.iter().collect()
+                    } else {
+                        game.player.affordable_stocks(&game.stocks)
+                    };
+                    if stocks.is_empty() {
+                        println!("You can't afford any stocks.");
+                        continue;
+                    }
+                    if let Some(stock) = stock_ref_menu(stocks, &game.player,
+                                                        game.show_stock_ids, game.stock_order)
+                        .expect("IO error") {
+                        if stock.is_halted() {
+                            println!("Trading in '{}' is halted this turn.", stock.name());
+                            continue;
+                        }
+                        if game.on_cooldown(stock.id()) {
+                            println!("You already traded '{}' this turn.", stock.name());
+                            continue;
+                        }
+                        let id = stock.id();
+                        let price = game.buy_price(stock);
+                        if game.next_trade_free() && game.spread_bps > 0 {
+                            println!("This trade is free of the spread.");
+                        }
+                        let max = game.round_to_lot(game.player.available_balance() / price);
+                        if game.min_trade > 1 {
+                            println!("Shares trade in lots of {}.", game.min_trade);
+                        }
                         let prompt = format!(
-                                "How much stock would you like to buy? (Max: {}) ",
-                                game.player.balance() / stock.value());
-                        let amount = number_input(&prompt)
+                                "How much stock would you like to buy at {}? (Max: {}) ",
+                                price, max);
+                        let amount = number_input_range(&prompt, 0, max as usize)
                             .expect("IO Error");
-                        if let Err(()) = game.player.buy_stock(stock, amount as i64) {
-                            println!("You could not afford that much stock.");
+                        if amount == 0 { continue; }
+                        let action = Action::Buy { stock_id: id, amount: amount as i64 };
+                        match game.apply_action(action, rng) {
+                            ActionResult::Failed(TransactionError::ActionBudgetExceeded) => {
+                                println!("You have no actions left this turn.");
+                            }
+                            ActionResult::Failed(TransactionError::BelowMinTrade) => {
+                                println!("You can only buy whole lots of {}.", game.min_trade);
+                            }
+                            ActionResult::Failed(_) => {
+                                println!("You could not afford that much stock.");
+                            }
+                            _ => (),
                         }
                     }
                 }
                 "Sell stocks" => {
-                    if let Some(stock) = menu(&game.stocks, true).expect("IO error") {
-                        let prompt = format!(
-                                "How much stock would you like to sell? (Max: {}) ",
-                                game.player.stock_balance(stock));
-                        let amount = number_input(&prompt)
-                            .expect("IO Error");
-                        if let Err(()) = game.player.sell_stock(stock, amount as i64) {
-                            println!("You do not have enough stock.");
-                    }
+                    if let Some(stock) = stock_menu(&game.stocks, &game.player,
+                                                    game.show_stock_ids, game.stock_order)
+                        .expect("IO error") {
+                        if stock.is_halted() {
+                            println!("Trading in '{}' is halted this turn.", stock.name());
+                            continue;
+                        }
+                        if game.on_cooldown(stock.id()) {
+                            println!("You already traded '{}' this turn.", stock.name());
+                            continue;
+                        }
+                        let id = stock.id();
+                        let price = game.sell_price(stock);
+                        if game.next_trade_free() && game.spread_bps > 0 {
+                            println!("This trade is free of the spread.");
+                        }
+                        let max = game.round_to_lot(game.sellable_shares(id));
+                        if game.sellable_shares(id) < game.player.stock_balance(stock) {
+                            println!("Some of your shares were bought too recently to sell.");
+                        }
+                        if game.min_trade > 1 {
+                            println!("Shares trade in lots of {}.", game.min_trade);
+                        }
+                        let modes = ["Sell an exact amount", "Sell up to an amount"];
+                        let amount = if *menu(&modes, false).expect("IO Error").unwrap()
+                            == modes[0] {
+                            let prompt = format!(
+                                    "How much stock would you like to sell at {}? (Max: {}) ",
+                                    price, max);
+                            number_input_range(&prompt, 0, max as usize).expect("IO Error")
+                        } else {
+                            let prompt = format!(
+                                    "How much stock would you like to sell at {}? (Start with \
+                                     $ to sell an amount of money's worth) ", price);
+                            let wanted = match sell_input(&prompt).expect("IO Error") {
+                                SellInput::Shares(n) => n,
+                                SellInput::Money(money) => {
+                                    game.player.shares_worth(id, price, money)
+                                }
+                            };
+                            game.round_to_lot(wanted.min(max)) as usize
+                        };
+                        if amount == 0 { continue; }
+                        let action = Action::Sell { stock_id: id, amount: amount as i64 };
+                        match game.apply_action(action, rng) {
+                            ActionResult::Failed(TransactionError::ActionBudgetExceeded) => {
+                                println!("You have no actions left this turn.");
+                            }
+                            ActionResult::Failed(TransactionError::BelowMinTrade) => {
+                                println!("You can only sell whole lots of {}.", game.min_trade);
+                            }
+                            ActionResult::Failed(_) => println!("You do not have enough stock."),
+                            _ => println!("Sold {} shares.", amount),
+                        }
                     }
                 }
                 "Increase income" => {
-                    println!("An income increase costs {}.", game.income_upgrade_cost);
-                    if double_check(
+                    let cost = game.income_upgrade_cost;
+                    println!("An income increase costs {}.", cost);
+                    if let Some(turns) = game.player.turns_to_afford(cost) {
+                        println!("That's {} turns of income.", turns);
+                    }
+                    if let Some(turns) = game.player.income_upgrade_payback(cost) {
+                        println!("It pays for itself after {} turns.", turns);
+                    }
+                    if !game.should_confirm(Confirmation::IncreaseIncome) || double_check(
                         "Are you sure you want to increase your income?", true
                     ).expect("IO Error") {
-                        if let Err(()) = game.player.increase_income(game.income_upgrade_cost) {
-                            println!("You couldn't afford an income increase.");
+                        match game.apply_action(Action::IncreaseIncome, rng) {
+                            ActionResult::Failed(TransactionError::ActionBudgetExceeded) => {
+                                println!("You have no actions left this turn.");
+                            }
+                            ActionResult::Failed(_) => {
+                                println!("You couldn't afford an income increase.");
+                            }
+                            _ => (),
                         }
                     }
                 }
                 "Add a new stock" => {
-                    println!("Adding a new stock costs {}", game.add_stock_cost);
-                    if double_check(
+                    let cost = game.current_add_stock_cost();
+                    println!("Adding a new stock costs {}", cost);
+                    if !game.should_confirm(Confirmation::AddStock) || double_check(
                         "Are you sure you want to unlock a new stock?", true
                     ).expect("IO error") {
-                        if let Err(()) = game.player.withdraw(game.add_stock_cost) {
-                            println!("You couldn't afford a new stock.");
-                        } else {
-                            let name = millionaire::generate_name();
-                            let stock = millionaire::generate_stock(
-                                game.stocks.len() as i64, 10, 100, 10, 100, name);
-                            game.stocks.push(stock);
+                        match game.apply_action(Action::AddStock, rng) {
+                            ActionResult::Failed(TransactionError::MarketFull) => {
+                                println!("The market can't hold any more stocks.");
+                            }
+                            ActionResult::Failed(_) => println!("You couldn't afford a new stock."),
+                            _ => (),
                         }
                     }
                 }
                 "Print net worth breakdown" => { 
-                    net_worth_breakdown(&game.player, &game.stocks);
+                    net_worth_breakdown(&game);
+                }
+                "Copy breakdown (Markdown)" => {
+                    println!("{}", millionaire::breakdown_markdown(&game.player, &game.stocks));
+                }
+                "Toggle rebuy after bankruptcy" => {
+                    if let Some(stock) = stock_menu(&game.stocks, &game.player,
+                                                    game.show_stock_ids, game.stock_order)
+                        .expect("IO error") {
+                        if game.player.toggle_rebuy_after_bankruptcy(stock) {
+                            println!("'{}' will be bought back if it goes bankrupt.",
+                                     stock.name());
+                        } else {
+                            println!("'{}' will no longer be bought back.", stock.name());
+                        }
+                    }
+                }
+                "Toggle watchlist" => {
+                    if let Some(stock) = stock_menu(&game.stocks, &game.player,
+                                                    game.show_stock_ids, game.stock_order)
+                        .expect("IO error") {
+                        let id = stock.id();
+                        if game.player.toggle_watch(id) {
+                            println!("'{}' is now on your watchlist.", stock.name());
+                        } else {
+                            println!("'{}' is no longer on your watchlist.", stock.name());
+                        }
+                    }
+                }
+                "Plan a purchase" => {
+                    if let Some(stock) = stock_menu(&game.stocks, &game.player,
+                                                    game.show_stock_ids, game.stock_order)
+                        .expect("IO error") {
+                        let future_price = number_input(&format!(
+                            "What price do you expect '{}' to reach? ", stock.name()))
+                            .expect("IO Error") as i64;
+                        let held = game.player.stock_balance(stock);
+                        let others = game.player.net_worth(&game.stocks)
+                            - game.player.balance() - held * stock.value();
+                        // Winning takes a net worth above the goal, not at it.
+                        let target = game.goal + 1 - others;
+                        match game.player.shares_for_target(stock, future_price, target) {
+                            Some(0) => println!("You'd reach the goal without buying any."),
+                            Some(shares) => println!(
+                                "Buying {} shares now would reach the goal at that price.",
+                                shares),
+                            None => println!("You can't afford enough shares to reach the \
+                                              goal at that price."),
+                        }
+                    }
+                }
+                "Add a net worth alert" => {
+                    let level = number_input("Alert me when my net worth passes: ")
+                        .expect("IO Error") as i64;
+                    if !game.alerts.contains(&level) {
+                        game.alerts.push(level);
+                        game.alerts.sort_unstable();
+                    }
+                }
+                "Remove a net worth alert" => {
+                    if game.alerts.is_empty() {
+                        println!("You have no net worth alerts.");
+                    } else if let Some(&level) = menu(&game.alerts, true).expect("IO Error") {
+                        game.alerts.retain(|a| *a != level);
+                    }
+                }
+                "View trade history" => {
+                    if let Some(stock) = stock_menu(&game.stocks, &game.player,
+                                                    game.show_stock_ids, game.stock_order)
+                        .expect("IO error") {
+                        let trades = game.player.trades_for(stock.id());
+                        if trades.is_empty() {
+                            println!("You haven't traded '{}'.", stock.name());
+                        }
+                        for t in trades {
+                            let action = if t.amount > 0 { "Bought" } else { "Sold" };
+                            println!("Turn {}: {} {} shares at {}", t.turn, action,
+                                     t.amount.abs(), t.price);
+                        }
+                    }
+                }
+                "Market movers" => {
+                    let (gainers, losers) = game.top_movers(MARKET_MOVERS);
+                    if gainers.is_empty() && losers.is_empty() {
+                        println!("Nothing moved last turn.");
+                    }
+                    let lists = [("Biggest gainers:", gainers), ("Biggest losers:", losers)];
+                    for (title, stocks) in lists {
+                        if stocks.is_empty() { continue; }
+                        println!("{}", title);
+                        for s in stocks {
+                            let change = s.last_change_bps();
+                            let percent = millionaire::format_percent(change as f64 / 100.0, 2);
+                            println!("  {}: {} ({})", s.name(), s.value(),
+                                     gain_or_loss(change, percent));
+                        }
+                    }
+                }
+                "Market ownership" => {
+                    for s in millionaire::sorted_by_id(&game.stocks) {
+                        let owners: Vec<String> = game.ownership_of(s.id()).into_iter()
+                            .filter(|(_, held)| *held > 0)
+                            .map(|(player, held)| format!("player {} holds {}", player + 1, held))
+                            .collect();
+                        let held = game.player.stock_balance(s);
+                        if owners.is_empty() {
+                            println!("{}: nobody", s.name());
+                        } else {
+                            let control = game.control_threshold.is_some_and(|t| held > t);
+                            println!("{}: {}{}", s.name(), owners.join(", "),
+                                     if control { " (controlling stake)" } else { "" });
+                        }
+                        if let Some(threshold) = game.control_threshold {
+                            if held <= threshold {
+                                // Buying pays half the spread on top of the value
+                                let cost = s.cost_to_reach(held, threshold + 1,
+                                                           game.spread_bps / 2);
+                                println!("  A controlling stake needs {} more shares, costing \
+                                          about {}.", threshold + 1 - held, cost);
+                            }
+                        }
+                    }
+                }
+                "Add journal note" => {
+                    let mut note = String::new();
+                    print!("Note: ");
+                    io::stdout().flush().expect("IO Error");
+                    io::stdin().read_line(&mut note).expect("IO Error");
+                    let note = note.trim();
+                    if !note.is_empty() { game.add_journal_note(note.to_string()); }
+                }
+                "View journal" => {
+                    if game.journal.is_empty() {
+                        println!("Your journal is empty.");
+                    }
+                    for (turn, note) in &game.journal {
+                        println!("Turn {}: {}", turn, note);
+                    }
+                }
+                "Rename this game" => {
+                    let mut new_name = String::new();
+                    print!("What will the new name of the game be? ");
+                    io::stdout().flush().expect("IO Error");
+                    io::stdin().read_line(&mut new_name).expect("IO Error");
+
+                    match save::rename(&save_path, &new_name, None) {
+                        Ok(new_path) => {
+                            if locked && save::unlock(&save_path).is_err() {
+                                println!("Couldn't remove the old lock on this save.");
+                            }
+                            save_path = new_path;
+                            locked = save::try_lock(&save_path).is_ok();
+                            println!("Game renamed!");
+                        }
+                        Err(save::Error::AlreadyExists) => {
+                            println!("A save with the same name already exists!");
+                        }
+                        Err(save::Error::EmptyFileName) => {
+                            println!("That filename was empty.");
+                        }
+                        Err(_) => {
+                            println!("Issue renaming the file.");
+                        }
+                    }
+                }
+                "Toggle stock ids" => {
+                    game.show_stock_ids = !game.show_stock_ids;
+                    println!("Stock ids will {}be shown in stock lists.",
+                             if game.show_stock_ids { "" } else { "not " });
+                }
+                "Toggle hints" => {
+                    game.show_hints = !game.show_hints;
+                    println!("Hints will {}be shown.", if game.show_hints { "" } else { "not " });
+                }
+                "Toggle express mode" => {
+                    game.express_mode = !game.express_mode;
+                    println!("Increasing income and adding stocks will {}ask first.",
+                             if game.express_mode { "not " } else { "" });
+                }
+                "Toggle empty holdings" => {
+                    game.hide_empty_holdings = !game.hide_empty_holdings;
+                    println!("Stocks you don't own will {}be shown in the breakdown.",
+                             if game.hide_empty_holdings { "not " } else { "" });
+                }
+                "Sort stocks" => {
+                    let keys = [("By id", SortKey::Id), ("By value", SortKey::Value),
+                                ("By variation", SortKey::Variation),
+                                ("By holding size", SortKey::HoldingSize),
+                                ("By change last turn", SortKey::PercentChange)];
+                    let labels: Vec<&str> = keys.iter().map(|(label, _)| *label).collect();
+                    if let Some(label) = menu(&labels, true).expect("IO error") {
+                        let key = keys.iter().find(|(l, _)| l == label).unwrap().1;
+                        game.stock_order = key;
+                    }
+                }
+                "Add a recurring buy" => {
+                    if let Some(stock) = stock_menu(&game.stocks, &game.player,
+                                                    game.show_stock_ids, game.stock_order)
+                        .expect("IO error") {
+                        let money = number_input(&format!(
+                                "How much money should be spent on '{}' every turn? ",
+                                stock.name())).expect("IO Error");
+                        let id = stock.id();
+                        game.player.add_recurring_buy(id, money as i64);
+                    }
+                }
+                "Remove a recurring buy" => {
+                    let buys: Vec<String> = game.player.recurring_buys().iter()
+                        .map(|(id, money)| match game.stock_by_id(*id) {
+                            Some(stock) => format!("{} every turn on '{}'", money, stock.name()),
+                            None => format!("{} every turn on delisted stock #{}", money, id),
+                        })
+                        .collect();
+                    if buys.is_empty() {
+                        println!("You have no recurring buys.");
+                    } else if let Some(buy) = menu(&buys, true).expect("IO error") {
+                        let pos = buys.iter().position(|b| b == buy).unwrap();
+                        let id = game.player.recurring_buys()[pos].0;
+                        game.player.remove_recurring_buy(id);
+                    }
+                }
+                "Reset my portfolio" => {
+                    if !game.should_confirm(Confirmation::ResetPortfolio) || double_check(
+                        "Are you sure you want to start your portfolio over?", false
+                    ).expect("IO Error") {
+                        game.reset_player();
+                        println!("Your portfolio was reset.");
+                    }
                 }
                 "End turn" => { 
-                    game.player.collect_income();
+                    if game.should_confirm(Confirmation::EndTurn) {
+                        net_worth_breakdown(&game);
+                        if !double_check("Are you sure you want to end your turn?", true)
+                            .expect("IO Error") {
+                            continue;
+                        }
+                    }
+                    if let ActionResult::TurnEnded(outcome)
+                        = game.apply_action(Action::EndTurn, rng) {
+                        print_outcome(&outcome, game.verbosity);
+                    }
+                    if let Some(minutes) = game.record_turn_time(turn_started, Local::now()) {
+                        println!("You spent {} minutes on that turn.", minutes);
+                    }
                     break; 
                 }
                 "Quit game" => {
-                    if double_check("Are you sure you want to end the game?", 
-                                    false).expect("IO Error") {
-                        run_game = false;
-                        break;
+                    // The game is saved at the start of a turn every `autosave_interval`
+                    // turns, so quitting without saving goes back to the last of those
+                    let unsaved_turns = game.turn - saved_turn.unwrap_or(game.turn);
+                    let warning = if unsaved_turns > 0 {
+                        format!("The last save is {} turn(s) old, and everything since then \
+                                 will be lost. Quit anyway?", unsaved_turns)
+                    } else {
+                        "Any changes this turn will be lost. Quit anyway?".to_string()
+                    };
+                    let choices = ["Save and quit", "Quit without saving"];
+                    match menu(&choices, true).expect("IO Error") {
+                        Some(&"Save and quit") => {
+                            game.store_rng(rng);
+                            if save::save(&save_path, &game).is_err() {
+                                println!("There was an error saving the game!");
+                                continue;
+                            }
+                        }
+                        Some(_) => {
+                            if !double_check(&warning, false).expect("IO Error") {
+                                continue;
+                            }
+                            if save::revert(&save_path, &mut game).is_ok() {
+                                *rng = game.rng();
+                            }
+                        }
+                        None => continue,
                     }
+                    run_game = false;
+                    break;
                 }
                 _ => { panic!("unreachable arm in game loop"); }
             }
         }
+    }
+    if locked && save::unlock(&save_path).is_err() {
+        println!("The save's lock file couldn't be removed.");
+    }
+    println!();
+}
 
-        for s in game.stocks.iter_mut() {
-            s.vary();
+fn play_save(path: &Path) {
+    match save::from_path(path) {
+        Ok(g) => {
+            let mut rng = g.rng();
+            run_game(g, path.to_path_buf(), &mut rng);
         }
+        Err(_) => println!("That save couldn't be loaded."),
+    }
+}
+
+fn continue_last_game(dir: Option<&Path>) {
+    match save::most_recent(dir, None) {
+        Ok(Some(save)) => play_save(&save.path),
+        Ok(None) => println!("There are no saved games."),
+        Err(_) => println!("The saved games couldn't be read."),
     }
-    println!();
 }
 
 fn main() {
+    let color = if env::args().any(|a| a == "--color") {
+        true
+    } else if env::args().any(|a| a == "--no-color") {
+        false
+    } else {
+        io::stdout().is_terminal() && env::var_os("NO_COLOR").is_none()
+    };
+    COLOR.store(color, Ordering::Relaxed);
+
+    if env::args().any(|a| a == "--dump-schema") {
+        println!("{}", save::schema().expect("Couldn't describe the save format"));
+        return;
+    }
+
     let path = None;
     
     loop {
-        match save::saves_in_folder(path) {
+        match save::saves_in_folder(path, None) {
             Ok(_) => {
                 break;
             }
@@ -224,6 +1180,10 @@ fn main() {
                     }
                 }
             }
+            Err(Error::NotADirectory(p)) => {
+                eprintln!("The save folder '{}' is a file, not a folder.", p.display());
+                process::exit(1);
+            }
             Err(Error::PlatformNotSupported) => {
                 eprintln!("A save folder cannot be found for this platform.");
                 process::exit(1);
@@ -232,85 +1192,125 @@ fn main() {
         }
     }
 
-    let mut goal = 1_000_000;
-    let mut income = 1000;
-    let mut initial_balance: Option<i64> = None;
-    let mut add_stock_cost = 15000;
-    let mut starting_stocks = 3;
-    let mut income_upgrade_cost: Option<i64> = None;
+    if save::remove_stale_locks(path, None).is_err() {
+        println!("Old lock files couldn't be cleaned up.");
+    }
+
+    if env::args().any(|a| a == "--continue") {
+        continue_last_game(path);
+    }
+
+    let mut vars = Variables::default();
+    let mut var_history = VariableHistory::default();
+    let mut seed: Option<u64> = None;
 
     loop {
-        let options = ["Play game!", "Load save", "Manage saves", "Edit variables", "Quit"];
+        let options = ["Continue", "Play game!", "Tutorial", "Start from stock file",
+                       "Load save", "Manage saves", "Import saves", "Edit variables",
+                       "Set seed", "Quit"];
         
         let choice = *menu(&options, false).expect("IO error").unwrap();
         println!();
 
         match choice {
             "Play game!" => {
+                let seed = seed.take().unwrap_or_else(rand::random);
+                let mut rng = GameRng::seed_from_u64(seed);
                 let mut stocks = Vec::new();
 
-                for _ in 0..starting_stocks {
-                    let name = millionaire::generate_name();
-                    let stock = millionaire::generate_stock(stocks.len() as i64, 10, 100, 
-                                                            10, 100, name);
+                for _ in 0..vars.starting_stocks {
+                    let name = if vars.unique_stock_names {
+                        millionaire::generate_unique_name(&mut rng, &stocks)
+                    } else {
+                        millionaire::generate_name(&mut rng)
+                    };
+                    let stock = vars.starting_stock_range.generate_trending(
+                        &mut rng, stocks.len() as i64, name, vars.start_direction_bps());
                     stocks.push(stock);
                 }
 
-                run_game(Game {
-                    stocks,
-                    player: Player::new(
-                        match initial_balance {
-                            Some(i) => i,
-                            None => income,
-                        }, 
-                        income
-                    ),
-                    goal,
-                    initial_income: income,
-                    add_stock_cost,
-                    income_upgrade_cost: match income_upgrade_cost {
-                        Some(i) => i,
-                        None => income * 10,
-                    }
-                },
-                save::make_path(path).unwrap());
+                let mut game = vars.new_game(stocks, seed);
+                let (score, label) = game.difficulty_score();
+                println!("Difficulty: {} ({:.1})", label, score);
+                loop {
+                    println!("The market:");
+                    for s in &game.stocks {
+                        println!("  {}: {}", s.name(), s.value());
+                    }
+                    let choices = ["Start playing", "Re-roll market"];
+                    if *menu(&choices, false).expect("IO Error").unwrap() == choices[0] { break; }
+                    game.reroll_market();
+                    println!();
+                }
+                run_game(game, vars.new_save_path(path).unwrap(), &mut rng);
+            }
+            "Tutorial" => {
+                let seed = seed.take().unwrap_or_else(rand::random);
+                let mut rng = GameRng::seed_from_u64(seed);
+                let game = save::tutorial_game(&mut rng, seed);
+                run_game(game, vars.new_save_path(path).unwrap(), &mut rng);
             }
+            "Start from stock file" => {
+                let mut stock_path = String::new();
+                print!("Where is the stock file? ");
+                io::stdout().flush().expect("IO Error");
+                io::stdin().read_line(&mut stock_path).expect("IO Error");
+
+                match save::load_stock_defs(Path::new(stock_path.trim())) {
+                    Ok(stocks) if stocks.is_empty() => {
+                        println!("That stock file doesn't define any stocks.");
+                    }
+                    Ok(stocks) => {
+                        let seed = seed.take().unwrap_or_else(rand::random);
+                        run_game(vars.new_game(stocks, seed), vars.new_save_path(path).unwrap(),
+                                 &mut GameRng::seed_from_u64(seed));
+                    }
+                    Err(Error::InvalidStockDef(idx, e)) => {
+                        println!("Stock #{} in that file is malformed: {}", idx + 1, e);
+                    }
+                    Err(Error::InvalidPriceSeries(series, line)) => {
+                        println!("Line {} of the price series {} isn't a valid price.",
+                                 line, series.display());
+                    }
+                    Err(_) => {
+                        println!("That stock file couldn't be read.");
+                    }
+                }
+            }
+            "Continue" => continue_last_game(path),
             "Load save" => {
                 // Safe unwrap because we verified this function works eariler
-                let saves = save::saves_in_folder(path).unwrap();
-                if saves.len() == 0 {
+                let mut saves = save::saves_in_folder(path, None).unwrap();
+                save::favorites_first(&mut saves);
+                if saves.is_empty() {
                     println!("There are no saved games.");
                 } else {
-                    let save = menu(&saves, true).expect("IO Error");
+                    let save = paged_menu(&saves, true, MENU_PAGE_SIZE).expect("IO Error");
                     if let Some(save) = save {
-                        let path = &save.path;
-                        match save::from_path(path) {
-                            Ok(g) => {
-                                run_game(g, path.to_path_buf());
-                            }
-                            Err(_e) => panic!(),
-                        }
+                        play_save(&save.path);
                     }
                 }
             },
             "Manage saves" => {
                 // Safe unwrap because we verified this function works eariler
-                let saves = save::saves_in_folder(path).unwrap();
-                if saves.len() == 0 {
+                let saves = save::saves_in_folder(path, None).unwrap();
+                if saves.is_empty() {
                     println!("There are no saved games.");
                 } else {
-                    let save = menu(&saves, true).expect("IO Error");
+                    let save = paged_menu(&saves, true, MENU_PAGE_SIZE).expect("IO Error");
                     if let Some(save) = save {
-                        let options = ["Copy save", "Delete save", "Rename save"];
+                        let options = ["Copy save", "Delete save", "Rename save",
+                                       "Toggle favorite", "Restore from backup",
+                                       "Compare with another save"];
                         if let Some(choice) = menu(&options, true).expect("IO Error") {
                             match *choice {
                                 "Copy save" => {
-                                    if let Err(_) = save::copy(&save.path) {
+                                    if save::copy(&save.path).is_err() {
                                         println!("There was an error copying the save file!");
                                     }
                                 }
                                 "Delete save" => {
-                                    if let Err(_) = save::delete(&save.path) {
+                                    if save::delete(&save.path).is_err() {
                                         println!("There was an error removing the save file!");
                                     }
                                 }
@@ -320,7 +1320,7 @@ fn main() {
                                     io::stdout().flush().expect("IO Error");
                                     io::stdin().read_line(&mut new_name).expect("IO Error");
 
-                                    match save::rename(&save.path, &new_name) {
+                                    match save::rename(&save.path, &new_name, None) {
                                         Ok(_) => {
                                             println!("Save file renamed!");
                                         }
@@ -335,39 +1335,208 @@ fn main() {
                                         }
                                     }
                                 }
+                                "Toggle favorite" => {
+                                    if save::set_favorite(&save.path, !save.favorite).is_err() {
+                                        println!("There was an error updating the save!");
+                                    } else if save.favorite {
+                                        println!("Removed the save from your favorites.");
+                                    } else {
+                                        println!("Added the save to your favorites.");
+                                    }
+                                }
+                                "Restore from backup" => {
+                                    let indexes = save::backups(&save.path);
+                                    let backups: Vec<String> = indexes.iter()
+                                        .map(|i| format!("Backup from {} saves ago", i + 1))
+                                        .collect();
+                                    if backups.is_empty() {
+                                        println!("That save has no backups.");
+                                    } else if let Some(backup) = menu(&backups, true)
+                                        .expect("IO Error") {
+                                        let pos = backups.iter().position(|b| b == backup);
+                                        let index = indexes[pos.unwrap()];
+                                        match save::restore_backup(&save.path, index) {
+                                            Ok(_) => println!("Save restored!"),
+                                            Err(_) => println!("Issue restoring the backup."),
+                                        }
+                                    }
+                                }
+                                "Compare with another save" => {
+                                    println!("Which save should it be compared to?");
+                                    let other = paged_menu(&saves, true, MENU_PAGE_SIZE)
+                                        .expect("IO Error");
+                                    if let Some(other) = other {
+                                        match save::diff(&save.path, &other.path) {
+                                            Ok(diff) => print_diff(&diff),
+                                            Err(_) => println!("Issue reading the saves."),
+                                        }
+                                    }
+                                }
                                 _ => panic!("unreachable arm in manage saves"),
                             }
                         }
                     }
                 }
             },
+            "Import saves" => {
+                let mut from = String::new();
+                print!("Which folder should saves be moved from? ");
+                io::stdout().flush().expect("IO Error");
+                io::stdin().read_line(&mut from).expect("IO Error");
+
+                // Safe unwrap because we verified this function works eariler
+                let to = path.map_or_else(|| save::project_save_dir().unwrap(), Path::to_path_buf);
+                match save::migrate_dir(Path::new(from.trim()), &to, None) {
+                    Ok(count) => println!("Moved {} saves.", count),
+                    Err(Error::NotFound(_)) | Err(Error::NotADirectory(_)) => {
+                        println!("That isn't a folder.");
+                    }
+                    Err(_) => println!("There was an error moving the saves!"),
+                }
+            }
             "Edit variables" => {
                 let options = ["Change goal", "Change income", "Change initial balance",
                                "Change add stock cost", "Change number of starting stocks",
-                               "Change income upgrade cost"];
-                
+                               "Change income upgrade cost", "Change split threshold",
+                               "Change split ratio", "Change starting stock ranges",
+                               "Change added stock ranges", "Change penny stock chance",
+                               "Toggle end turn confirmation", "Toggle unique stock names",
+                               "Toggle trending starts", "Change observation turns",
+                               "Change lot size", "Toggle automatic income upgrades",
+                               "Change autosave interval", "Change save limit",
+                               "Change slow turn reminder",
+                               "Toggle memorable save names", "Toggle ending on the goal",
+                               "Change announcement verbosity", "Undo last variable change"];
+                let before = vars.clone();
+
                 match *menu(&options, false).expect("IO Error").unwrap() {
                     "Change goal" => {
-                        goal = new_number("goal", Some(1_000_000)).expect("IO Error");
+                        vars.goal = new_number("goal", Some(1_000_000)).expect("IO Error");
                     },
                     "Change income" => {
-                        income = new_number("income", Some(1000)).expect("IO Error");
+                        vars.income = new_number("income", Some(1000)).expect("IO Error");
                     },
                     "Change initial balance" => {
-                        initial_balance = default_or_number("initial balance", "Same as income").expect("IO Error");
+                        vars.initial_balance = default_or_number("initial balance", "Same as income").expect("IO Error");
                     },
                     "Change add stock cost" => {
-                        add_stock_cost = new_number("add stock cost", Some(15000)).expect("IO Error");
+                        vars.add_stock_cost = new_number("add stock cost", Some(15000)).expect("IO Error");
                     },
                     "Change number of starting stocks" => {
-                        starting_stocks = new_number("number of starting stocks", Some(3)).expect("IO Error");
+                        vars.starting_stocks = new_number("number of starting stocks", Some(3)).expect("IO Error");
                     },
                     "Change income upgrade cost" => {
-                        income_upgrade_cost = default_or_number("income upgrade cost", "Ten times initial income").expect("IO Error");
+                        vars.income_upgrade_cost = default_or_number("income upgrade cost", "Ten times initial income").expect("IO Error");
+                    },
+                    "Change split threshold" => {
+                        vars.split_threshold = default_or_number("split threshold", "No automatic splits").expect("IO Error");
+                    },
+                    "Change split ratio" => {
+                        vars.split_ratio = new_number("split ratio", Some(2)).expect("IO Error");
+                    },
+                    "Change starting stock ranges" => {
+                        let chance = vars.starting_stock_range.penny_stock_chance_bps;
+                        vars.starting_stock_range = new_range("starting stock").expect("IO Error")
+                            .with_penny_stock_chance(chance);
+                    },
+                    "Change added stock ranges" => {
+                        let chance = vars.added_stock_range.penny_stock_chance_bps;
+                        vars.added_stock_range = new_range("added stock").expect("IO Error")
+                            .with_penny_stock_chance(chance);
+                    },
+                    "Change penny stock chance" => {
+                        let percent = new_number("penny stock chance (in percent)", Some(0))
+                            .expect("IO Error").min(100);
+                        vars.starting_stock_range.penny_stock_chance_bps = percent * 100;
+                        vars.added_stock_range.penny_stock_chance_bps = percent * 100;
+                    },
+                    "Toggle end turn confirmation" => {
+                        vars.confirm_end_turn = !vars.confirm_end_turn;
+                        println!("Ending a turn will {}ask for confirmation.",
+                                 if vars.confirm_end_turn { "" } else { "not " });
+                    },
+                    "Toggle unique stock names" => {
+                        vars.unique_stock_names = !vars.unique_stock_names;
+                        println!("Stock names will {}be kept unique.",
+                                 if vars.unique_stock_names { "" } else { "not " });
+                    },
+                    "Toggle automatic income upgrades" => {
+                        vars.auto_upgrade_income = !vars.auto_upgrade_income;
+                        println!("Income will {}be increased automatically when you can afford \
+                                  it.", if vars.auto_upgrade_income { "" } else { "not " });
+                    },
+                    "Toggle trending starts" => {
+                        vars.trending_starts = !vars.trending_starts;
+                        println!("New stocks will {}start out trending.",
+                                 if vars.trending_starts { "sometimes " } else { "never " });
+                    },
+                    "Toggle memorable save names" => {
+                        vars.memorable_save_names = !vars.memorable_save_names;
+                        println!("New saves will be named {}.", if vars.memorable_save_names {
+                            "with a random pair of words"
+                        } else {
+                            "after the time they were made"
+                        });
+                    },
+                    "Toggle ending on the goal" => {
+                        vars.end_on_goal = !vars.end_on_goal;
+                        println!("Reaching the goal will {}.", if vars.end_on_goal {
+                            "end the game"
+                        } else {
+                            "let you keep playing"
+                        });
+                    },
+                    "Change autosave interval" => {
+                        vars.autosave_interval = new_number("autosave interval (in turns)",
+                                                            Some(1))
+                            .expect("IO Error").max(1) as u32;
+                    },
+                    "Change observation turns" => {
+                        vars.observation_turns = new_number("observation turns", Some(0))
+                            .expect("IO Error") as u32;
+                    },
+                    "Change lot size" => {
+                        vars.min_trade = new_number("lot size (shares per trade)", Some(1))
+                            .expect("IO Error").max(1);
+                    },
+                    "Change save limit" => {
+                        vars.max_saves = default_or_number("save limit", "No limit")
+                            .expect("IO Error").map(|n| n.max(1) as usize);
+                    },
+                    "Change slow turn reminder" => {
+                        vars.slow_turn_minutes = default_or_number(
+                                "slow turn reminder (in minutes)", "No reminder")
+                            .expect("IO Error").map(|n| n.max(0));
+                    },
+                    "Change announcement verbosity" => {
+                        let levels = [("Quiet (only bankruptcies)", Verbosity::Quiet),
+                                      ("Normal", Verbosity::Normal),
+                                      ("Verbose (every price move)", Verbosity::Verbose)];
+                        let labels: Vec<&str> = levels.iter().map(|(label, _)| *label).collect();
+                        if let Some(label) = menu(&labels, true).expect("IO error") {
+                            vars.verbosity = levels.iter().find(|(l, _)| l == label).unwrap().1;
+                        }
+                    },
+                    "Undo last variable change" => {
+                        if !var_history.undo(&mut vars) {
+                            println!("There are no variable changes to undo.");
+                        }
+                        continue;
                     },
                     _ => panic!("unreachable arm in edit variables option"),
                 }
+                if let Err(problems) = vars.validate() {
+                    for problem in problems { println!("{}", problem); }
+                    println!("The change wasn't made.");
+                    vars = before;
+                    continue;
+                }
+                var_history.record(before, &vars);
             },
+            "Set seed" => {
+                seed = seed_input("What seed should the next game use? (Blank for random) ")
+                    .expect("IO Error");
+            }
             "Quit" => {
                 println!("Goodbye ;(");
                 break;
@@ -376,3 +1545,99 @@ fn main() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn numbers_are_checked_against_the_range() {
+        assert_eq!(check_range(0, 0, 10), Ok(0));
+        assert_eq!(check_range(10, 0, 10), Ok(10));
+        assert_eq!(check_range(11, 0, 10), Err("`11` must be between 0 and 10!".to_string()));
+        assert!(check_range(1, 2, 10).is_err());
+        assert!(check_range(1, 0, 0).is_err());
+    }
+
+    #[test]
+    fn menus_are_split_into_pages() {
+        let shown = page(32, 15, 0);
+        assert_eq!((shown.start, shown.end, shown.has_prev, shown.has_next), (0, 15, false, true));
+        let shown = page(32, 15, 2);
+        assert_eq!((shown.start, shown.end, shown.has_prev, shown.has_next), (30, 32, true, false));
+        let shown = page(32, 15, 9);
+        assert_eq!((shown.start, shown.end), (30, 32));
+        let shown = page(0, 15, 0);
+        assert_eq!((shown.start, shown.end, shown.has_prev, shown.has_next), (0, 0, false, false));
+        let shown = page(3, 0, 1);
+        assert_eq!((shown.start, shown.end), (1, 2));
+    }
+
+    #[test]
+    fn menu_choices_resolve_to_stocks_in_id_order() {
+        let stocks = [Stock::new(3, "Three".to_string(), 10, 1),
+                      Stock::new(0, "Zero".to_string(), 10, 1),
+                      Stock::new(2, "Two".to_string(), 10, 1)];
+        let mut player = Player::new(0, 0);
+        let chosen = |player: &Player, choice: usize| {
+            let entries = stock_entries(stocks.iter().collect(), player, false, SortKey::Id);
+            entries[choice - 1].stock.id()
+        };
+        assert_eq!((chosen(&player, 1), chosen(&player, 2), chosen(&player, 3)), (0, 2, 3));
+
+        player.toggle_watch(3);
+        assert_eq!((chosen(&player, 1), chosen(&player, 2), chosen(&player, 3)), (3, 0, 2));
+    }
+
+    #[test]
+    fn variable_changes_can_be_undone() {
+        let mut history = VariableHistory::default();
+        let mut vars = Variables::default();
+        history.record(vars.clone(), &vars);
+        assert!(!history.undo(&mut vars));
+
+        for goal in 1..=(VARIABLE_UNDO_LEN as i64 + 2) {
+            let before = vars.clone();
+            vars.goal = goal;
+            history.record(before, &vars);
+        }
+        assert!(history.undo(&mut vars));
+        assert_eq!(vars.goal, VARIABLE_UNDO_LEN as i64 + 1);
+        while history.undo(&mut vars) {}
+        assert_eq!(vars.goal, 2);
+    }
+
+    #[test]
+    fn stock_entries_show_ids_when_asked() {
+        let mut stock = Stock::new(3, "Crab".to_string(), 40, 1);
+        for _ in 0..millionaire::NEW_STOCK_TURNS { stock.age(); }
+        let entry = |watched, show_id| StockEntry { stock: &stock, watched, show_id }.to_string();
+        assert_eq!(entry(false, false), "Crab, Value: 40");
+        assert_eq!(entry(false, true), "Crab, Value: 40 [#3]");
+        assert_eq!(entry(true, true), "★ Crab, Value: 40 [#3]");
+    }
+
+    #[test]
+    fn sell_input_takes_shares_or_money() {
+        assert_eq!(parse_sell_input(" 12\n"), Ok(SellInput::Shares(12)));
+        assert_eq!(parse_sell_input("$500"), Ok(SellInput::Money(500)));
+        assert_eq!(parse_sell_input("$ 40"), Ok(SellInput::Money(40)));
+        assert!(parse_sell_input("$").is_err());
+        assert!(parse_sell_input("-3").is_err());
+        assert!(parse_sell_input("ten").is_err());
+    }
+
+    #[test]
+    fn color_codes_only_appear_when_color_is_on() {
+        COLOR.store(true, Ordering::Relaxed);
+        assert_eq!(bold("a".to_string()), "\x1b[1ma\x1b[0m");
+        assert_eq!(gain_or_loss(5, "+5".to_string()), "\x1b[32m+5\x1b[0m");
+        assert_eq!(gain_or_loss(-5, "-5".to_string()), "\x1b[31m-5\x1b[0m");
+        assert_eq!(gain_or_loss(0, "0".to_string()), "0");
+
+        COLOR.store(false, Ordering::Relaxed);
+        assert_eq!(bold("a".to_string()), "a");
+        assert_eq!(gain_or_loss(5, "+5".to_string()), "+5");
+        assert_eq!(gain_or_loss(-5, "-5".to_string()), "-5");
+    }
+}
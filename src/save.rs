@@ -1,21 +1,40 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeSet, VecDeque};
 use std::fmt;
 use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::Duration;
+use chrono::DateTime;
 use chrono::offset::Local;
-use crate::{Stock, Player};
+use crate::{apply_bps, format_percent, generate_name, generate_unique_name,
+            generate_unique_name_with, spin_off, CashFlowReason, DefaultMarket, Event,
+            MarketModel, NameWords, SortKey, Stock, StockRange, Player, RoundingMode,
+            TradeRecord, TransactionError};
 use directories::ProjectDirs;
-use serde::{Serialize, Deserialize};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha12Rng;
+use serde::{de, Serialize, Deserialize};
 use serde_json::error;
 
 #[derive(Debug)]
 pub enum Error {
     NotFound(PathBuf),
+    NotADirectory(PathBuf),
     PlatformNotSupported,
     IoError(io::Error),
     SerdeJsonError(error::Error),
     AlreadyExists,
     EmptyFileName,
+    InvalidStockDef(usize, error::Error),
+    UnsupportedFormat(PathBuf),
+    Locked(PathBuf),
+    /// A price series had a line that wasn't a positive price. Holds the line number,
+    /// starting at 1.
+    InvalidPriceSeries(PathBuf, usize),
+    #[cfg(feature = "binary-saves")]
+    BincodeError(bincode::Error),
 }
 
 impl From<io::Error> for Error {
@@ -30,122 +49,4657 @@ impl From<error::Error> for Error {
     }
 }
 
+#[cfg(feature = "binary-saves")]
+impl From<bincode::Error> for Error {
+    fn from(error: bincode::Error) -> Self {
+        Error::BincodeError(error)
+    }
+}
+
 #[derive(Serialize, Deserialize)]
 pub struct Game {
+    /// The market, in id order. New stocks get the next id, so adding them at the end
+    /// keeps the order; `stock_by_id` relies on it.
     pub stocks: Vec<Stock>,
     pub player: Player,
     pub goal: i64,
     pub add_stock_cost: i64,
     pub initial_income: i64,
     pub income_upgrade_cost: i64,
+    #[serde(default)]
+    pub seed: u64,
+    /// How much cheaper adding a stock gets each turn one isn't added, in basis points.
+    #[serde(default)]
+    pub add_stock_cost_decay_bps: i64,
+    /// The lowest the add stock cost can decay to.
+    #[serde(default)]
+    pub add_stock_cost_floor: i64,
+    #[serde(default)]
+    pub turns_since_stock_added: u32,
+    /// Stocks valued below this for `delist_after_turns` turns in a row get delisted.
+    #[serde(default)]
+    pub delist_below: Option<i64>,
+    #[serde(default)]
+    pub delist_after_turns: u32,
+    /// The lowest id a new stock can get, so the ids of delisted stocks aren't handed out
+    /// again.
+    #[serde(default)]
+    pub stock_id_floor: i64,
+    /// The player's net worth at the end of the last turn.
+    #[serde(default)]
+    pub previous_net_worth: Option<i64>,
+    /// The balance the player started with. `None` means the same as the initial income.
+    #[serde(default)]
+    pub initial_balance: Option<i64>,
+    /// The chance of an income event each turn, in basis points.
+    #[serde(default)]
+    pub income_event_chance_bps: i64,
+    /// The income modifier in effect and how many more turns it lasts.
+    #[serde(default)]
+    pub income_modifier: Option<(i64, u32)>,
+    /// Stocks valued above this get split automatically.
+    #[serde(default)]
+    pub split_threshold: Option<i64>,
+    /// How many shares each share becomes in a split.
+    #[serde(default = "default_split_ratio")]
+    pub split_ratio: i64,
+    /// The range the stocks a game starts with were generated from.
+    #[serde(default)]
+    pub starting_stock_range: StockRange,
+    /// The range stocks added during the game are generated from.
+    #[serde(default)]
+    pub added_stock_range: StockRange,
+    /// Whether to buy an income increase at the end of each turn, once the turn's income
+    /// is in, whenever the player can afford one.
+    #[serde(default)]
+    pub auto_upgrade_income: bool,
+    /// How many turns at the start of the game the player only watches the market move,
+    /// without income or trading. Counts down as they're played.
+    #[serde(default)]
+    pub observation_turns: u32,
+    /// The most stocks the market can have. Adding a stock past this fails. `None` has
+    /// no limit.
+    #[serde(default)]
+    pub max_stocks: Option<usize>,
+    /// How far stocks added during the game can already be trending, in basis points of
+    /// their variation. 0 starts them with no direction.
+    #[serde(default)]
+    pub start_direction_bps: i64,
+    /// Whether to show the breakdown and ask before ending a turn.
+    #[serde(default)]
+    pub confirm_end_turn: bool,
+    /// Whether to skip asking before increasing income or adding a stock.
+    #[serde(default)]
+    pub express_mode: bool,
+    /// Stocks that move more than this in one turn, in basis points, are halted for a
+    /// turn.
+    #[serde(default)]
+    pub halt_threshold_bps: Option<i64>,
+    /// The gap between the price stocks are bought and sold at, in basis points of their
+    /// value. Half of it is added when buying and half taken off when selling.
+    #[serde(default)]
+    pub spread_bps: i64,
+    /// The most recent headlines, oldest first.
+    #[serde(default)]
+    pub news: VecDeque<String>,
+    /// How many headlines `news` keeps.
+    #[serde(default = "default_news_len")]
+    pub news_len: usize,
+    /// How much of what happens each turn gets announced.
+    #[serde(default)]
+    pub verbosity: Verbosity,
+    /// The chance each turn of the most valuable stock spinning off a new one, in basis
+    /// points.
+    #[serde(default)]
+    pub spin_off_chance_bps: i64,
+    /// How much of the parent's value a spin-off takes, in basis points. Fractions of
+    /// 10000 or more are treated as 9999, so the parent is never left worthless.
+    #[serde(default = "default_spin_off_fraction_bps")]
+    pub spin_off_fraction_bps: i64,
+    /// How many turns pass between autosaves. 1 saves every turn.
+    #[serde(default = "default_autosave_interval")]
+    pub autosave_interval: u32,
+    /// The most saves to keep in the save's folder. Saving a new game past this deletes
+    /// the oldest saves first. `None` keeps every save.
+    #[serde(default)]
+    pub max_saves: Option<usize>,
+    /// How many turns at the start of the game stocks are kept at a value of at least 1
+    /// instead of going bankrupt.
+    #[serde(default)]
+    pub bankruptcy_grace_turns: u32,
+    /// Whether reaching the goal ends the game. If not, the player can keep playing.
+    #[serde(default = "default_end_on_goal")]
+    pub end_on_goal: bool,
+    /// Whether the goal has been reached and announced, for games that carry on past it.
+    #[serde(default)]
+    pub goal_reached: bool,
+    /// The turn the player first ended with the goal reached, which can be earlier than
+    /// the last turn in games that carry on past it.
+    #[serde(default)]
+    pub goal_reached_turn: Option<u32>,
+    /// How many times the starting market was re-rolled with `reroll_market`.
+    #[serde(default)]
+    pub rerolls: u32,
+    /// Whether this is the tutorial game, which shows `tutorial_hints` instead of `hints`.
+    #[serde(default)]
+    pub tutorial: bool,
+    /// The lot size: buys and sells must be a multiple of this many shares. 1 allows any
+    /// amount.
+    #[serde(default = "default_min_trade")]
+    pub min_trade: i64,
+    /// Whether each stock can only be traded once per turn.
+    #[serde(default)]
+    pub trade_cooldown: bool,
+    /// The ids of the stocks traded this turn.
+    #[serde(default)]
+    pub traded_this_turn: BTreeSet<i64>,
+    /// How many trades and income increases the player made this turn.
+    #[serde(default)]
+    pub actions_taken_this_turn: u32,
+    /// How many trades each turn skip the spread, traded at the stock's value instead.
+    #[serde(default)]
+    pub free_trades_per_turn: u32,
+    /// How many buys and sells the player made this turn.
+    #[serde(default)]
+    pub trades_this_turn: u32,
+    /// The most actions the player can take each turn, if limited.
+    #[serde(default)]
+    pub action_cap: Option<u32>,
+    /// How many turns have ended since the game started.
+    #[serde(default)]
+    pub turn: u32,
+    /// How many turns bought shares have to be held before they can be sold.
+    #[serde(default)]
+    pub settlement_turns: u32,
+    /// The chance of a flash crash each turn, in basis points.
+    #[serde(default)]
+    pub flash_crash_chance_bps: i64,
+    /// How much a flash crash drops every stock by, in basis points.
+    #[serde(default = "default_flash_crash_drop_bps")]
+    pub flash_crash_drop_bps: i64,
+    /// How much stocks recover the turn after a flash crash, in basis points.
+    #[serde(default = "default_flash_crash_recovery_bps")]
+    pub flash_crash_recovery_bps: i64,
+    /// How much stock variation and event chances grow each turn, in basis points, to make
+    /// the game harder as it goes on. 0 for no ramp.
+    #[serde(default)]
+    pub ramp_bps: i64,
+    /// The most the ramp can scale variation and event chances to, in basis points.
+    #[serde(default = "default_ramp_cap_bps")]
+    pub ramp_cap_bps: i64,
+    /// Pauses the game when a turn loses more than this much of the player's net worth,
+    /// in basis points. `None` never pauses.
+    #[serde(default)]
+    pub loss_circuit_bps: Option<i64>,
+    /// How stock values move each turn. Isn't saved, so a game always loads with the
+    /// default market and a custom model has to be set again.
+    #[serde(skip, default = "default_market")]
+    pub market: Box<dyn MarketModel>,
+    /// The player's net worth as of the last `net_worth_cached`, or `NO_NET_WORTH` once
+    /// it's invalidated. Atomic rather than a `Cell` so `Game` stays `Sync`.
+    #[serde(skip, default = "no_net_worth")]
+    net_worth_cache: AtomicI64,
+    /// Whether the market's mood moves and pushes every stock up or down.
+    #[serde(default)]
+    pub sentiment_enabled: bool,
+    /// The market's mood, from `-SENTIMENT_MAX` (bearish) to `SENTIMENT_MAX` (bullish).
+    #[serde(default)]
+    pub sentiment: i64,
+    /// The recovery due next turn after a flash crash.
+    #[serde(default)]
+    pub pending_recovery_bps: Option<i64>,
+    /// Notes the player wrote, with the turn each was written on.
+    #[serde(default)]
+    pub journal: Vec<(u32, String)>,
+    /// How far the game's random number generator has got through its stream, so a
+    /// reloaded game carries on where it left off.
+    #[serde(default)]
+    pub rng_word_pos: u128,
+    /// The player loses once their net worth falls to or below this.
+    #[serde(default)]
+    pub bust_threshold: Option<i64>,
+    /// Whether stock lists show each stock's id next to its name.
+    #[serde(default)]
+    pub show_stock_ids: bool,
+    /// The order stock lists are shown in. The market itself stays in the order stocks
+    /// were listed.
+    #[serde(default)]
+    pub stock_order: SortKey,
+    /// How the income modifier, spread and add stock cost decay round.
+    #[serde(default)]
+    pub rounding_mode: RoundingMode,
+    /// Holding more than this many shares of a stock is a controlling stake, which pays
+    /// a bonus with the income.
+    #[serde(default)]
+    pub control_threshold: Option<i64>,
+    /// How much each controlling stake pays per turn, in basis points of its worth.
+    #[serde(default)]
+    pub control_bonus_bps: i64,
+    /// Whether to show hints for new players at the start of each turn.
+    #[serde(default)]
+    pub show_hints: bool,
+    /// Whether the net worth breakdown leaves out stocks the player holds none of.
+    #[serde(default)]
+    pub hide_empty_holdings: bool,
+    /// The highest net worth the player had at the end of a turn.
+    #[serde(default)]
+    pub peak_net_worth: i64,
+    /// The biggest drop in end of turn net worth from an earlier peak.
+    #[serde(default)]
+    pub max_drawdown: i64,
+    /// The biggest drop in end of turn net worth from an earlier peak, in basis points of
+    /// that peak.
+    #[serde(default)]
+    pub max_drawdown_bps: i64,
+    /// How many buys and sells the player made.
+    #[serde(default)]
+    pub trades: u32,
+    /// The most profit made on a single sale.
+    #[serde(default)]
+    pub best_trade: Option<i64>,
+    /// The least profit (or biggest loss) made on a single sale.
+    #[serde(default)]
+    pub worst_trade: Option<i64>,
+    /// Turns that take longer than this many minutes get a reminder, if set.
+    #[serde(default)]
+    pub slow_turn_minutes: Option<i64>,
+    /// The most minutes the player spent on a single turn.
+    #[serde(default)]
+    pub longest_turn_minutes: i64,
+    /// Whether stocks listed during the game get names no other stock has.
+    #[serde(default)]
+    pub unique_stock_names: bool,
+    /// Weighted words to name stocks from instead of the usual ones.
+    #[serde(default)]
+    pub name_words: Option<NameWords>,
+    /// Net worths the player wants to hear about passing, up or down.
+    #[serde(default)]
+    pub alerts: Vec<i64>,
+    /// Fields this version doesn't know about, such as ones from a save written by a newer
+    /// version. They're written back out unchanged when saving.
+    #[serde(flatten)]
+    pub extra: serde_json::Map<String, serde_json::Value>,
 }
 
-#[derive(Hash)]
-pub struct Save {
-    pub path: PathBuf,
-    pub name: String,
-}
+fn default_split_ratio() -> i64 { 2 }
 
-impl fmt::Display for Save {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.name)
+fn default_news_len() -> usize { 10 }
+
+fn default_spin_off_fraction_bps() -> i64 { 2000 }
+
+fn default_flash_crash_drop_bps() -> i64 { 4000 }
+
+fn default_flash_crash_recovery_bps() -> i64 { 3000 }
+
+fn default_ramp_cap_bps() -> i64 { 30000 }
+
+fn default_autosave_interval() -> u32 { 1 }
+
+fn default_end_on_goal() -> bool { true }
+
+fn default_min_trade() -> i64 { 1 }
+
+fn default_market() -> Box<dyn MarketModel> { Box::new(DefaultMarket::default()) }
+
+/// Stands in for a net worth that hasn't been worked out since the game last changed.
+const NO_NET_WORTH: i64 = i64::MIN;
+
+fn no_net_worth() -> AtomicI64 { AtomicI64::new(NO_NET_WORTH) }
+
+/// The random number generator games use. Its position is stored in the save, so a game
+/// keeps the same random sequence across save and load.
+pub type GameRng = ChaCha12Rng;
+
+/// How far market sentiment can go either way. At the limit, every stock's momentum is
+/// pushed by half a percent of its value each turn.
+pub const SENTIMENT_MAX: i64 = 100;
+
+/// The starting direction, in basis points of variation, stocks get when trending starts
+/// are turned on.
+pub const TRENDING_START_BPS: i64 = 5000;
+
+/// The most stocks a game can start with.
+pub const MAX_STARTING_STOCKS: i64 = 1000;
+
+/// Checks the variables a new game is set up from, returning a message for each one
+/// that would make the game broken or unplayable.
+#[allow(clippy::too_many_arguments)]
+pub fn validate_variables(goal: i64, income: i64, initial_balance: Option<i64>,
+                          starting_stocks: i64, split_threshold: Option<i64>, split_ratio: i64,
+                          starting_range: StockRange, added_range: StockRange)
+    -> Result<(), Vec<String>> {
+    let mut problems = Vec::new();
+    let balance = initial_balance.unwrap_or(income);
+
+    if goal <= 0 {
+        problems.push("The goal has to be more than 0.".to_string());
+    } else if balance > goal {
+        problems.push("The initial balance is already past the goal.".to_string());
+    }
+    if income < 0 { problems.push("The income can't be negative.".to_string()); }
+    if balance < 0 { problems.push("The initial balance can't be negative.".to_string()); }
+    if income <= 0 && balance <= 0 {
+        problems.push("With no income and no initial balance, nothing can be bought."
+                      .to_string());
+    }
+    if !(0..=MAX_STARTING_STOCKS).contains(&starting_stocks) {
+        problems.push(format!("The number of starting stocks has to be between 0 and {}.",
+                              MAX_STARTING_STOCKS));
+    }
+    if split_threshold.is_some_and(|t| t <= 0) {
+        problems.push("The split threshold has to be more than 0.".to_string());
+    }
+    if split_ratio < 2 { problems.push("The split ratio has to be at least 2.".to_string()); }
+    for (name, range) in [("starting", starting_range), ("added", added_range)] {
+        if range.min_value <= 0 {
+            problems.push(format!("The {} stock minimum value has to be more than 0.", name));
+        }
     }
-}
 
-/// Turns a `&Path` into a `Game`. Will return an error if there was an issue reading
-/// the file at the Path or if there's an issue parsing the JSON.
-pub fn from_path(path: &Path) -> Result<Game, Error> {
-    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    if problems.is_empty() { Ok(()) } else { Err(problems) }
 }
 
-fn project_save_dir() -> Result<PathBuf, Error> {
-    let pd = ProjectDirs::from("xyz", "Rainbow Asteroids", "Millionaire");
-    let pd = match pd {
-        Some(pd) => pd,
-        None => return Err(Error::PlatformNotSupported),
-    };
+/// How much adding a stock costs by default.
+pub const DEFAULT_ADD_STOCK_COST: i64 = 15000;
 
-    Ok(pd.data_dir().to_path_buf())
-}
+impl Game {
+    /// Creates a game with the default relationships between its variables: the player
+    /// starts with one turn of income, an income upgrade costs ten times the income and
+    /// adding a stock costs `DEFAULT_ADD_STOCK_COST`.
+    pub fn new(goal: i64, income: i64, mut stocks: Vec<Stock>) -> Self {
+        stocks.sort();
+        Self {
+            stocks,
+            player: Player::new(income, income),
+            goal,
+            add_stock_cost: DEFAULT_ADD_STOCK_COST,
+            initial_income: income,
+            income_upgrade_cost: income * 10,
+            seed: 0,
+            add_stock_cost_decay_bps: 0,
+            add_stock_cost_floor: 0,
+            turns_since_stock_added: 0,
+            delist_below: None,
+            delist_after_turns: 0,
+            stock_id_floor: 0,
+            previous_net_worth: None,
+            initial_balance: None,
+            income_event_chance_bps: 0,
+            income_modifier: None,
+            split_threshold: None,
+            split_ratio: default_split_ratio(),
+            starting_stock_range: StockRange::default(),
+            added_stock_range: StockRange::default(),
+            start_direction_bps: 0,
+            max_stocks: None,
+            observation_turns: 0,
+            auto_upgrade_income: false,
+            confirm_end_turn: false,
+            express_mode: false,
+            halt_threshold_bps: None,
+            spread_bps: 0,
+            news: VecDeque::new(),
+            news_len: default_news_len(),
+            verbosity: Verbosity::default(),
+            spin_off_chance_bps: 0,
+            spin_off_fraction_bps: default_spin_off_fraction_bps(),
+            autosave_interval: default_autosave_interval(),
+            max_saves: None,
+            bankruptcy_grace_turns: 0,
+            end_on_goal: true,
+            goal_reached: false,
+            goal_reached_turn: None,
+            rerolls: 0,
+            tutorial: false,
+            min_trade: default_min_trade(),
+            trade_cooldown: false,
+            traded_this_turn: BTreeSet::new(),
+            actions_taken_this_turn: 0,
+            free_trades_per_turn: 0,
+            trades_this_turn: 0,
+            action_cap: None,
+            turn: 0,
+            settlement_turns: 0,
+            flash_crash_chance_bps: 0,
+            ramp_bps: 0,
+            ramp_cap_bps: default_ramp_cap_bps(),
+            loss_circuit_bps: None,
+            market: default_market(),
+            net_worth_cache: no_net_worth(),
+            sentiment_enabled: false,
+            sentiment: 0,
+            flash_crash_drop_bps: default_flash_crash_drop_bps(),
+            flash_crash_recovery_bps: default_flash_crash_recovery_bps(),
+            pending_recovery_bps: None,
+            journal: Vec::new(),
+            rng_word_pos: 0,
+            bust_threshold: None,
+            show_stock_ids: false,
+            stock_order: SortKey::default(),
+            rounding_mode: RoundingMode::Truncate,
+            control_threshold: None,
+            control_bonus_bps: 0,
+            show_hints: true,
+            hide_empty_holdings: false,
+            peak_net_worth: 0,
+            max_drawdown: 0,
+            max_drawdown_bps: 0,
+            trades: 0,
+            best_trade: None,
+            worst_trade: None,
+            slow_turn_minutes: None,
+            longest_turn_minutes: 0,
+            unique_stock_names: true,
+            name_words: None,
+            alerts: Vec::new(),
+            extra: serde_json::Map::new(),
+        }
+    }
 
-/// Finds all the potential save files and returns them. Will error if there was some
-/// issue reading the directory.
-pub fn saves_in_folder(dir: Option<&Path>) -> Result<Vec<Save>, Error> {
-    let mut result = Vec::new();
-        
-    let dir = match dir {
-        Some(p) => p.to_path_buf(),
-        None => project_save_dir()?,
-    };
+    /// Splits every stock valued above `split_threshold`, multiplying the player's shares
+    /// to keep their worth. Returns the names of the stocks split and the ratio used.
+    pub fn process_splits(&mut self) -> Vec<(String, i64)> {
+        self.invalidate_net_worth();
+        let mut result = Vec::new();
+        let threshold = match self.split_threshold {
+            Some(t) if self.split_ratio > 1 => t,
+            _ => return result,
+        };
 
-    if !dir.is_dir() {
-        return Err(Error::NotFound(dir));
+        for s in self.stocks.iter_mut() {
+            if s.value() <= threshold || !s.split(self.split_ratio) { continue; }
+
+            self.player.apply_split(s.id(), self.split_ratio);
+            result.push((s.name().to_string(), self.split_ratio));
+        }
+
+        result
     }
 
-    for f in dir.read_dir()? {
-        let f = match f {
-            Ok(de) => de,
-            Err(_) => continue,
+    /// Sometimes has the most valuable stock spin off a new one, giving the player a
+    /// share of it for each share of the parent they hold. Returns the names of the
+    /// parent and the spin-off.
+    pub fn process_spin_offs<R: Rng>(&mut self, rng: &mut R) -> Option<(String, String)> {
+        self.invalidate_net_worth();
+        if rng.gen_range(0..10000) >= self.spin_off_chance_bps { return None; }
+
+        let new_id = self.next_stock_id();
+        let fraction_bps = self.spin_off_fraction_bps.min(9999);
+        let parent = self.stocks.iter_mut()
+            .filter(|s| s.value() * fraction_bps / 10000 > 0)
+            .max_by_key(|s| s.value())?;
+
+        let child = spin_off(parent, new_id, fraction_bps);
+        self.player.apply_spin_off(parent.id(), new_id, fraction_bps);
+        let names = (parent.name().to_string(), child.name().to_string());
+        self.stocks.push(child);
+        Some(names)
+    }
+
+    /// Pays the player their income, scaled by any income modifier in effect, plus any
+    /// controlling stake bonuses. Returns the amount paid.
+    pub fn collect_income(&mut self) -> i64 {
+        self.invalidate_net_worth();
+        let mut payout = self.player.income();
+
+        if let Some((multiplier_bps, turns)) = self.income_modifier {
+            payout = apply_bps(payout, multiplier_bps, self.rounding_mode);
+            self.income_modifier = if turns > 1 {
+                Some((multiplier_bps, turns - 1))
+            } else {
+                None
+            };
+        }
+
+        self.player.deposit_for(payout, CashFlowReason::Income);
+        payout + self.collect_control_bonuses()
+    }
+
+    /// The `n` stocks that rose the most last turn and the `n` that fell the most, by
+    /// percent change, biggest first. Stocks that didn't move aren't in either list, so
+    /// each list can have fewer than `n`.
+    pub fn top_movers(&self, n: usize) -> (Vec<&Stock>, Vec<&Stock>) {
+        let mut gainers: Vec<&Stock> = self.stocks.iter()
+            .filter(|s| s.last_change_bps() > 0)
+            .collect();
+        gainers.sort_by_key(|s| Reverse(s.last_change_bps()));
+        gainers.truncate(n);
+
+        let mut losers: Vec<&Stock> = self.stocks.iter()
+            .filter(|s| s.last_change_bps() < 0)
+            .collect();
+        losers.sort_by_key(|s| s.last_change_bps());
+        losers.truncate(n);
+
+        (gainers, losers)
+    }
+
+    /// Each player's index and how many shares of a stock they hold. There's only ever
+    /// one player, at index 0.
+    pub fn ownership_of(&self, stock_id: i64) -> Vec<(usize, i64)> {
+        let held = self.stock_by_id(stock_id).map_or(0, |s| self.player.stock_balance(s));
+        vec![(0, held)]
+    }
+
+    /// All the money the player would collect at the end of this turn if prices stayed
+    /// where they are: their income, scaled by any income modifier in effect, plus any
+    /// controlling stake bonuses.
+    pub fn turn_income(&self) -> i64 {
+        let mut income = self.player.income();
+        if let Some((multiplier_bps, _)) = self.income_modifier {
+            income = apply_bps(income, multiplier_bps, self.rounding_mode);
+        }
+        income + self.control_bonus()
+    }
+
+    /// The bonus `collect_control_bonuses` would pay at current prices.
+    pub fn control_bonus(&self) -> i64 {
+        let threshold = match self.control_threshold {
+            Some(t) => t,
+            None => return 0,
         };
 
-        if f.file_name().to_string_lossy().ends_with(".save.json") {
-            let mut name = f.file_name().to_string_lossy().into_owned();
-            name.replace_range(name.len()-10.., ""); // Remove the extension
+        self.stocks.iter()
+            .map(|s| (s, self.player.stock_balance(s)))
+            .filter(|(_, held)| *held > threshold)
+            .map(|(s, held)| apply_bps(s.value() * held, self.control_bonus_bps,
+                                       self.rounding_mode))
+            .sum()
+    }
 
-            result.push(Save {
-                path: f.path(),
-                name
+    /// Pays the player `control_bonus_bps` of the worth of every holding bigger than
+    /// `control_threshold` shares. Returns the amount paid.
+    pub fn collect_control_bonuses(&mut self) -> i64 {
+        self.invalidate_net_worth();
+        let bonus = self.control_bonus();
+        if bonus != 0 {
+            self.player.deposit_for(bonus, CashFlowReason::ControlBonus);
+        }
+        bonus
+    }
+
+    /// Randomly decides which events happen this turn and applies them.
+    pub fn roll_events<R: Rng>(&mut self, rng: &mut R) -> Vec<Event> {
+        let mut result = Vec::new();
+        let scale_bps = self.ramp_scale_bps();
+
+        if self.income_modifier.is_none()
+            && rng.gen_range(0..10000) < self.income_event_chance_bps * scale_bps / 10000 {
+            let multiplier_bps = if rng.gen_bool(0.5) { 20000 } else { 5000 };
+            let event = Event::IncomeModifier { multiplier_bps, turns: 1 };
+            self.apply_event(event);
+            result.push(event);
+        }
+
+        if let Some(recovery_bps) = self.pending_recovery_bps.take() {
+            let event = Event::Recovery { recovery_bps };
+            self.apply_event(event);
+            result.push(event);
+        } else if self.flash_crash_chance_bps > 0
+            && rng.gen_range(0..10000) < self.flash_crash_chance_bps * scale_bps / 10000 {
+            let event = Event::FlashCrash {
+                drop_bps: self.flash_crash_drop_bps,
+                recovery_bps: self.flash_crash_recovery_bps,
+            };
+            self.apply_event(event);
+            result.push(event);
+        }
+
+        result
+    }
+
+    /// How much the difficulty ramp scales stock variation and event chances this turn,
+    /// in basis points. Grows by `ramp_bps` each turn, up to `ramp_cap_bps`, and never
+    /// goes below 10000 (no change).
+    pub fn ramp_scale_bps(&self) -> i64 {
+        let ramped = 10000i64.saturating_add((self.turn as i64).saturating_mul(self.ramp_bps));
+        ramped.min(self.ramp_cap_bps).max(10000)
+    }
+
+    /// Whether the last turn lost enough of the player's net worth to trip the loss
+    /// circuit.
+    pub fn loss_circuit_tripped(&self) -> bool {
+        match (self.loss_circuit_bps, self.previous_net_worth) {
+            (Some(limit_bps), Some(prev)) => {
+                exceeds_loss_limit(prev, self.player.net_worth(&self.stocks), limit_bps)
+            }
+            _ => false,
+        }
+    }
+
+    /// Applies an event to the game.
+    pub fn apply_event(&mut self, event: Event) {
+        self.invalidate_net_worth();
+        match event {
+            Event::IncomeModifier { multiplier_bps, turns } => {
+                self.income_modifier = Some((multiplier_bps, turns));
+            }
+            Event::FlashCrash { drop_bps, recovery_bps } => {
+                for s in self.stocks.iter_mut() { s.shock(-drop_bps); }
+                self.pending_recovery_bps = Some(recovery_bps);
+            }
+            Event::Recovery { recovery_bps } => {
+                for s in self.stocks.iter_mut() { s.shock(recovery_bps); }
+            }
+        }
+    }
+
+    /// Scales income upgrades to the player's current income: each one adds the current
+    /// income and costs ten times it, like at the start of a new game. This keeps upgrades
+    /// meaningful after the income was set high, at the cost of them no longer being
+    /// cheap. The game's `initial_income` is left alone, so `reset_player` still puts the
+    /// player back to the income and balance they started with.
+    pub fn rebase_income_upgrades(&mut self) {
+        self.player.rebase_initial_income();
+        self.income_upgrade_cost = self.player.income() * 10;
+    }
+
+    /// Puts the player back to how they started the game, leaving the market as it is.
+    pub fn reset_player(&mut self) {
+        let balance = self.initial_balance.unwrap_or(self.initial_income);
+        self.player.reset(balance, self.initial_income);
+        self.previous_net_worth = None;
+        self.invalidate_net_worth();
+    }
+
+    /// The game's settings as a builder, so they can be changed and built into a new game.
+    /// Every setting is kept, but none of the game's progress: the stocks, the player's
+    /// holdings and balance, the turn, the news and the stats. The `market` model isn't
+    /// kept either, as it isn't saved.
+    pub fn to_builder(&self) -> GameBuilder {
+        GameBuilder {
+            goal: self.goal,
+            income: self.initial_income,
+            seed: self.seed,
+            initial_balance: self.initial_balance,
+            add_stock_cost: self.add_stock_cost,
+            income_upgrade_cost: self.income_upgrade_cost,
+            split_threshold: self.split_threshold,
+            split_ratio: self.split_ratio,
+            starting_stock_range: self.starting_stock_range,
+            added_stock_range: self.added_stock_range,
+            start_direction_bps: self.start_direction_bps,
+            max_stocks: self.max_stocks,
+            spread_bps: self.spread_bps,
+            free_trades_per_turn: self.free_trades_per_turn,
+            confirm_end_turn: self.confirm_end_turn,
+            unique_stock_names: self.unique_stock_names,
+            auto_upgrade_income: self.auto_upgrade_income,
+            end_on_goal: self.end_on_goal,
+            observation_turns: self.observation_turns,
+            autosave_interval: self.autosave_interval,
+            max_saves: self.max_saves,
+            slow_turn_minutes: self.slow_turn_minutes,
+            verbosity: self.verbosity,
+            add_stock_cost_decay_bps: self.add_stock_cost_decay_bps,
+            add_stock_cost_floor: self.add_stock_cost_floor,
+            delist_below: self.delist_below,
+            delist_after_turns: self.delist_after_turns,
+            income_event_chance_bps: self.income_event_chance_bps,
+            express_mode: self.express_mode,
+            halt_threshold_bps: self.halt_threshold_bps,
+            news_len: self.news_len,
+            spin_off_chance_bps: self.spin_off_chance_bps,
+            spin_off_fraction_bps: self.spin_off_fraction_bps,
+            bankruptcy_grace_turns: self.bankruptcy_grace_turns,
+            tutorial: self.tutorial,
+            min_trade: self.min_trade,
+            trade_cooldown: self.trade_cooldown,
+            action_cap: self.action_cap,
+            settlement_turns: self.settlement_turns,
+            flash_crash_chance_bps: self.flash_crash_chance_bps,
+            flash_crash_drop_bps: self.flash_crash_drop_bps,
+            flash_crash_recovery_bps: self.flash_crash_recovery_bps,
+            ramp_bps: self.ramp_bps,
+            ramp_cap_bps: self.ramp_cap_bps,
+            loss_circuit_bps: self.loss_circuit_bps,
+            sentiment_enabled: self.sentiment_enabled,
+            bust_threshold: self.bust_threshold,
+            show_stock_ids: self.show_stock_ids,
+            stock_order: self.stock_order,
+            rounding_mode: self.rounding_mode,
+            control_threshold: self.control_threshold,
+            control_bonus_bps: self.control_bonus_bps,
+            show_hints: self.show_hints,
+            hide_empty_holdings: self.hide_empty_holdings,
+            name_words: self.name_words.clone(),
+            alerts: self.alerts.clone(),
+            extra: self.extra.clone(),
+        }
+    }
+
+    /// The cost of adding a new stock this turn, after decay.
+    pub fn current_add_stock_cost(&self) -> i64 {
+        let floor = self.add_stock_cost_floor.min(self.add_stock_cost);
+        let mut cost = self.add_stock_cost;
+
+        for _ in 0..self.turns_since_stock_added {
+            if cost <= floor || self.add_stock_cost_decay_bps <= 0 { break; }
+            cost -= apply_bps(cost, self.add_stock_cost_decay_bps, self.rounding_mode);
+        }
+
+        cost.max(floor)
+    }
+
+    /// The player's net worth, remembered until the game's own methods next change it.
+    /// Cheaper than `Player::net_worth` when it's asked for repeatedly, as it only goes
+    /// through the stocks once. Code that changes `player` or `stocks` directly must call
+    /// `invalidate_net_worth` afterwards.
+    pub fn net_worth_cached(&self) -> i64 {
+        let cached = self.net_worth_cache.load(Ordering::Relaxed);
+        if cached != NO_NET_WORTH { return cached; }
+        let net_worth = self.player.net_worth(&self.stocks);
+        self.net_worth_cache.store(net_worth, Ordering::Relaxed);
+        net_worth
+    }
+
+    /// Forgets the net worth remembered by `net_worth_cached`.
+    pub fn invalidate_net_worth(&mut self) {
+        *self.net_worth_cache.get_mut() = NO_NET_WORTH;
+    }
+
+    /// All the money in the game: the player's balance plus the market value of their
+    /// holdings. Trading at market value shouldn't change this, which makes it useful for
+    /// checking the economy doesn't create or destroy money by accident.
+    pub fn total_money_in_play(&self) -> i64 {
+        self.player.net_worth(&self.stocks)
+    }
+
+    /// Estimates how hard the game is to win from its variables. Each factor of ten
+    /// between the goal and the player's first turn of money adds 10 points, and the
+    /// stocks' typical variation as a fraction of their value adds 10 points per 100%.
+    /// Slow income growth adds 5 points per factor of ten in the turns an income increase
+    /// takes to pay for itself, events add 10 points per 100% chance each turn, and the
+    /// spread adds a point per 1%. Returns the score along with a label for it.
+    pub fn difficulty_score(&self) -> (f64, &'static str) {
+        let start = (self.initial_balance.unwrap_or(self.initial_income) + self.initial_income)
+            .max(1) as f64;
+        let wealth_gap = (self.goal as f64 / start).max(1.0).log10();
+
+        let volatility = |r: &StockRange| {
+            (r.min_variation + r.max_variation) as f64 / (r.min_value + r.max_value).max(1) as f64
+        };
+        let volatility = (volatility(&self.starting_stock_range)
+            + volatility(&self.added_stock_range)) / 2.0;
+
+        // Income that never grows counts the same as taking 1000 turns to pay back
+        let payback = if self.initial_income > 0 {
+            self.income_upgrade_cost as f64 / self.initial_income as f64
+        } else {
+            1000.0
+        };
+        let slow_growth = payback.clamp(1.0, 1000.0).log10();
+        let events = (self.income_event_chance_bps + self.flash_crash_chance_bps) as f64 / 10000.0;
+        let spread = self.spread_bps as f64 / 100.0;
+
+        let score = wealth_gap * 10.0 + volatility * 10.0 + slow_growth * 5.0 + events * 10.0
+            + spread;
+        let label = match score {
+            s if s < 25.0 => "Easy",
+            s if s < 40.0 => "Normal",
+            s if s < 55.0 => "Hard",
+            _ => "Extreme",
+        };
+
+        (score, label)
+    }
+
+    /// The price the player pays for a share of a stock, including the spread.
+    pub fn ask_price(&self, stock: &Stock) -> i64 {
+        stock.value() + self.rounding_mode.divide(stock.value() * self.spread_bps, 20000)
+    }
+
+    /// The price the player gets for a share of a stock, after the spread.
+    pub fn bid_price(&self, stock: &Stock) -> i64 {
+        stock.value() - self.rounding_mode.divide(stock.value() * self.spread_bps, 20000)
+    }
+
+    /// Whether the player's next trade this turn is one of the `free_trades_per_turn` that
+    /// skip the spread.
+    pub fn next_trade_free(&self) -> bool {
+        self.trades_this_turn < self.free_trades_per_turn
+    }
+
+    /// The price the player's next buy of a stock would pay, which is the ask price unless the
+    /// trade is free.
+    pub fn buy_price(&self, stock: &Stock) -> i64 {
+        if self.next_trade_free() { stock.value() } else { self.ask_price(stock) }
+    }
+
+    /// The price the player's next sale of a stock would get, which is the bid price unless the
+    /// trade is free.
+    pub fn sell_price(&self, stock: &Stock) -> i64 {
+        if self.next_trade_free() { stock.value() } else { self.bid_price(stock) }
+    }
+
+    /// Finds a stock by its id, with a binary search since the market is kept in id order.
+    pub fn stock_by_id(&self, id: i64) -> Option<&Stock> {
+        let idx = self.stocks.binary_search_by_key(&id, |s| s.id()).ok()?;
+        Some(&self.stocks[idx])
+    }
+
+    /// Like `stock_by_id`, but lets the stock be changed.
+    pub fn stock_by_id_mut(&mut self, id: i64) -> Option<&mut Stock> {
+        self.invalidate_net_worth();
+        let idx = self.stocks.binary_search_by_key(&id, |s| s.id()).ok()?;
+        Some(&mut self.stocks[idx])
+    }
+
+    fn stock(&self, id: i64) -> Result<&Stock, TransactionError> {
+        self.stock_by_id(id).ok_or(TransactionError::UnknownStock(id))
+    }
+
+    /// How many more actions the player can take this turn, or `None` if unlimited.
+    pub fn actions_left(&self) -> Option<u32> {
+        self.action_cap.map(|cap| cap.saturating_sub(self.actions_taken_this_turn))
+    }
+
+    /// Runs an action if the player has any left this turn, counting it if it succeeds.
+    fn act<T>(&mut self, action: impl FnOnce(&mut Self) -> Result<T, TransactionError>)
+        -> Result<T, TransactionError> {
+        if self.observation_turns > 0 { return Err(TransactionError::Observing); }
+        if self.actions_left() == Some(0) {
+            return Err(TransactionError::ActionBudgetExceeded);
+        }
+        let result = action(self);
+        self.invalidate_net_worth();
+        let result = result?;
+        self.actions_taken_this_turn += 1;
+        Ok(result)
+    }
+
+    /// Whether the trade cooldown stops a stock being traded again this turn.
+    pub fn on_cooldown(&self, stock_id: i64) -> bool {
+        self.trade_cooldown && self.traded_this_turn.contains(&stock_id)
+    }
+
+    /// Rounds an amount of shares down to a whole number of lots.
+    pub fn round_to_lot(&self, amount: i64) -> i64 {
+        amount - amount % self.min_trade.max(1)
+    }
+
+    /// Buys a stock for the player at `buy_price`. Will return
+    /// `TransactionError::InvalidAmount` if the amount isn't positive,
+    /// `TransactionError::Cooldown` if the cooldown stops the stock being traded,
+    /// `TransactionError::Halted` if trading in the stock is halted, or
+    /// `TransactionError::BelowMinTrade` if the amount isn't a whole number of lots.
+    pub fn buy_stock(&mut self, stock_id: i64, amount: i64) -> Result<(), TransactionError> {
+        self.act(|game| {
+            if amount <= 0 { return Err(TransactionError::InvalidAmount); }
+            if game.on_cooldown(stock_id) { return Err(TransactionError::Cooldown); }
+            if game.stock(stock_id)?.is_halted() { return Err(TransactionError::Halted); }
+            if game.round_to_lot(amount) != amount { return Err(TransactionError::BelowMinTrade); }
+            let price = game.buy_price(game.stock(stock_id)?);
+            game.buy_at(stock_id, price, amount)?;
+            game.traded_this_turn.insert(stock_id);
+            game.trades_this_turn += 1;
+            Ok(())
+        })
+    }
+
+    /// Buys shares for the player at `price` and records the trade, without the limits on
+    /// what the player can do in a turn. Shared by `buy_stock` and the buys the game makes
+    /// on the player's behalf, which still have to be whole lots.
+    fn buy_at(&mut self, stock_id: i64, price: i64, amount: i64) -> Result<(), TransactionError> {
+        if self.round_to_lot(amount) != amount { return Err(TransactionError::BelowMinTrade); }
+        self.player.buy_stock_at(stock_id, price, amount)?;
+        self.player.record_trade(TradeRecord { turn: self.turn, stock_id, price, amount });
+        if self.settlement_turns > 0 {
+            self.player.record_purchase(stock_id, self.turn, amount);
+        }
+        self.trades += 1;
+        self.invalidate_net_worth();
+        Ok(())
+    }
+
+    /// How many shares of a stock the player can sell this turn.
+    pub fn sellable_shares(&self, stock_id: i64) -> i64 {
+        self.player.settled_shares(stock_id, self.turn, self.settlement_turns)
+    }
+
+    /// Sells a stock for the player at `sell_price`. Will return
+    /// `TransactionError::InvalidAmount` if the amount isn't positive,
+    /// `TransactionError::UnsettledShares` if that would sell shares bought less than
+    /// `settlement_turns` turns ago, `TransactionError::Cooldown` if the cooldown stops the
+    /// stock being traded, `TransactionError::Halted` if trading in the stock is halted, or
+    /// `TransactionError::BelowMinTrade` if the amount isn't a whole number of lots.
+    pub fn sell_stock(&mut self, stock_id: i64, amount: i64) -> Result<(), TransactionError> {
+        self.act(|game| {
+            if amount <= 0 { return Err(TransactionError::InvalidAmount); }
+            if game.on_cooldown(stock_id) { return Err(TransactionError::Cooldown); }
+            if game.stock(stock_id)?.is_halted() { return Err(TransactionError::Halted); }
+            if game.round_to_lot(amount) != amount { return Err(TransactionError::BelowMinTrade); }
+            if amount <= game.player.stock_balance(game.stock(stock_id)?)
+                && amount > game.sellable_shares(stock_id) {
+                return Err(TransactionError::UnsettledShares);
+            }
+            let price = game.sell_price(game.stock(stock_id)?);
+            let realized_before = game.player.realized_pl();
+            game.player.sell_stock_at(stock_id, price, amount)?;
+            game.player.record_trade(TradeRecord {
+                turn: game.turn, stock_id, price, amount: -amount,
             });
+            game.traded_this_turn.insert(stock_id);
+
+            let profit = game.player.realized_pl() - realized_before;
+            game.best_trade = Some(game.best_trade.map_or(profit, |b| b.max(profit)));
+            game.worst_trade = Some(game.worst_trade.map_or(profit, |w| w.min(profit)));
+            game.trades += 1;
+            game.trades_this_turn += 1;
+            Ok(())
+        })
+    }
+
+    /// Increases the player's income for `income_upgrade_cost`.
+    pub fn increase_income(&mut self) -> Result<(), TransactionError> {
+        self.act(|game| {
+            game.player.increase_income(game.income_upgrade_cost)
+                .map_err(|_| TransactionError::InsufficientFunds)
+        })
+    }
+
+    /// Does something on the player's behalf, the same as choosing it from the game menu.
+    /// Adding a stock and ending the turn draw from `rng`. Buys and sells of an amount that
+    /// isn't positive fail with `TransactionError::InvalidAmount` without counting as an
+    /// action.
+    pub fn apply_action<R: Rng>(&mut self, action: Action, rng: &mut R) -> ActionResult {
+        if let Action::Buy { amount, .. } | Action::Sell { amount, .. } = action {
+            if amount <= 0 { return ActionResult::Failed(TransactionError::InvalidAmount); }
+        }
+
+        let result = match action {
+            Action::Buy { stock_id, amount } => self.buy_stock(stock_id, amount),
+            Action::Sell { stock_id, amount } => self.sell_stock(stock_id, amount),
+            Action::IncreaseIncome => self.increase_income(),
+            Action::AddStock => {
+                return match self.add_random_stock(rng) {
+                    Ok(stock) => ActionResult::StockAdded(stock.id()),
+                    Err(e) => ActionResult::Failed(e),
+                };
+            }
+            Action::EndTurn => return ActionResult::TurnEnded(Box::new(self.tick(rng))),
+            Action::Quit => return ActionResult::Quit,
+        };
+
+        match result {
+            Ok(()) => ActionResult::Done,
+            Err(e) => ActionResult::Failed(e),
         }
     }
 
-    Ok(result)
-}
+    /// Whether the player's net worth has passed the goal.
+    pub fn has_won(&self) -> bool {
+        self.player.net_worth(&self.stocks) > self.goal
+    }
 
-/// Get a path to a save file.
-pub fn make_path(dir: Option<&Path>) -> Result<PathBuf, Error> {
-    let mut dir = match dir {
-        Some(p) => p.to_path_buf(),
-        None => project_save_dir()?,
-    };
+    /// Turns the game into JSON, the same as a save file holds, without touching any file.
+    pub fn to_json(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string(self)?)
+    }
 
-    dir.push(Local::now().format("%Y-%m-%d %H:%M:%S.save.json").to_string());
-    Ok(dir)
-}
+    /// Reads a game from JSON, such as from `to_json`. Unlike `from_path`, the game isn't
+    /// sanitized, so it comes back exactly as written.
+    pub fn from_json(json: &str) -> Result<Game, Error> {
+        Ok(serde_json::from_str(json)?)
+    }
 
-/// Saves a game at path
-pub fn save(path: &Path, game: &Game) -> Result<(), Error> {
-    fs::write(path, serde_json::to_string(game)?)?;
-    
-    Ok(())
-}
+    /// Turns just the market into JSON, without the player or any settings, so it can be
+    /// shared and read back with `import_market`.
+    pub fn export_market(&self) -> Result<String, Error> {
+        Ok(serde_json::to_string(&self.stocks)?)
+    }
 
-/// Copies a save in the same folder as the specified save.
-pub fn copy(path: &Path) -> Result<(), Error> {
-    let copy_name = format!("{} {}", "Copy of", path.file_name().unwrap().to_string_lossy());
-    let mut copy_path = path.to_path_buf();
-    copy_path.set_file_name(copy_name);
+    /// Whether the game is due an autosave this turn. Games that are over are always due
+    /// one, so the final state is kept.
+    // `is_multiple_of` would need Rust 1.87
+    #[allow(clippy::manual_is_multiple_of)]
+    pub fn autosave_due(&self) -> bool {
+        self.autosave_interval <= 1 || self.turn % self.autosave_interval == 0
+            || self.is_over()
+    }
 
-    fs::copy(path, &copy_path)?;
+    /// Whether the player should be asked before doing something. Express mode skips
+    /// asking before things that don't throw anything away.
+    pub fn should_confirm(&self, confirmation: Confirmation) -> bool {
+        match confirmation {
+            Confirmation::IncreaseIncome | Confirmation::AddStock => !self.express_mode,
+            Confirmation::EndTurn => self.confirm_end_turn,
+            Confirmation::ResetPortfolio => true,
+        }
+    }
 
-    Ok(())
-}
+    /// Whether the game has ended, by reaching the goal (when that ends the game) or going
+    /// bust.
+    pub fn is_over(&self) -> bool {
+        (self.end_on_goal && self.has_won()) || self.is_bust()
+    }
 
-/// Deletes a save. Pretty much the same as `std::fs::remove_file`.
-pub fn delete(path: &Path) -> Result<(), Error> {
-    fs::remove_file(path)?;
-    Ok(())
-}
+    /// Whether the player's net worth has fallen to or below `bust_threshold`, losing
+    /// the game.
+    pub fn is_bust(&self) -> bool {
+        self.bust_threshold.is_some_and(|t| self.player.net_worth(&self.stocks) <= t)
+    }
 
-/// Renames save file.
-pub fn rename(path: &Path, name: &str) -> Result<(), Error> {
-    let name = name.trim();
-    if name == "" { return Err(Error::EmptyFileName); }
+    /// Roughly how many more turns until the player reaches the goal on income alone,
+    /// assuming stock prices stay flat. Returns `Some(0)` if the goal is already reached
+    /// and `None` if it never will be because the player has no income.
+    pub fn estimated_turns_to_goal(&self) -> Option<u32> {
+        if self.has_won() { return Some(0); }
+        // Winning takes a net worth above the goal, not at it.
+        let net_worth = self.player.net_worth(&self.stocks);
+        self.player.turns_to_afford(self.goal.saturating_add(1).saturating_sub(net_worth))
+    }
 
-    let mut new_path = path.to_path_buf();
-    new_path.set_file_name(format!("{}.save.json", name));
-    if new_path.exists() { return Err(Error::AlreadyExists); }
-    fs::rename(path, &new_path)?;
+    /// Ends the current turn and advances the world to the next one: the player collects
+    /// their income, the market moves, events happen and then bankruptcies, splits,
+    /// spin-offs, delistings and recurring buys are processed.
+    pub fn tick<R: Rng>(&mut self, rng: &mut R) -> TickOutcome {
+        let before = self.audit_snapshot();
+        self.invalidate_net_worth();
+        let net_worth = self.player.net_worth(&self.stocks);
+        self.actions_taken_this_turn = 0;
+        self.traded_this_turn.clear();
+        self.trades_this_turn = 0;
+        self.turn += 1;
+        self.player.settle(self.turn, self.settlement_turns);
+        let income = self.collect_income();
+        let income_upgraded = self.auto_upgrade_income
+            && self.player.increase_income(self.income_upgrade_cost).is_ok();
+        let values: Vec<(i64, i64)> = self.stocks.iter().map(|s| (s.id(), s.value())).collect();
+        self.advance_market(rng);
+        let events = self.roll_events(rng);
+        let price_moves = values.into_iter()
+            .filter_map(|(id, before)| {
+                let s = self.stock_by_id(id)?;
+                (s.value() != before).then(|| (s.name().to_string(), before, s.value()))
+            })
+            .collect();
 
-    Ok(())
+        let mut outcome = TickOutcome {
+            income,
+            income_upgraded,
+            price_moves,
+            events,
+            bankruptcies: self.process_bankruptcies(),
+            splits: self.process_splits(),
+            spin_off: self.process_spin_offs(rng),
+            delistings: self.process_delistings(),
+            recurring_buys: self.process_recurring_buys(),
+            won: self.has_won(),
+            bust: self.is_bust(),
+            alerts: self.check_alerts(net_worth),
+            audit: Ok(()),
+        };
+
+        if outcome.won && self.goal_reached_turn.is_none() {
+            self.goal_reached_turn = Some(self.turn);
+        }
+        self.invalidate_net_worth();
+        self.record_net_worth(self.net_worth_cached());
+        for headline in outcome.headlines() {
+            self.push_news(headline);
+        }
+
+        outcome.audit = self.audit_turn(&before);
+        outcome
+    }
+
+    /// Records the player's money, to be checked with `audit_turn` later.
+    pub fn audit_snapshot(&self) -> AuditSnapshot {
+        AuditSnapshot {
+            balance: self.player.balance(),
+            cash_flows: self.player.cash_flows().len(),
+            trade_cash: self.player.trade_cash(),
+        }
+    }
+
+    /// Checks that every change to the player's money since `before` was taken during a
+    /// turn is explained by trades (including delisting payouts and recurring buys) or by
+    /// the cash flows a turn can cause: income, control bonuses and automatic income
+    /// upgrades. Every `tick` checks itself and reports the result in its outcome.
+    pub fn audit_turn(&self, before: &AuditSnapshot) -> Result<(), AuditError> {
+        let flows = self.player.cash_flows().get(before.cash_flows..).unwrap_or(&[]);
+        if let Some(flow) = flows.iter().find(|f| !matches!(f.reason,
+            CashFlowReason::Income | CashFlowReason::ControlBonus
+            | CashFlowReason::IncomeUpgrade)) {
+            return Err(AuditError::UnexpectedCashFlow(flow.reason));
+        }
+
+        let flow_total: i64 = flows.iter().map(|f| f.amount).sum();
+        let unexplained = self.player.balance() - before.balance - flow_total
+            - (self.player.trade_cash() - before.trade_cash);
+        if unexplained != 0 { return Err(AuditError::Unexplained(unexplained)); }
+        Ok(())
+    }
+
+    /// Fixes values a hand-edited save could have broken: stock values below 1, holdings
+    /// of stocks that don't exist and a negative goal or income. Each fix is added to the
+    /// news feed, and also returned. The market is also put back in id order, which saves
+    /// from before sorting stopped reordering it might not be in.
+    pub fn sanitize(&mut self) -> Vec<String> {
+        self.invalidate_net_worth();
+        self.stocks.sort();
+        let mut fixes = Vec::new();
+
+        for s in self.stocks.iter_mut() {
+            if s.clamp_value() {
+                fixes.push(format!("Stock '{}' had a value below 1 and was set to 1.", s.name()));
+            }
+        }
+        for id in self.player.drop_unknown_holdings(&self.stocks) {
+            fixes.push(format!("Shares of a stock that doesn't exist (#{}) were removed.", id));
+        }
+        if self.goal < 0 {
+            self.goal = 0;
+            fixes.push("The goal was negative and was set to 0.".to_string());
+        }
+        if self.player.clamp_income() {
+            fixes.push("The income was negative and was set to 0.".to_string());
+        }
+
+        for fix in &fixes {
+            self.push_news(format!("Save repaired: {}", fix));
+        }
+        fixes
+    }
+
+    /// The game's random number generator, at the position it was last stored at.
+    pub fn rng(&self) -> GameRng {
+        let mut rng = GameRng::seed_from_u64(self.seed);
+        rng.set_word_pos(self.rng_word_pos);
+        rng
+    }
+
+    /// Remembers the position of the game's random number generator, to be picked up
+    /// again by `rng`.
+    pub fn store_rng(&mut self, rng: &GameRng) {
+        self.rng_word_pos = rng.get_word_pos();
+    }
+
+    /// Totals for the game so far, for showing when it ends.
+    pub fn summary_stats(&self) -> SummaryStats {
+        let net_worth = self.player.net_worth(&self.stocks);
+        let reached_goal = self.goal_reached || net_worth > self.goal;
+        SummaryStats {
+            seed: self.seed,
+            goal: self.goal,
+            reached_goal,
+            goal_turn: self.goal_reached_turn.or_else(|| reached_goal.then_some(self.turn)),
+            turns_played: self.turn,
+            final_net_worth: net_worth,
+            peak_net_worth: self.peak_net_worth.max(net_worth),
+            max_drawdown: self.max_drawdown,
+            max_drawdown_bps: self.max_drawdown_bps,
+            realized_pl: self.player.realized_pl(),
+            trades: self.trades,
+            best_trade: self.best_trade,
+            worst_trade: self.worst_trade,
+            longest_turn_minutes: self.longest_turn_minutes,
+        }
+    }
+
+    /// Records how long a turn took, given when it started and ended. Returns the minutes
+    /// spent if the turn took longer than `slow_turn_minutes`.
+    pub fn record_turn_time(&mut self, started: DateTime<Local>, ended: DateTime<Local>)
+        -> Option<i64> {
+        let minutes = minutes_between(started, ended);
+        self.longest_turn_minutes = self.longest_turn_minutes.max(minutes);
+        self.slow_turn_minutes.filter(|&limit| minutes > limit).map(|_| minutes)
+    }
+
+    /// Updates the peak net worth and the biggest drawdowns with the net worth at the end
+    /// of a turn.
+    pub fn record_net_worth(&mut self, net_worth: i64) {
+        self.peak_net_worth = self.peak_net_worth.max(net_worth);
+        let drop = self.peak_net_worth - net_worth;
+        self.max_drawdown = self.max_drawdown.max(drop);
+        if self.peak_net_worth > 0 {
+            self.max_drawdown_bps = self.max_drawdown_bps.max(drop * 10000 / self.peak_net_worth);
+        }
+    }
+
+    /// The alerts the player's net worth passed since it was `prev_net_worth`.
+    pub fn check_alerts(&self, prev_net_worth: i64) -> Vec<AlertCrossed> {
+        let net_worth = self.player.net_worth(&self.stocks);
+        self.alerts.iter().filter_map(|&level| {
+            if prev_net_worth < level && net_worth >= level {
+                Some(AlertCrossed { level, up: true })
+            } else if prev_net_worth >= level && net_worth < level {
+                Some(AlertCrossed { level, up: false })
+            } else {
+                None
+            }
+        }).collect()
+    }
+
+    /// Writes a note in the journal against the current turn.
+    pub fn add_journal_note(&mut self, note: String) {
+        self.journal.push((self.turn, note));
+    }
+
+    /// Adds a headline to the news feed, dropping the oldest ones past `news_len`.
+    pub fn push_news(&mut self, headline: String) {
+        self.news.push_back(headline);
+        while self.news.len() > self.news_len {
+            self.news.pop_front();
+        }
+    }
+
+    /// Lists a new stock from `added_stock_range` if the market has none left, since the
+    /// game can't be played without one. Returns the new stock's name.
+    pub fn ensure_market<R: Rng>(&mut self, rng: &mut R) -> Option<String> {
+        if !self.stocks.is_empty() { return None; }
+
+        let name = self.new_stock_name(rng);
+        let stock = self.generate_added_stock(rng, name.clone());
+        self.stocks.push(stock);
+        Some(name)
+    }
+
+    /// Charges the player `current_add_stock_cost` and lists a new stock from
+    /// `added_stock_range`. Either both happen or neither does: will return
+    /// `TransactionError::MarketFull` if the market is at `max_stocks`, or
+    /// `TransactionError::InsufficientFunds` if the player can't pay, without charging
+    /// anything.
+    pub fn add_random_stock<R: Rng>(&mut self, rng: &mut R) -> Result<&Stock, TransactionError> {
+        if self.max_stocks.is_some_and(|max| self.stocks.len() >= max) {
+            return Err(TransactionError::MarketFull);
+        }
+        let cost = self.current_add_stock_cost();
+        self.player.withdraw_for(cost, CashFlowReason::AddStockCost)?;
+        self.invalidate_net_worth();
+
+        let name = self.new_stock_name(rng);
+        let stock = self.generate_added_stock(rng, name);
+        self.stocks.push(stock);
+        self.turns_since_stock_added = 0;
+        // Safe unwrap because a stock was just pushed
+        Ok(self.stocks.last().unwrap())
+    }
+
+    /// Replaces the market with the same number of new stocks from `starting_stock_range`.
+    /// Meant for before the first turn, when the player doesn't hold anything yet; the
+    /// player isn't touched. Each re-roll draws from its own stream of the game's seed,
+    /// counted in `rerolls`, so the market can be rebuilt from the seed and the count.
+    pub fn reroll_market(&mut self) {
+        self.rerolls += 1;
+        let mut rng = GameRng::seed_from_u64(self.seed);
+        rng.set_stream(self.rerolls as u64);
+
+        let mut stocks = Vec::new();
+        for id in 0..self.stocks.len() as i64 {
+            let name = self.stock_name(&mut rng, &stocks);
+            stocks.push(self.starting_stock_range.generate_trending(&mut rng, id, name,
+                                                                    self.start_direction_bps));
+        }
+        self.stocks = stocks;
+        self.invalidate_net_worth();
+    }
+
+    /// Generates a stock from `added_stock_range`, trending as `start_direction_bps` allows.
+    pub fn generate_added_stock<R: Rng>(&self, rng: &mut R, name: String) -> Stock {
+        self.added_stock_range.generate_trending(rng, self.next_stock_id(), name,
+                                                 self.start_direction_bps)
+    }
+
+    /// A name for a stock being listed, from `name_words` if there are any, and unique if
+    /// `unique_stock_names` is set.
+    pub fn new_stock_name<R: Rng>(&self, rng: &mut R) -> String {
+        self.stock_name(rng, &self.stocks)
+    }
+
+    /// Like `new_stock_name`, but only avoiding the names of `stocks`.
+    fn stock_name<R: Rng>(&self, rng: &mut R, stocks: &[Stock]) -> String {
+        let generate = |rng: &mut R| match &self.name_words {
+            Some(words) => words.generate(rng),
+            None => generate_name(rng),
+        };
+        if self.unique_stock_names {
+            generate_unique_name_with(rng, stocks, generate)
+        } else {
+            generate(rng)
+        }
+    }
+
+    /// The id the next added stock should get. Ids are never reused, even once a stock is
+    /// delisted.
+    pub fn next_stock_id(&self) -> i64 {
+        self.stocks.iter().map(|s| s.id() + 1).max().unwrap_or(0).max(self.stock_id_floor)
+    }
+
+    /// Plays one of the `observation_turns`: the market moves and bankrupt stocks are
+    /// reset, but the player collects nothing. Returns the stocks that went bankrupt, or
+    /// `None` if there are no observation turns left.
+    pub fn observe_turn<R: Rng>(&mut self, rng: &mut R) -> Option<Vec<Bankruptcy>> {
+        if self.observation_turns == 0 { return None; }
+        self.observation_turns -= 1;
+        self.advance_market(rng);
+        Some(self.process_bankruptcies())
+    }
+
+    /// Varies every stock and moves the game on to the next turn.
+    pub fn advance_market<R: Rng>(&mut self, rng: &mut R) {
+        self.invalidate_net_worth();
+        self.previous_net_worth = Some(self.player.net_worth(&self.stocks));
+        if self.sentiment_enabled {
+            self.update_sentiment(rng);
+            for s in self.stocks.iter_mut() { s.bias(self.sentiment / 2); }
+        }
+        self.market.set_volatility_scale(self.ramp_scale_bps());
+        self.market.step(&mut self.stocks, rng);
+        for s in self.stocks.iter_mut() {
+            s.age();
+            if let Some(threshold) = self.delist_below {
+                s.update_low_turns(threshold);
+            }
+            if let Some(threshold) = self.halt_threshold_bps {
+                s.update_halt(threshold);
+            }
+        }
+        self.turns_since_stock_added += 1;
+    }
+
+    /// Moves the market sentiment randomly, pulling it a tenth of the way back to neutral
+    /// each turn.
+    fn update_sentiment<R: Rng>(&mut self, rng: &mut R) {
+        let step = rng.gen_range(-SENTIMENT_MAX / 10..=SENTIMENT_MAX / 10);
+        self.sentiment = (self.sentiment * 9 / 10 + step).clamp(-SENTIMENT_MAX, SENTIMENT_MAX);
+    }
+
+    /// Resets every stock whose value reached or went below 0, wiping out the player's
+    /// holdings in it. If the player flagged the stock for rebuying, as many shares as the
+    /// holding was worth before the crash are bought back at the reset ask price, when
+    /// affordable. During the bankruptcy grace period, stocks are raised to a value of 1
+    /// instead.
+    pub fn process_bankruptcies(&mut self) -> Vec<Bankruptcy> {
+        self.invalidate_net_worth();
+        let mut result = Vec::new();
+        if self.in_bankruptcy_grace() {
+            for s in self.stocks.iter_mut() { s.clamp_value(); }
+            return result;
+        }
+
+        let bankrupt: Vec<i64> = self.stocks.iter()
+            .filter(|s| s.is_bankrupt())
+            .map(|s| s.id())
+            .collect();
+        for id in bankrupt {
+            let s = match self.stock_by_id_mut(id) {
+                Some(s) => s,
+                None => continue,
+            };
+            // The value before the crash, or the reset value if the stock has no history
+            let before = s.history().iter().rev().nth(1).copied().filter(|&v| v > 0);
+            s.reset();
+            let s = match self.stock_by_id(id) {
+                Some(s) => s,
+                None => continue,
+            };
+            let name = s.name().to_string();
+            let held = self.player.stock_balance(s);
+            let worth = held * before.unwrap_or_else(|| s.value());
+            let price = self.ask_price(s);
+            let flagged = self.player.rebuys_after_bankruptcy(s);
+            self.player.reset_stock_by_id(id);
+
+            let mut rebought = None;
+            let amount = if price > 0 { self.round_to_lot(worth / price) } else { 0 };
+            if flagged && amount > 0 && self.buy_at(id, price, amount).is_ok() {
+                rebought = Some(amount);
+            }
+
+            result.push(Bankruptcy { name, rebought });
+        }
+
+        result
+    }
+
+    /// Whether the game is still in its first `bankruptcy_grace_turns` turns, when stocks
+    /// can't go bankrupt.
+    pub fn in_bankruptcy_grace(&self) -> bool {
+        self.bankruptcy_grace_turns > 0 && self.turn <= self.bankruptcy_grace_turns
+    }
+
+    /// Carries out the player's recurring buys, buying as many whole lots as each one's
+    /// money allows at the ask price. Buys the player can't afford from their available
+    /// balance this turn are skipped. The buys are recorded like the player's own trades.
+    /// Returns the names of the stocks bought and how many shares of each.
+    pub fn process_recurring_buys(&mut self) -> Vec<(String, i64)> {
+        self.invalidate_net_worth();
+        let mut result = Vec::new();
+
+        for (id, money) in self.player.recurring_buys().to_vec() {
+            let (name, halted, price) = match self.stock_by_id(id) {
+                Some(s) => (s.name().to_string(), s.is_halted(), self.ask_price(s)),
+                None => continue,
+            };
+            if halted || price <= 0 || money > self.player.available_balance() {
+                continue;
+            }
+
+            let amount = self.round_to_lot(money / price);
+            if amount > 0 && self.buy_at(id, price, amount).is_ok() {
+                result.push((name, amount));
+            }
+        }
+
+        result
+    }
+
+    /// Removes every stock that has stayed below `delist_below` for `delist_after_turns`
+    /// turns. The player is paid out for their shares at the stock's last value, and their
+    /// watchlist, rebuy and recurring buy settings for the stock are dropped.
+    pub fn process_delistings(&mut self) -> Vec<Delisting> {
+        self.invalidate_net_worth();
+        let mut result = Vec::new();
+        if self.delist_below.is_none() { return result; }
+
+        let after = self.delist_after_turns.max(1);
+        let player = &mut self.player;
+        let floor = &mut self.stock_id_floor;
+        self.stocks.retain(|s| {
+            if s.low_turns() < after { return true; }
+
+            let payout = if s.is_bankrupt() {
+                player.reset_stock(s);
+                0
+            } else {
+                player.liquidate_stock(s.id(), s.value())
+            };
+            player.forget_stock(s.id());
+            *floor = (*floor).max(s.id() + 1);
+            result.push(Delisting { name: s.name().to_string(), payout });
+            false
+        });
+
+        result
+    }
+}
+
+/// The settings a new game starts with. Make one with `GameBuilder::new` or
+/// `Game::to_builder`, change its fields and then `build` the game.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameBuilder {
+    pub goal: i64,
+    pub income: i64,
+    pub seed: u64,
+    pub initial_balance: Option<i64>,
+    pub add_stock_cost: i64,
+    pub income_upgrade_cost: i64,
+    pub split_threshold: Option<i64>,
+    pub split_ratio: i64,
+    pub starting_stock_range: StockRange,
+    pub added_stock_range: StockRange,
+    pub start_direction_bps: i64,
+    pub max_stocks: Option<usize>,
+    pub spread_bps: i64,
+    pub free_trades_per_turn: u32,
+    pub confirm_end_turn: bool,
+    pub unique_stock_names: bool,
+    pub auto_upgrade_income: bool,
+    pub end_on_goal: bool,
+    pub observation_turns: u32,
+    pub autosave_interval: u32,
+    pub max_saves: Option<usize>,
+    pub slow_turn_minutes: Option<i64>,
+    pub verbosity: Verbosity,
+    pub add_stock_cost_decay_bps: i64,
+    pub add_stock_cost_floor: i64,
+    pub delist_below: Option<i64>,
+    pub delist_after_turns: u32,
+    pub income_event_chance_bps: i64,
+    pub express_mode: bool,
+    pub halt_threshold_bps: Option<i64>,
+    pub news_len: usize,
+    pub spin_off_chance_bps: i64,
+    pub spin_off_fraction_bps: i64,
+    pub bankruptcy_grace_turns: u32,
+    pub tutorial: bool,
+    pub min_trade: i64,
+    pub trade_cooldown: bool,
+    pub action_cap: Option<u32>,
+    pub settlement_turns: u32,
+    pub flash_crash_chance_bps: i64,
+    pub flash_crash_drop_bps: i64,
+    pub flash_crash_recovery_bps: i64,
+    pub ramp_bps: i64,
+    pub ramp_cap_bps: i64,
+    pub loss_circuit_bps: Option<i64>,
+    pub sentiment_enabled: bool,
+    pub bust_threshold: Option<i64>,
+    pub show_stock_ids: bool,
+    pub stock_order: SortKey,
+    pub rounding_mode: RoundingMode,
+    pub control_threshold: Option<i64>,
+    pub control_bonus_bps: i64,
+    pub show_hints: bool,
+    pub hide_empty_holdings: bool,
+    pub name_words: Option<NameWords>,
+    pub alerts: Vec<i64>,
+    /// Fields of the game the builder was made from that this version doesn't know about.
+    pub extra: serde_json::Map<String, serde_json::Value>,
+}
+
+impl GameBuilder {
+    /// A builder with the same settings `Game::new` gives a game.
+    pub fn new(goal: i64, income: i64) -> Self {
+        Game::new(goal, income, Vec::new()).to_builder()
+    }
+
+    /// Builds a game with these settings, an empty market and a fresh player.
+    pub fn build(&self) -> Game {
+        let mut game = Game::new(self.goal, self.income, Vec::new());
+        game.goal = self.goal;
+        game.initial_income = self.income;
+        game.seed = self.seed;
+        game.initial_balance = self.initial_balance;
+        game.add_stock_cost = self.add_stock_cost;
+        game.income_upgrade_cost = self.income_upgrade_cost;
+        game.split_threshold = self.split_threshold;
+        game.split_ratio = self.split_ratio;
+        game.starting_stock_range = self.starting_stock_range;
+        game.added_stock_range = self.added_stock_range;
+        game.start_direction_bps = self.start_direction_bps;
+        game.max_stocks = self.max_stocks;
+        game.spread_bps = self.spread_bps;
+        game.free_trades_per_turn = self.free_trades_per_turn;
+        game.confirm_end_turn = self.confirm_end_turn;
+        game.unique_stock_names = self.unique_stock_names;
+        game.auto_upgrade_income = self.auto_upgrade_income;
+        game.end_on_goal = self.end_on_goal;
+        game.observation_turns = self.observation_turns;
+        game.autosave_interval = self.autosave_interval;
+        game.max_saves = self.max_saves;
+        game.slow_turn_minutes = self.slow_turn_minutes;
+        game.verbosity = self.verbosity;
+        game.add_stock_cost_decay_bps = self.add_stock_cost_decay_bps;
+        game.add_stock_cost_floor = self.add_stock_cost_floor;
+        game.delist_below = self.delist_below;
+        game.delist_after_turns = self.delist_after_turns;
+        game.income_event_chance_bps = self.income_event_chance_bps;
+        game.express_mode = self.express_mode;
+        game.halt_threshold_bps = self.halt_threshold_bps;
+        game.news_len = self.news_len;
+        game.spin_off_chance_bps = self.spin_off_chance_bps;
+        game.spin_off_fraction_bps = self.spin_off_fraction_bps;
+        game.bankruptcy_grace_turns = self.bankruptcy_grace_turns;
+        game.tutorial = self.tutorial;
+        game.min_trade = self.min_trade;
+        game.trade_cooldown = self.trade_cooldown;
+        game.action_cap = self.action_cap;
+        game.settlement_turns = self.settlement_turns;
+        game.flash_crash_chance_bps = self.flash_crash_chance_bps;
+        game.flash_crash_drop_bps = self.flash_crash_drop_bps;
+        game.flash_crash_recovery_bps = self.flash_crash_recovery_bps;
+        game.ramp_bps = self.ramp_bps;
+        game.ramp_cap_bps = self.ramp_cap_bps;
+        game.loss_circuit_bps = self.loss_circuit_bps;
+        game.sentiment_enabled = self.sentiment_enabled;
+        game.bust_threshold = self.bust_threshold;
+        game.show_stock_ids = self.show_stock_ids;
+        game.stock_order = self.stock_order;
+        game.rounding_mode = self.rounding_mode;
+        game.control_threshold = self.control_threshold;
+        game.control_bonus_bps = self.control_bonus_bps;
+        game.show_hints = self.show_hints;
+        game.hide_empty_holdings = self.hide_empty_holdings;
+        game.name_words = self.name_words.clone();
+        game.alerts = self.alerts.clone();
+        game.extra = self.extra.clone();
+        game.reset_player();
+        game
+    }
+}
+
+/// Something the player can do on their turn, for `Game::apply_action`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Action {
+    Buy { stock_id: i64, amount: i64 },
+    Sell { stock_id: i64, amount: i64 },
+    IncreaseIncome,
+    AddStock,
+    EndTurn,
+    /// Stops playing. Doesn't change the game; saving is up to the caller.
+    Quit,
+}
+
+/// What came of a `Game::apply_action`.
+pub enum ActionResult {
+    /// The action went through.
+    Done,
+    /// A new stock was listed, with this id.
+    StockAdded(i64),
+    /// The turn ended, with this outcome.
+    TurnEnded(Box<TickOutcome>),
+    Quit,
+    /// The action couldn't be done, and nothing changed.
+    Failed(TransactionError),
+}
+
+/// Something the player can be asked to confirm before it's done.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Confirmation {
+    IncreaseIncome,
+    AddStock,
+    EndTurn,
+    ResetPortfolio,
+}
+
+/// Everything that happened during a `Game::tick`.
+pub struct TickOutcome {
+    /// The income the player collected.
+    pub income: i64,
+    /// Whether an income increase was bought automatically.
+    pub income_upgraded: bool,
+    /// The names of the stocks whose value changed, with their values before and after.
+    pub price_moves: Vec<(String, i64, i64)>,
+    pub events: Vec<Event>,
+    pub bankruptcies: Vec<Bankruptcy>,
+    /// The names of the stocks that split and the ratio they split at.
+    pub splits: Vec<(String, i64)>,
+    /// The names of the stock that spun off a new one and of the new stock.
+    pub spin_off: Option<(String, String)>,
+    pub delistings: Vec<Delisting>,
+    /// The names of the stocks bought by recurring buys and how many shares of each.
+    pub recurring_buys: Vec<(String, i64)>,
+    /// Whether the player has reached the goal.
+    pub won: bool,
+    /// Whether the player has gone bust.
+    pub bust: bool,
+    pub alerts: Vec<AlertCrossed>,
+    /// Whether `Game::audit_turn` could account for all the money that moved this turn.
+    pub audit: Result<(), AuditError>,
+}
+
+impl TickOutcome {
+    /// A notification for each thing that happened, in the order they happened.
+    pub fn notifications(&self) -> Vec<Notification> {
+        let mut result = Vec::new();
+        let mut push = |verbosity, text| result.push(Notification { verbosity, text });
+
+        if self.income_upgraded {
+            push(Verbosity::Normal, "Your income was increased automatically.".to_string());
+        }
+
+        for (name, before, after) in &self.price_moves {
+            push(Verbosity::Verbose,
+                 format!("Stock '{}' moved from {} to {}.", name, before, after));
+        }
+
+        for event in &self.events {
+            let text = match *event {
+                Event::IncomeModifier { multiplier_bps, turns } if multiplier_bps >= 10000 => {
+                    format!("Bonus payday! Your income changes by {} for {} turns.",
+                            bps_percent(multiplier_bps - 10000), turns)
+                }
+                Event::IncomeModifier { multiplier_bps, turns } => {
+                    format!("Slow week... Your income changes by {} for {} turns.",
+                            bps_percent(multiplier_bps - 10000), turns)
+                }
+                Event::FlashCrash { drop_bps, .. } => {
+                    format!("Flash crash! Every stock moved {}.", bps_percent(-drop_bps))
+                }
+                Event::Recovery { recovery_bps } => {
+                    format!("The market bounced back! Every stock moved {}.",
+                            bps_percent(recovery_bps))
+                }
+            };
+            push(Verbosity::Normal, text);
+        }
+
+        for b in &self.bankruptcies {
+            push(Verbosity::Quiet, format!("Stock '{}' went bankrupt!", b.name));
+            if let Some(amount) = b.rebought {
+                push(Verbosity::Quiet, format!("Bought back {} shares of '{}'.", amount, b.name));
+            }
+        }
+
+        for (name, ratio) in &self.splits {
+            push(Verbosity::Normal, format!("Stock '{}' split {}-for-1!", name, ratio));
+        }
+
+        if let Some((parent, child)) = &self.spin_off {
+            push(Verbosity::Normal, format!("Stock '{}' spun off '{}'! Holders got a share of it \
+                                             for each share they held.", parent, child));
+        }
+
+        for d in &self.delistings {
+            push(Verbosity::Normal, format!("Stock '{}' was delisted! You were paid {} for your \
+                                             shares.", d.name, d.payout));
+        }
+
+        for (name, amount) in &self.recurring_buys {
+            push(Verbosity::Normal,
+                 format!("Bought {} shares of '{}' as a recurring buy.", amount, name));
+        }
+
+        for a in &self.alerts {
+            if a.up {
+                push(Verbosity::Normal, format!("Your net worth passed {}!", a.level));
+            } else {
+                push(Verbosity::Normal, format!("Your net worth fell below {}.", a.level));
+            }
+        }
+
+        result
+    }
+
+    /// A line describing each thing that happened, in the order they happened. Price
+    /// moves are left out.
+    pub fn headlines(&self) -> Vec<String> {
+        filter_notifications(self.notifications(), Verbosity::Normal).into_iter()
+            .map(|n| n.text)
+            .collect()
+    }
+}
+
+/// How much of what happens each turn gets announced.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum Verbosity {
+    /// Only bankruptcies.
+    Quiet,
+    /// Everything except price moves.
+    #[default]
+    Normal,
+    /// Everything, including every stock's price move.
+    Verbose,
+}
+
+/// Something that happened during a turn that can be announced to the player.
+pub struct Notification {
+    /// The lowest verbosity this is announced at.
+    pub verbosity: Verbosity,
+    pub text: String,
+}
+
+/// Keeps only the notifications that get announced at `verbosity`.
+pub fn filter_notifications(notifications: Vec<Notification>, verbosity: Verbosity)
+    -> Vec<Notification> {
+    notifications.into_iter().filter(|n| n.verbosity <= verbosity).collect()
+}
+
+/// The player's money at some point, for `Game::audit_turn`.
+pub struct AuditSnapshot {
+    pub balance: i64,
+    /// How many cash flows the player had.
+    pub cash_flows: usize,
+    pub trade_cash: i64,
+}
+
+/// A problem `Game::audit_turn` found with a turn's money.
+#[derive(Debug, PartialEq, Eq)]
+pub enum AuditError {
+    /// The balance changed by this much more than trades and cash flows account for.
+    Unexplained(i64),
+    /// Money moved for a reason a turn shouldn't cause.
+    UnexpectedCashFlow(CashFlowReason),
+}
+
+/// A net worth alert that was passed.
+pub struct AlertCrossed {
+    pub level: i64,
+    /// Whether net worth rose past the level, rather than fell below it.
+    pub up: bool,
+}
+
+/// Formats basis points as a percentage with `format_percent`, leaving off the decimals
+/// when it's a whole percent.
+fn bps_percent(bps: i64) -> String {
+    format_percent(bps as f64 / 100.0, if bps % 100 == 0 { 0 } else { 2 })
+}
+
+/// Totals for a whole game, from `Game::summary_stats`.
+#[derive(Debug, Serialize)]
+pub struct SummaryStats {
+    /// The seed the game was started from, so it can be played again.
+    pub seed: u64,
+    pub goal: i64,
+    /// Whether the player reached the goal at some point.
+    pub reached_goal: bool,
+    /// The turn the goal was first reached, if it was.
+    pub goal_turn: Option<u32>,
+    pub turns_played: u32,
+    pub final_net_worth: i64,
+    pub peak_net_worth: i64,
+    pub max_drawdown: i64,
+    /// `max_drawdown` in basis points of the peak it fell from.
+    pub max_drawdown_bps: i64,
+    pub realized_pl: i64,
+    pub trades: u32,
+    /// The most profit made on a single sale, if anything was sold.
+    pub best_trade: Option<i64>,
+    /// The least profit made on a single sale, if anything was sold.
+    pub worst_trade: Option<i64>,
+    /// The most minutes spent on a single turn.
+    pub longest_turn_minutes: i64,
+}
+
+impl SummaryStats {
+    /// A line with the seed and how the game went, for sharing, like
+    /// "Seed: 1234 — reached 1000000 in 42 turns".
+    pub fn share_line(&self) -> String {
+        let turns = |n| if n == 1 { "turn" } else { "turns" };
+        match self.goal_turn {
+            Some(turn) => format!("Seed: {} — reached {} in {} {}", self.seed, self.goal,
+                                  turn, turns(turn)),
+            None => format!("Seed: {} — finished with {} after {} {}", self.seed,
+                            self.final_net_worth, self.turns_played, turns(self.turns_played)),
+        }
+    }
+}
+
+/// How many whole minutes passed from `started` to `ended`. 0 if `ended` is earlier.
+pub fn minutes_between(started: DateTime<Local>, ended: DateTime<Local>) -> i64 {
+    (ended - started).num_minutes().max(0)
+}
+
+/// A stock removed from the market during `Game::process_delistings`.
+pub struct Delisting {
+    pub name: String,
+    /// How much the player was paid for their shares.
+    pub payout: i64,
+}
+
+/// A stock that went bankrupt during `Game::process_bankruptcies`.
+pub struct Bankruptcy {
+    pub name: String,
+    /// The amount of shares automatically bought back, if any.
+    pub rebought: Option<i64>,
+}
+
+/// Stocks whose recent returns have a standard deviation above this are called out as
+/// volatile by `hints`.
+pub const VOLATILE_THRESHOLD: f64 = 0.2;
+
+/// How many stocks the tutorial game starts with.
+pub const TUTORIAL_STOCKS: i64 = 3;
+
+/// Builds the tutorial game: a few calm stocks and a goal income alone reaches in a
+/// handful of turns. Random events are left off, as they are in any new game.
+pub fn tutorial_game<R: Rng>(rng: &mut R, seed: u64) -> Game {
+    let range = StockRange::new(10, 30, 1, 2);
+    let mut stocks = Vec::new();
+    for id in 0..TUTORIAL_STOCKS {
+        let name = generate_unique_name(rng, &stocks);
+        stocks.push(range.generate(rng, id, name));
+    }
+
+    let mut game = Game::new(5000, 1000, stocks);
+    game.seed = seed;
+    game.tutorial = true;
+    game
+}
+
+/// The tutorial's step by step guidance, based on the turn and what the player has done.
+pub fn tutorial_hints(game: &Game) -> Vec<String> {
+    let mut result = Vec::new();
+    let player = &game.player;
+    let holds_stock = game.stocks.iter().any(|s| player.stock_balance(s) > 0);
+
+    if game.turn == 0 {
+        if holds_stock {
+            result.push("Nice! Now choose End turn to see how prices move.".to_string());
+        } else {
+            result.push(format!("Welcome! Your goal is a net worth over {}. Start by \
+                                 choosing Buy stocks and buying a few shares.", game.goal));
+        }
+        return result;
+    }
+
+    result.push(format!("Every turn you collect {} in income, and the stocks you own \
+                         change in value.", player.income()));
+    if player.can_increase_income(game.income_upgrade_cost) {
+        result.push(format!("Try Increase income. It costs {}, but you'll earn more every \
+                             turn after.", game.income_upgrade_cost));
+    }
+    if holds_stock && game.turn >= 2 {
+        result.push("If a stock has gone up, selling it locks in the profit.".to_string());
+    }
+    if game.turn >= 3 {
+        result.push("Print net worth breakdown shows how close you are to the \
+                     goal.".to_string());
+    }
+
+    result
+}
+
+/// Below this ratio of income to net worth, `hints` suggests increasing income.
+pub const LOW_INCOME_RATIO: f64 = 0.01;
+
+/// Above this ratio of income to net worth, `hints` suggests investing rather than
+/// increasing income further.
+pub const HIGH_INCOME_RATIO: f64 = 0.5;
+
+/// Tips for new players that fit the state of the game.
+pub fn hints(game: &Game) -> Vec<String> {
+    let mut result = Vec::new();
+    let player = &game.player;
+
+    let net_worth = player.net_worth(&game.stocks);
+    if net_worth > 0 && player.balance() * 10 > net_worth * 9
+        && !player.affordable_stocks(&game.stocks).is_empty() {
+        result.push("Your cash is sitting idle. Consider buying some stocks.".to_string());
+    }
+    if player.can_increase_income(game.income_upgrade_cost) {
+        result.push("You can afford an income increase.".to_string());
+    }
+    let income_ratio = player.income_to_net_worth(&game.stocks);
+    if net_worth > 0 && income_ratio < LOW_INCOME_RATIO {
+        result.push("Your income is low compared to your wealth. Consider increasing \
+                     it.".to_string());
+    } else if income_ratio > HIGH_INCOME_RATIO
+        && player.can_increase_income(game.income_upgrade_cost) {
+        result.push("Your income is already high compared to your wealth. Stocks may grow \
+                     your money faster than another income increase.".to_string());
+    }
+    if player.realized_pl() > 0 {
+        for (id, loss) in player.loss_positions(&game.stocks) {
+            // Safe unwrap because loss_positions only returns ids from the stocks given
+            let name = game.stock_by_id(id).unwrap().name();
+            result.push(format!("'{}' is down {}. Selling it would offset some of the {} \
+                                 profit you've locked in.", name, loss, player.realized_pl()));
+        }
+    }
+    for s in &game.stocks {
+        if s.realized_volatility().is_some_and(|v| v > VOLATILE_THRESHOLD) {
+            result.push(format!("'{}' is highly volatile. Its price can swing a lot in a \
+                                 turn.", s.name()));
+        }
+    }
+
+    result
+}
+
+/// How the game changed between two saves. Changes are the later save minus the earlier.
+pub struct GameDiff {
+    pub balance: i64,
+    pub income: i64,
+    pub net_worth: i64,
+    pub stocks: Vec<StockDiff>,
+}
+
+/// How a stock changed between two saves. `None` means the stock wasn't in that save.
+pub struct StockDiff {
+    pub name: String,
+    pub shares: (Option<i64>, Option<i64>),
+    pub value: (Option<i64>, Option<i64>),
+}
+
+#[derive(Deserialize)]
+struct StockDef {
+    name: String,
+    value: i64,
+    variation: i64,
+    #[serde(default)]
+    annual_drift_bps: i64,
+    /// A CSV of prices for the stock to follow, relative to the stock file.
+    #[serde(default)]
+    price_series: Option<PathBuf>,
+}
+
+#[derive(Hash)]
+pub struct Save {
+    pub path: PathBuf,
+    pub name: String,
+    /// Whether the player marked the save as a favorite.
+    pub favorite: bool,
+}
+
+impl fmt::Display for Save {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.favorite {
+            write!(f, "{} (favorite)", self.name)
+        } else {
+            write!(f, "{}", self.name)
+        }
+    }
+}
+
+/// A format games can be saved in.
+pub trait SaveCodec {
+    /// Turns a `Game` into the bytes of a save file.
+    fn encode(&self, game: &Game) -> Result<Vec<u8>, Error>;
+
+    /// Turns the bytes of a save file back into a `Game`.
+    fn decode(&self, bytes: &[u8]) -> Result<Game, Error>;
+}
+
+/// Saves games as JSON.
+pub struct JsonCodec;
+
+impl SaveCodec for JsonCodec {
+    fn encode(&self, game: &Game) -> Result<Vec<u8>, Error> {
+        Ok(game.to_json()?.into_bytes())
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Game, Error> {
+        Ok(serde_json::from_slice(bytes)?)
+    }
+}
+
+/// Saves games with bincode. Needs the `binary-saves` feature.
+///
+/// Bincode can't read the flattened `Game::extra` map back on its own, so the game goes
+/// through its JSON, stored as a `BinaryValue`.
+#[cfg(feature = "binary-saves")]
+pub struct BincodeCodec;
+
+#[cfg(feature = "binary-saves")]
+impl SaveCodec for BincodeCodec {
+    fn encode(&self, game: &Game) -> Result<Vec<u8>, Error> {
+        let value: serde_json::Value = serde_json::from_str(&game.to_json()?)?;
+        Ok(bincode::serialize(&BinaryValue::from(value))?)
+    }
+
+    fn decode(&self, bytes: &[u8]) -> Result<Game, Error> {
+        let value: BinaryValue = bincode::deserialize(bytes)?;
+        Ok(serde_json::from_value(value.into())?)
+    }
+}
+
+/// A JSON value in a form bincode can read back without knowing its shape ahead of time.
+#[cfg(feature = "binary-saves")]
+#[derive(Serialize, Deserialize)]
+enum BinaryValue {
+    Null,
+    Bool(bool),
+    Int(i64),
+    UInt(u64),
+    Float(f64),
+    String(String),
+    Array(Vec<BinaryValue>),
+    Object(Vec<(String, BinaryValue)>),
+}
+
+#[cfg(feature = "binary-saves")]
+impl From<serde_json::Value> for BinaryValue {
+    fn from(value: serde_json::Value) -> Self {
+        use serde_json::Value;
+        match value {
+            Value::Null => BinaryValue::Null,
+            Value::Bool(b) => BinaryValue::Bool(b),
+            Value::Number(n) => match (n.as_i64(), n.as_u64()) {
+                (Some(i), _) => BinaryValue::Int(i),
+                (_, Some(u)) => BinaryValue::UInt(u),
+                // Safe unwrap because a number that isn't an integer is a float
+                _ => BinaryValue::Float(n.as_f64().unwrap()),
+            },
+            Value::String(s) => BinaryValue::String(s),
+            Value::Array(a) => BinaryValue::Array(a.into_iter().map(Into::into).collect()),
+            Value::Object(o) => BinaryValue::Object(o.into_iter()
+                .map(|(k, v)| (k, v.into()))
+                .collect()),
+        }
+    }
+}
+
+#[cfg(feature = "binary-saves")]
+impl From<BinaryValue> for serde_json::Value {
+    fn from(value: BinaryValue) -> Self {
+        use serde_json::Value;
+        match value {
+            BinaryValue::Null => Value::Null,
+            BinaryValue::Bool(b) => Value::Bool(b),
+            BinaryValue::Int(i) => Value::from(i),
+            BinaryValue::UInt(u) => Value::from(u),
+            BinaryValue::Float(f) => Value::from(f),
+            BinaryValue::String(s) => Value::String(s),
+            BinaryValue::Array(a) => Value::Array(a.into_iter().map(Into::into).collect()),
+            BinaryValue::Object(o) => Value::Object(o.into_iter()
+                .map(|(k, v)| (k, v.into()))
+                .collect()),
+        }
+    }
+}
+
+/// Picks the codec to use for a save file based on its extension. Files ending in ".json"
+/// are JSON, and with the `binary-saves` feature, files ending in ".bin" use bincode. Will
+/// return `Error::UnsupportedFormat` if no codec handles the extension.
+pub fn codec_for(path: &Path) -> Result<&'static dyn SaveCodec, Error> {
+    match path.extension().and_then(|e| e.to_str()) {
+        Some("json") => Ok(&JsonCodec),
+        #[cfg(feature = "binary-saves")]
+        Some("bin") => Ok(&BincodeCodec),
+        _ => Err(Error::UnsupportedFormat(path.to_path_buf())),
+    }
+}
+
+fn json_type(value: &serde_json::Value) -> &'static str {
+    match value {
+        serde_json::Value::Null => "null",
+        serde_json::Value::Bool(_) => "boolean",
+        serde_json::Value::Number(_) => "number",
+        serde_json::Value::String(_) => "string",
+        serde_json::Value::Array(_) => "array",
+        serde_json::Value::Object(_) => "object",
+    }
+}
+
+/// Describes the save format as JSON: the version of the game writing it and every
+/// field of a `Game` along with its type and default value.
+pub fn schema() -> Result<String, Error> {
+    // Goes through the JSON text since serde_json can't turn the RNG's u128 into a Value
+    let default: serde_json::Value =
+        serde_json::from_str(&Game::new(1_000_000, 1000, Vec::new()).to_json()?)?;
+    let mut fields = serde_json::Map::new();
+
+    if let serde_json::Value::Object(map) = default {
+        for (key, value) in map {
+            fields.insert(key, serde_json::json!({
+                "type": json_type(&value),
+                "default": value,
+            }));
+        }
+    }
+
+    Ok(serde_json::to_string_pretty(&serde_json::json!({
+        "version": env!("CARGO_PKG_VERSION"),
+        "fields": fields,
+    }))?)
+}
+
+/// Turns a `&Path` into a `Game`, sanitizing it with `Game::sanitize`. Will return an
+/// error if there was an issue reading the file at the Path or if there's an issue
+/// decoding it.
+pub fn from_path(path: &Path) -> Result<Game, Error> {
+    let mut game = codec_for(path)?.decode(&fs::read(path)?)?;
+    game.sanitize();
+    Ok(game)
+}
+
+/// Reads a JSON array of stock definitions (`name`, `value`, `variation` and optionally
+/// `annual_drift_bps` and `price_series`) and turns them into stocks with sequential ids.
+/// Any other keys in an entry are ignored. Will return `Error::InvalidStockDef` with the
+/// index of the first malformed entry.
+pub fn load_stock_defs(path: &Path) -> Result<Vec<Stock>, Error> {
+    let entries: Vec<serde_json::Value> = serde_json::from_str(&fs::read_to_string(path)?)?;
+    let mut result = Vec::new();
+
+    for (idx, entry) in entries.into_iter().enumerate() {
+        let def: StockDef = serde_json::from_value(entry)
+            .map_err(|e| Error::InvalidStockDef(idx, e))?;
+
+        if def.value <= 0 {
+            return Err(Error::InvalidStockDef(idx, de::Error::custom("value must be positive")));
+        }
+        if def.variation < 0 {
+            return Err(Error::InvalidStockDef(idx, de::Error::custom("variation must not be negative")));
+        }
+
+        let mut stock = Stock::new(idx as i64, def.name, def.value, def.variation)
+            .with_annual_drift(def.annual_drift_bps);
+        if let Some(series) = def.price_series {
+            let series = path.parent().unwrap_or_else(|| Path::new("")).join(series);
+            stock = stock.with_price_series(load_price_series(&series)?);
+        }
+        result.push(stock);
+    }
+
+    Ok(result)
+}
+
+/// Reads a CSV of prices, one row per turn, using the last column of each row. A first
+/// row that isn't a price is treated as a header and skipped; blank lines are ignored.
+/// Will return `Error::InvalidPriceSeries` with the first row that isn't a positive price.
+pub fn load_price_series(path: &Path) -> Result<Vec<i64>, Error> {
+    let mut prices = Vec::new();
+
+    for (idx, line) in fs::read_to_string(path)?.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() { continue; }
+
+        let field = line.rsplit(',').next().unwrap_or(line).trim();
+        match field.parse::<i64>() {
+            Ok(price) if price > 0 => prices.push(price),
+            Err(_) if idx == 0 => continue,
+            _ => return Err(Error::InvalidPriceSeries(path.to_path_buf(), idx + 1)),
+        }
+    }
+
+    Ok(prices)
+}
+
+pub fn project_save_dir() -> Result<PathBuf, Error> {
+    let pd = ProjectDirs::from("xyz", "Rainbow Asteroids", "Millionaire");
+    let pd = match pd {
+        Some(pd) => pd,
+        None => return Err(Error::PlatformNotSupported),
+    };
+
+    Ok(pd.data_dir().to_path_buf())
+}
+
+/// The extension save files are named with by default. The functions that name or list
+/// saves take an `extension` to use instead, like `dir` for the folder: `None` means this
+/// one. Another extension has to end in ".json" (or ".bin" with the `binary-saves`
+/// feature) for `codec_for` to know how to read the saves, like ".mgame.json".
+pub const DEFAULT_SAVE_EXTENSION: &str = ".save.json";
+
+/// The extension to name and list saves with, with a leading dot added if it's missing.
+fn save_extension(extension: Option<&str>) -> String {
+    match extension {
+        Some(ext) if ext.starts_with('.') => ext.to_string(),
+        Some(ext) => format!(".{}", ext),
+        None => DEFAULT_SAVE_EXTENSION.to_string(),
+    }
+}
+
+/// The extension a save at `path` is named with: the default one if it ends with it, or
+/// else its last extension.
+fn extension_of(path: &Path) -> String {
+    let name = path.file_name().map(|n| n.to_string_lossy().into_owned()).unwrap_or_default();
+    if name.ends_with(DEFAULT_SAVE_EXTENSION) { return DEFAULT_SAVE_EXTENSION.to_string(); }
+    path.extension().map_or_else(String::new, |e| format!(".{}", e.to_string_lossy()))
+}
+
+/// Reads a market written by `Game::export_market`, ready to start a new game with.
+pub fn import_market(json: &str) -> Result<Vec<Stock>, Error> {
+    Ok(serde_json::from_str(json)?)
+}
+
+/// Finds all the potential save files, named with `extension`, and returns them. Will
+/// error if there was some issue reading the directory.
+pub fn saves_in_folder(dir: Option<&Path>, extension: Option<&str>)
+    -> Result<Vec<Save>, Error> {
+    let mut result = Vec::new();
+        
+    let dir = match dir {
+        Some(p) => p.to_path_buf(),
+        None => project_save_dir()?,
+    };
+
+    if dir.exists() && !dir.is_dir() {
+        return Err(Error::NotADirectory(dir));
+    }
+    if !dir.is_dir() {
+        return Err(Error::NotFound(dir));
+    }
+
+    let extension = save_extension(extension);
+    for f in dir.read_dir()? {
+        let f = match f {
+            Ok(de) => de,
+            Err(_) => continue,
+        };
+
+        let file_name = f.file_name().to_string_lossy().into_owned();
+        if let Some(name) = file_name.strip_suffix(&extension) {
+            result.push(Save {
+                favorite: is_favorite(&f.path()),
+                path: f.path(),
+                name: name.to_string(),
+            });
+        }
+    }
+
+    Ok(result)
+}
+
+/// Whether going from `prev` to `current` net worth lost more than `limit_bps` basis
+/// points of `prev`. A loss of exactly the limit doesn't count. Never true if `prev`
+/// wasn't positive.
+pub fn exceeds_loss_limit(prev: i64, current: i64, limit_bps: i64) -> bool {
+    let (prev, current, limit_bps) = (prev as i128, current as i128, limit_bps as i128);
+    prev > 0 && (prev - current) * 10000 > prev * limit_bps
+}
+
+/// Sorts favorite saves before the rest, keeping the order within each group.
+pub fn favorites_first(saves: &mut [Save]) {
+    saves.sort_by_key(|s| !s.favorite);
+}
+
+/// Finds the save that was written to most recently. Returns `None` if there are no
+/// saves.
+pub fn most_recent(dir: Option<&Path>, extension: Option<&str>)
+    -> Result<Option<Save>, Error> {
+    let mut newest = None;
+
+    for save in saves_in_folder(dir, extension)? {
+        let modified = fs::metadata(&save.path)?.modified()?;
+        match &newest {
+            Some((time, _)) if *time >= modified => {}
+            _ => newest = Some((modified, save)),
+        }
+    }
+
+    Ok(newest.map(|(_, save)| save))
+}
+
+/// Get a path to a save file.
+pub fn make_path(dir: Option<&Path>, extension: Option<&str>) -> Result<PathBuf, Error> {
+    let mut dir = match dir {
+        Some(p) => p.to_path_buf(),
+        None => project_save_dir()?,
+    };
+
+    dir.push(format!("{}{}", Local::now().format("%Y-%m-%d %H:%M:%S"),
+                     save_extension(extension)));
+    Ok(dir)
+}
+
+/// A memorable two word name for a save, like "Rainbow Market", drawn from the same
+/// words as stock names.
+pub fn generate_save_name<R: Rng>(rng: &mut R) -> String {
+    generate_name(rng)
+}
+
+/// Get a path to a save file called `name`. If a save by that name already exists, a
+/// number is added to the name.
+pub fn make_named_path(dir: Option<&Path>, name: &str, extension: Option<&str>)
+    -> Result<PathBuf, Error> {
+    let dir = match dir {
+        Some(p) => p.to_path_buf(),
+        None => project_save_dir()?,
+    };
+
+    let extension = save_extension(extension);
+    let mut path = dir.join(format!("{}{}", name, extension));
+    let mut n = 2;
+    while path.exists() {
+        path = dir.join(format!("{} ({}){}", name, n, extension));
+        n += 1;
+    }
+    Ok(path)
+}
+
+/// How many old versions of a save are kept around as backups.
+pub const BACKUP_COUNT: usize = 3;
+
+/// Get the path of a save's backup. Index 0 is the newest backup.
+pub fn backup_path(path: &Path, index: usize) -> PathBuf {
+    let mut name = path.file_name().unwrap().to_os_string();
+    if index == 0 {
+        name.push(".bak");
+    } else {
+        name.push(format!(".bak.{}", index));
+    }
+    path.with_file_name(name)
+}
+
+/// Lists the indexes of the backups that exist for a save, newest first.
+pub fn backups(path: &Path) -> Vec<usize> {
+    (0..BACKUP_COUNT).filter(|i| backup_path(path, *i).exists()).collect()
+}
+
+fn rotate_backups(path: &Path) -> Result<(), Error> {
+    for i in (1..BACKUP_COUNT).rev() {
+        let from = backup_path(path, i - 1);
+        if from.exists() { fs::rename(&from, backup_path(path, i))?; }
+    }
+    fs::copy(path, backup_path(path, 0))?;
+
+    Ok(())
+}
+
+/// Deletes the oldest saves in `dir` until fewer than `max` are left, so a new one can
+/// be added. `keep` is never deleted, and neither are favorites or saves being played. If
+/// those alone already reach `max`, nothing is deleted, since it wouldn't make room.
+/// Returns how many saves were deleted.
+pub fn evict_oldest(dir: &Path, max: usize, keep: &Path, extension: Option<&str>)
+    -> Result<usize, Error> {
+    let mut saves = Vec::new();
+    let mut count = 0;
+    for save in saves_in_folder(Some(dir), extension)? {
+        if save.path == keep { continue; }
+        count += 1;
+        if save.favorite || lock_path(&save.path).exists() { continue; }
+        saves.push((fs::metadata(&save.path)?.modified()?, save.path));
+    }
+    saves.sort();
+    if count - saves.len() >= max { return Ok(0); }
+
+    let mut evicted = 0;
+    for (_, path) in saves {
+        if count < max { break; }
+        delete(&path)?;
+        count -= 1;
+        evicted += 1;
+    }
+
+    Ok(evicted)
+}
+
+/// Saves a game at path. If a different save is already at path, it gets backed up
+/// first. If this is a new save and the folder already has `game.max_saves` saves, the
+/// oldest are deleted to make room.
+pub fn save(path: &Path, game: &Game) -> Result<(), Error> {
+    let bytes = codec_for(path)?.encode(game)?;
+    if let (Some(max), Some(dir)) = (game.max_saves, path.parent()) {
+        if !path.exists() && dir.is_dir() {
+            evict_oldest(dir, max.max(1), path, Some(&extension_of(path)))?;
+        }
+    }
+    if let Ok(previous) = fs::read(path) {
+        if previous != bytes { rotate_backups(path)?; }
+    }
+    fs::write(path, bytes)?;
+    
+    Ok(())
+}
+
+/// Throws away every change made to `game` since it was last saved at path by loading
+/// the save back over it. `game` is left alone if the save can't be read.
+pub fn revert(path: &Path, game: &mut Game) -> Result<(), Error> {
+    *game = from_path(path)?;
+
+    Ok(())
+}
+
+/// Replaces a save with one of its backups.
+pub fn restore_backup(path: &Path, index: usize) -> Result<(), Error> {
+    let backup = backup_path(path, index);
+    if !backup.exists() { return Err(Error::NotFound(backup)); }
+    fs::copy(&backup, path)?;
+
+    Ok(())
+}
+
+/// Copies a save in the same folder as the specified save.
+pub fn copy(path: &Path) -> Result<(), Error> {
+    let copy_name = format!("{} {}", "Copy of", path.file_name().unwrap().to_string_lossy());
+    let mut copy_path = path.to_path_buf();
+    copy_path.set_file_name(copy_name);
+
+    fs::copy(path, &copy_path)?;
+
+    Ok(())
+}
+
+/// Deletes a save along with its backups.
+pub fn delete(path: &Path) -> Result<(), Error> {
+    fs::remove_file(path)?;
+    for i in backups(path) {
+        fs::remove_file(backup_path(path, i))?;
+    }
+    set_favorite(path, false)
+}
+
+/// Renames save file, naming it with `extension`. Returns the save's new path.
+pub fn rename(path: &Path, name: &str, extension: Option<&str>) -> Result<PathBuf, Error> {
+    let name = name.trim();
+    if name.is_empty() { return Err(Error::EmptyFileName); }
+
+    let mut new_path = path.to_path_buf();
+    new_path.set_file_name(format!("{}{}", name, save_extension(extension)));
+    if new_path.exists() { return Err(Error::AlreadyExists); }
+    fs::rename(path, &new_path)?;
+    for i in backups(path) {
+        fs::rename(backup_path(path, i), backup_path(&new_path, i))?;
+    }
+    if is_favorite(path) {
+        fs::rename(favorite_path(path), favorite_path(&new_path))?;
+    }
+
+    Ok(new_path)
+}
+
+/// Moves every save in `from`, along with its backups, into `to`. A save whose name is
+/// already taken in `to` gets a number added to its name. Returns how many saves were
+/// moved.
+pub fn migrate_dir(from: &Path, to: &Path, extension: Option<&str>) -> Result<usize, Error> {
+    if !to.is_dir() { return Err(Error::NotFound(to.to_path_buf())); }
+
+    let saves = saves_in_folder(Some(from), extension)?;
+    let extension = save_extension(extension);
+    for save in &saves {
+        let mut new_path = to.join(format!("{}{}", save.name, extension));
+        let mut n = 2;
+        while new_path.exists() {
+            new_path = to.join(format!("{} ({}){}", save.name, n, extension));
+            n += 1;
+        }
+
+        move_file(&save.path, &new_path)?;
+        for i in backups(&save.path) {
+            move_file(&backup_path(&save.path, i), &backup_path(&new_path, i))?;
+        }
+        if save.favorite {
+            move_file(&favorite_path(&save.path), &favorite_path(&new_path))?;
+        }
+    }
+
+    Ok(saves.len())
+}
+
+/// Renames a file, falling back to copying it for moves between filesystems.
+fn move_file(from: &Path, to: &Path) -> Result<(), Error> {
+    if fs::rename(from, to).is_err() {
+        fs::copy(from, to)?;
+        fs::remove_file(from)?;
+    }
+    Ok(())
+}
+
+/// Get the path of the file that marks a save as a favorite.
+pub fn favorite_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap().to_os_string();
+    name.push(".favorite");
+    path.with_file_name(name)
+}
+
+/// Whether a save is marked as a favorite.
+pub fn is_favorite(path: &Path) -> bool {
+    favorite_path(path).exists()
+}
+
+/// Marks or unmarks a save as a favorite.
+pub fn set_favorite(path: &Path, favorite: bool) -> Result<(), Error> {
+    if favorite {
+        fs::write(favorite_path(path), "")?;
+    } else if let Err(e) = fs::remove_file(favorite_path(path)) {
+        if e.kind() != io::ErrorKind::NotFound { return Err(e.into()); }
+    }
+    Ok(())
+}
+
+/// Locks older than this are assumed to be left over from a crash.
+pub const STALE_LOCK_AGE: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Get the path of the lock file for a save.
+pub fn lock_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap().to_os_string();
+    name.push(".lock");
+    path.with_file_name(name)
+}
+
+/// Marks a save as being played so other instances don't write to it too. Will return
+/// `Error::Locked` if the save is already locked.
+pub fn try_lock(path: &Path) -> Result<(), Error> {
+    match fs::OpenOptions::new().write(true).create_new(true).open(lock_path(path)) {
+        Ok(_) => Ok(()),
+        Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+            Err(Error::Locked(path.to_path_buf()))
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// Releases the lock on a save. Unlocking a save that isn't locked does nothing.
+pub fn unlock(path: &Path) -> Result<(), Error> {
+    match fs::remove_file(lock_path(path)) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+/// Removes the locks in a save folder older than `STALE_LOCK_AGE`. Returns how many were
+/// removed.
+pub fn remove_stale_locks(dir: Option<&Path>, extension: Option<&str>)
+    -> Result<usize, Error> {
+    let dir = match dir {
+        Some(p) => p.to_path_buf(),
+        None => project_save_dir()?,
+    };
+
+    let lock_extension = format!("{}.lock", save_extension(extension));
+    let mut removed = 0;
+    for f in dir.read_dir()? {
+        let f = match f {
+            Ok(de) => de,
+            Err(_) => continue,
+        };
+        if !f.file_name().to_string_lossy().ends_with(&lock_extension) { continue; }
+
+        let age = f.metadata()?.modified()?.elapsed().unwrap_or_default();
+        if age > STALE_LOCK_AGE {
+            fs::remove_file(f.path())?;
+            removed += 1;
+        }
+    }
+
+    Ok(removed)
+}
+
+/// Compares the saves at two paths, `a` being the earlier one.
+pub fn diff(a: &Path, b: &Path) -> Result<GameDiff, Error> {
+    let (a, b) = (from_path(a)?, from_path(b)?);
+
+    let mut ids: Vec<i64> = a.stocks.iter().chain(&b.stocks).map(|s| s.id()).collect();
+    ids.sort_unstable();
+    ids.dedup();
+
+    let stocks = ids.into_iter().map(|id| {
+        let before = a.stocks.iter().find(|s| s.id() == id);
+        let after = b.stocks.iter().find(|s| s.id() == id);
+        StockDiff {
+            // Safe unwrap because the id came from one of the two saves
+            name: after.or(before).unwrap().name().to_string(),
+            shares: (before.map(|s| a.player.stock_balance(s)),
+                     after.map(|s| b.player.stock_balance(s))),
+            value: (before.map(|s| s.value()), after.map(|s| s.value())),
+        }
+    }).collect();
+
+    let player = a.player.diff(&b.player);
+    Ok(GameDiff {
+        balance: player.balance,
+        income: player.income,
+        net_worth: b.player.net_worth(&b.stocks) - a.player.net_worth(&a.stocks),
+        stocks,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FIRST_NAME_WORDS, LAST_NAME_WORDS};
+
+    /// A fresh, empty directory for a test to write files to.
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir()
+            .join(format!("millionaire-test-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn stock_defs_load_with_sequential_ids() {
+        let dir = temp_dir("stock_defs");
+        let path = dir.join("stocks.json");
+        fs::write(&path, r#"[
+            {"name": "Cake Store", "value": 100, "variation": 10, "sector": "food"},
+            {"name": "Crab Box", "value": 50, "variation": 0}
+        ]"#).unwrap();
+
+        let stocks = load_stock_defs(&path).unwrap();
+        assert_eq!(stocks.len(), 2);
+        assert_eq!((stocks[0].id(), stocks[0].name(), stocks[0].value()), (0, "Cake Store", 100));
+        assert_eq!((stocks[1].id(), stocks[1].name(), stocks[1].value()), (1, "Crab Box", 50));
+    }
+
+    #[test]
+    fn stock_defs_report_the_bad_entry() {
+        let dir = temp_dir("stock_defs_bad");
+        let path = dir.join("stocks.json");
+        fs::write(&path, r#"[
+            {"name": "Cake Store", "value": 100, "variation": 10},
+            {"name": "Crab Box", "value": 0, "variation": 1}
+        ]"#).unwrap();
+        assert!(matches!(load_stock_defs(&path), Err(Error::InvalidStockDef(1, _))));
+
+        fs::write(&path, r#"[{"name": "Crab Box", "variation": 1}]"#).unwrap();
+        assert!(matches!(load_stock_defs(&path), Err(Error::InvalidStockDef(0, _))));
+    }
+
+    /// A game with `n` stocks generated from `seed`, along with the generator used.
+    fn seeded_game(seed: u64, n: i64) -> (Game, GameRng) {
+        let mut rng = GameRng::seed_from_u64(seed);
+        let stocks = (0..n).map(|id| {
+            let name = generate_name(&mut rng);
+            StockRange::default().generate(&mut rng, id, name)
+        }).collect();
+        let mut game = Game::new(1_000_000, 1000, stocks);
+        game.seed = seed;
+        (game, rng)
+    }
+
+    fn values(game: &Game) -> Vec<(String, i64)> {
+        game.stocks.iter().map(|s| (s.name().to_string(), s.value())).collect()
+    }
+
+    #[test]
+    fn same_seed_gives_same_market() {
+        let (mut a, mut rng_a) = seeded_game(42, 5);
+        let (mut b, mut rng_b) = seeded_game(42, 5);
+        let (c, _) = seeded_game(43, 5);
+        assert_eq!(values(&a), values(&b));
+        assert_ne!(values(&a), values(&c));
+        for _ in 0..10 {
+            a.tick(&mut rng_a);
+            b.tick(&mut rng_b);
+        }
+        assert_eq!(values(&a), values(&b));
+    }
+
+    /// A stock that follows `prices`, one per turn.
+    fn scripted(id: i64, prices: &[i64]) -> Stock {
+        Stock::new(id, format!("Stock {}", id), prices[0], 0).with_price_series(prices.to_vec())
+    }
+
+    #[test]
+    fn bankruptcy_rebuys_only_flagged_stocks() {
+        let stocks = vec![scripted(0, &[20, 10, 0]), scripted(1, &[20, 10, 0])];
+        let mut game = Game::new(1_000_000, 0, stocks);
+        game.player.deposit(500);
+        game.buy_stock(0, 10).unwrap();
+        game.buy_stock(1, 10).unwrap();
+        game.player.toggle_rebuy_after_bankruptcy(&game.stocks[0]);
+        let mut rng = GameRng::seed_from_u64(0);
+        game.tick(&mut rng);
+
+        let outcome = game.tick(&mut rng);
+        // 10 shares were worth 100 before the crash, which buys 5 at the reset price of 20
+        assert_eq!(outcome.bankruptcies[0].rebought, Some(5));
+        assert_eq!(outcome.bankruptcies[1].rebought, None);
+        assert_eq!(game.player.stock_balance(&game.stocks[0]), 5);
+        assert_eq!(game.player.stock_balance(&game.stocks[1]), 0);
+        assert_eq!(game.player.balance(), 0);
+        assert_eq!(game.player.trades_for(0).last().unwrap().amount, 5);
+    }
+
+    #[test]
+    fn bankruptcy_rebuy_respects_the_balance() {
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[20, 10, 0])]);
+        game.player.deposit(200);
+        game.buy_stock(0, 10).unwrap();
+        game.player.toggle_rebuy_after_bankruptcy(&game.stocks[0]);
+        let mut rng = GameRng::seed_from_u64(0);
+        game.tick(&mut rng);
+
+        let outcome = game.tick(&mut rng);
+        assert_eq!(outcome.bankruptcies[0].rebought, None);
+        assert_eq!(game.player.stock_balance(&game.stocks[0]), 0);
+        assert_eq!(game.player.balance(), 0);
+    }
+
+    /// A game with some state worth round-tripping, including an unknown field.
+    fn sample_game() -> Game {
+        let (mut game, mut rng) = seeded_game(7, 3);
+        game.tick(&mut rng);
+        game.store_rng(&rng);
+        game.player.deposit(5000);
+        game.buy_stock(1, 3).unwrap();
+        game.add_journal_note("Bought some".to_string());
+        game.extra.insert("from_the_future".to_string(), serde_json::json!([1, 2.5, "x"]));
+        game
+    }
+
+    fn round_trip(codec: &dyn SaveCodec, game: &Game) -> Game {
+        codec.decode(&codec.encode(game).unwrap()).unwrap()
+    }
+
+    /// The game as loaded from a save written before `field` existed.
+    fn without_field(game: &Game, field: &str) -> Game {
+        let mut json: serde_json::Value = serde_json::from_str(&game.to_json().unwrap()).unwrap();
+        json.as_object_mut().unwrap().remove(field).unwrap();
+        Game::from_json(&json.to_string()).unwrap()
+    }
+
+    #[test]
+    fn json_codec_round_trips() {
+        let game = sample_game();
+        assert_eq!(round_trip(&JsonCodec, &game).to_json().unwrap(), game.to_json().unwrap());
+    }
+
+    #[cfg(feature = "binary-saves")]
+    #[test]
+    fn bincode_codec_round_trips() {
+        let game = sample_game();
+        assert_eq!(round_trip(&BincodeCodec, &game).to_json().unwrap(),
+                   game.to_json().unwrap());
+    }
+
+    #[test]
+    fn codec_is_picked_by_extension() {
+        let json = JsonCodec.encode(&sample_game()).unwrap();
+        let saved = format!("game{}", DEFAULT_SAVE_EXTENSION);
+        assert!(codec_for(Path::new(&saved)).is_ok());
+        assert_eq!(codec_for(Path::new("game.json")).unwrap().encode(&sample_game()).unwrap(),
+                   json);
+        assert!(matches!(codec_for(Path::new("game.txt")), Err(Error::UnsupportedFormat(_))));
+        #[cfg(feature = "binary-saves")]
+        assert_ne!(codec_for(Path::new("game.bin")).unwrap().encode(&sample_game()).unwrap(),
+                   json);
+        #[cfg(not(feature = "binary-saves"))]
+        assert!(codec_for(Path::new("game.bin")).is_err());
+    }
+
+    #[test]
+    fn turns_to_goal_covers_reachable_reached_and_unreachable() {
+        let mut game = Game::new(1000, 100, Vec::new());
+        // 100 to start, and winning takes more than the goal
+        assert_eq!(game.estimated_turns_to_goal(), Some(10));
+        game.player.deposit(1000);
+        assert_eq!(game.estimated_turns_to_goal(), Some(0));
+
+        let game = Game::new(1000, 0, Vec::new());
+        assert_eq!(game.estimated_turns_to_goal(), None);
+
+        let game = Game::new(i64::MAX, 1, Vec::new());
+        assert_eq!(game.estimated_turns_to_goal(), None);
+        assert_eq!(game.player.income_over(u32::MAX), i64::from(u32::MAX));
+        assert_eq!(Player::new(0, i64::MAX).income_over(2), i64::MAX);
+    }
+
+    fn save_file(dir: &Path, name: &str) -> PathBuf {
+        dir.join(format!("{}{}", name, DEFAULT_SAVE_EXTENSION))
+    }
+
+    #[test]
+    fn saving_over_a_save_backs_it_up() {
+        let dir = temp_dir("backups");
+        let path = save_file(&dir, "game");
+        let mut game = Game::new(1_000_000, 1000, Vec::new());
+        save(&path, &game).unwrap();
+        save(&path, &game).unwrap();
+        assert!(backups(&path).is_empty(), "an unchanged save shouldn't be backed up");
+
+        for goal in 1..=BACKUP_COUNT as i64 + 2 {
+            game.goal = goal;
+            save(&path, &game).unwrap();
+        }
+        assert_eq!(backups(&path), (0..BACKUP_COUNT).collect::<Vec<_>>());
+        let newest = JsonCodec.decode(&fs::read(backup_path(&path, 0)).unwrap()).unwrap();
+        assert_eq!(newest.goal, BACKUP_COUNT as i64 + 1);
+
+        restore_backup(&path, 0).unwrap();
+        assert_eq!(from_path(&path).unwrap().goal, BACKUP_COUNT as i64 + 1);
+        assert!(matches!(restore_backup(&path, BACKUP_COUNT), Err(Error::NotFound(_))));
+
+        delete(&path).unwrap();
+        assert!(!path.exists());
+        assert!(backups(&path).is_empty());
+    }
+
+    #[test]
+    fn renaming_a_save_moves_its_backups() {
+        let dir = temp_dir("rename_backups");
+        let path = save_file(&dir, "old");
+        let mut game = Game::new(1_000_000, 1000, Vec::new());
+        save(&path, &game).unwrap();
+        game.goal = 5;
+        save(&path, &game).unwrap();
+
+        let new_path = rename(&path, "new", None).unwrap();
+        assert_eq!(new_path, save_file(&dir, "new"));
+        assert!(backups(&path).is_empty());
+        assert_eq!(backups(&new_path), vec![0]);
+        assert!(matches!(rename(&new_path, "  ", None), Err(Error::EmptyFileName)));
+    }
+
+    #[test]
+    fn add_stock_cost_decays_to_the_floor() {
+        let mut game = Game::new(1_000_000, 1000, Vec::new());
+        game.add_stock_cost = 1000;
+        game.add_stock_cost_decay_bps = 1000;
+        game.add_stock_cost_floor = 800;
+        assert_eq!(game.current_add_stock_cost(), 1000);
+
+        game.turns_since_stock_added = 1;
+        assert_eq!(game.current_add_stock_cost(), 900);
+        game.turns_since_stock_added = 2;
+        assert_eq!(game.current_add_stock_cost(), 810);
+        game.turns_since_stock_added = 50;
+        assert_eq!(game.current_add_stock_cost(), 800);
+
+        game.add_stock_cost_decay_bps = 0;
+        assert_eq!(game.current_add_stock_cost(), 1000);
+    }
+
+    #[test]
+    fn adding_a_stock_resets_the_decay() {
+        let (mut game, mut rng) = seeded_game(1, 1);
+        game.add_stock_cost_decay_bps = 1000;
+        game.tick(&mut rng);
+        assert_eq!(game.turns_since_stock_added, 1);
+        game.player.deposit(game.current_add_stock_cost());
+        game.add_random_stock(&mut rng).unwrap();
+        assert_eq!(game.turns_since_stock_added, 0);
+    }
+
+    #[test]
+    fn save_folder_that_is_a_file_is_reported() {
+        let dir = temp_dir("not_a_dir");
+        let file = dir.join("saves");
+        fs::write(&file, "").unwrap();
+        assert!(matches!(saves_in_folder(Some(&file), None), Err(Error::NotADirectory(_))));
+        assert!(matches!(saves_in_folder(Some(&dir.join("missing")), None),
+                         Err(Error::NotFound(_))));
+        assert!(saves_in_folder(Some(&dir), None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn stocks_low_for_long_enough_are_delisted() {
+        let stocks = vec![scripted(0, &[100, 5, 5, 20]), scripted(1, &[100, 5, 20, 5])];
+        let mut game = Game::new(1_000_000, 0, stocks);
+        game.delist_below = Some(10);
+        game.delist_after_turns = 2;
+        game.player.deposit(300);
+        game.buy_stock(0, 3).unwrap();
+        let mut rng = GameRng::seed_from_u64(0);
+
+        assert!(game.tick(&mut rng).delistings.is_empty());
+        let outcome = game.tick(&mut rng);
+        assert_eq!(outcome.delistings.len(), 1);
+        assert_eq!((outcome.delistings[0].name.as_str(), outcome.delistings[0].payout),
+                   ("Stock 0", 15));
+        assert_eq!(game.player.balance(), 15);
+        assert_eq!(game.stocks.iter().map(|s| s.id()).collect::<Vec<_>>(), vec![1]);
+
+        // Stock 1 climbed back above the threshold, so its count started over
+        assert!(game.tick(&mut rng).delistings.is_empty());
+    }
+
+    #[test]
+    fn ticking_remembers_the_previous_net_worth() {
+        let mut game = Game::new(1_000_000, 100, vec![scripted(0, &[10, 20])]);
+        game.buy_stock(0, 5).unwrap();
+        assert_eq!(game.previous_net_worth, None);
+        game.tick(&mut GameRng::seed_from_u64(0));
+        // Taken after income but before the market moved
+        assert_eq!(game.previous_net_worth, Some(200));
+        assert_eq!(game.player.net_worth(&game.stocks), 250);
+    }
+
+    #[test]
+    fn new_game_uses_the_default_relationships() {
+        let game = Game::new(50_000, 250, Vec::new());
+        assert_eq!(game.goal, 50_000);
+        assert_eq!((game.player.balance(), game.player.income()), (250, 250));
+        assert_eq!(game.initial_income, 250);
+        assert_eq!(game.income_upgrade_cost, 2500);
+        assert_eq!(game.add_stock_cost, DEFAULT_ADD_STOCK_COST);
+        assert_eq!(game.turn, 0);
+    }
+
+    #[test]
+    fn resetting_the_player_keeps_the_market() {
+        let mut game = Game::new(1_000_000, 100, vec![scripted(0, &[10, 20])]);
+        game.initial_balance = Some(500);
+        game.buy_stock(0, 5).unwrap();
+        game.tick(&mut GameRng::seed_from_u64(0));
+
+        game.reset_player();
+        assert_eq!((game.player.balance(), game.player.income()), (500, 100));
+        assert_eq!(game.player.stock_balance(&game.stocks[0]), 0);
+        assert_eq!(game.previous_net_worth, None);
+        assert_eq!((game.stocks.len(), game.stocks[0].value(), game.turn), (1, 20, 1));
+    }
+
+    #[test]
+    fn income_modifiers_scale_income_for_their_turns() {
+        let mut game = Game::new(1_000_000, 100, Vec::new());
+        game.apply_event(Event::IncomeModifier { multiplier_bps: 20000, turns: 2 });
+        assert_eq!(game.collect_income(), 200);
+        assert_eq!(game.income_modifier, Some((20000, 1)));
+        assert_eq!(game.collect_income(), 200);
+        assert_eq!(game.income_modifier, None);
+        assert_eq!(game.collect_income(), 100);
+    }
+
+    #[test]
+    fn income_events_roll_with_their_chance() {
+        let (mut game, mut rng) = seeded_game(3, 0);
+        assert!(game.roll_events(&mut rng).is_empty());
+
+        game.income_event_chance_bps = 10000;
+        let events = game.roll_events(&mut rng);
+        assert!(matches!(events[..], [Event::IncomeModifier { turns: 1, .. }]));
+        assert!(game.income_modifier.is_some());
+        // No new income event while one is in effect
+        assert!(game.roll_events(&mut rng).is_empty());
+    }
+
+    #[test]
+    fn stocks_above_the_threshold_split() {
+        let stocks = vec![Stock::new(0, "A".to_string(), 300, 5),
+                          Stock::new(1, "B".to_string(), 100, 5)];
+        let mut game = Game::new(1_000_000, 1000, stocks);
+        game.buy_stock(0, 2).unwrap();
+        assert!(game.process_splits().is_empty());
+
+        game.split_threshold = Some(200);
+        game.split_ratio = 3;
+        assert_eq!(game.process_splits(), vec![("A".to_string(), 3)]);
+        assert_eq!(game.stocks[0].value(), 100);
+        assert_eq!(game.player.stock_balance(&game.stocks[0]), 6);
+        assert_eq!(game.stocks[1].value(), 100);
+    }
+
+    /// Sets when a file was last modified, `secs` seconds after the epoch.
+    fn set_modified(path: &Path, secs: u64) {
+        let time = std::time::UNIX_EPOCH + Duration::from_secs(secs);
+        fs::File::options().write(true).open(path).unwrap().set_modified(time).unwrap();
+    }
+
+    #[test]
+    fn most_recent_save_is_the_last_modified() {
+        let dir = temp_dir("most_recent");
+        assert!(most_recent(Some(&dir), None).unwrap().is_none());
+
+        let game = Game::new(1_000_000, 1000, Vec::new());
+        for (name, secs) in [("older", 1000), ("newest", 3000), ("old", 2000)] {
+            let path = save_file(&dir, name);
+            save(&path, &game).unwrap();
+            set_modified(&path, secs);
+        }
+        assert_eq!(most_recent(Some(&dir), None).unwrap().unwrap().name, "newest");
+    }
+
+    #[test]
+    fn added_stocks_use_the_added_range() {
+        let (mut game, mut rng) = seeded_game(5, 0);
+        game.added_stock_range = StockRange::new(7, 7, 2, 2);
+        game.player.deposit(game.current_add_stock_cost());
+        let stock = game.add_random_stock(&mut rng).unwrap();
+        assert_eq!((stock.value(), stock.variation), (7, 2));
+    }
+
+    #[test]
+    fn stock_ranges_default_in_old_saves() {
+        let (mut game, _) = seeded_game(5, 0);
+        game.starting_stock_range = StockRange::new(1, 2, 3, 4);
+        assert_eq!(without_field(&game, "starting_stock_range").starting_stock_range,
+                   StockRange::default());
+    }
+
+    #[test]
+    fn total_money_is_kept_by_market_trades() {
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[40, 40])]);
+        game.player.deposit(1000);
+        let before = game.total_money_in_play();
+        game.player.buy_stock(&game.stocks[0], 20).unwrap();
+        assert_eq!(game.total_money_in_play(), before);
+    }
+
+    #[test]
+    fn ending_a_turn_is_confirmed_only_when_asked() {
+        let (mut game, _) = seeded_game(5, 0);
+        assert!(!game.should_confirm(Confirmation::EndTurn));
+        game.confirm_end_turn = true;
+        assert!(game.should_confirm(Confirmation::EndTurn));
+        assert!(!without_field(&game, "confirm_end_turn").confirm_end_turn);
+    }
+
+    #[test]
+    fn schema_lists_every_saved_field() {
+        let schema: serde_json::Value = serde_json::from_str(&schema().unwrap()).unwrap();
+        let fields = schema["fields"].as_object().unwrap();
+        let game: serde_json::Value =
+            serde_json::from_str(&Game::new(1_000_000, 1000, Vec::new()).to_json().unwrap())
+                .unwrap();
+        for key in game.as_object().unwrap().keys() {
+            assert!(fields.contains_key(key), "{} is missing", key);
+        }
+        assert_eq!(fields["goal"]["type"], "number");
+        assert_eq!(fields["goal"]["default"], 1_000_000);
+    }
+
+    #[test]
+    fn recurring_buys_spend_the_available_balance() {
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[30]), scripted(1, &[40])]);
+        game.min_trade = 2;
+        game.player.deposit(200);
+        game.player.add_recurring_buy(0, 100);
+        game.player.add_recurring_buy(1, 90);
+
+        assert_eq!(game.process_recurring_buys(),
+                   vec![("Stock 0".to_string(), 2), ("Stock 1".to_string(), 2)]);
+        assert_eq!(game.player.available_balance(), 60);
+        assert_eq!(game.player.trades_for(1).len(), 1);
+        assert_eq!(game.trades, 2);
+
+        game.player.lock(10).unwrap();
+        game.player.add_recurring_buy(0, 60);
+        game.player.remove_recurring_buy(1);
+        assert!(game.process_recurring_buys().is_empty());
+    }
+
+    #[test]
+    fn harder_settings_score_higher() {
+        let base = || {
+            let mut game = Game::new(1_000_000, 1000, Vec::new());
+            game.income_upgrade_cost = 10_000;
+            game
+        };
+        let score = |game: &Game| game.difficulty_score().0;
+        let easy = score(&base());
+
+        let mut game = base();
+        game.goal *= 10;
+        assert!(score(&game) > easy);
+        let mut game = base();
+        game.starting_stock_range = StockRange::new(10, 100, 50, 100);
+        assert!(score(&game) > easy);
+        let mut game = base();
+        game.income_upgrade_cost *= 10;
+        assert!(score(&game) > easy);
+        let mut game = base();
+        game.initial_income = 0;
+        assert!(score(&game) > easy);
+        let mut game = base();
+        game.income_event_chance_bps = 2000;
+        assert!(score(&game) > easy);
+        let mut game = base();
+        game.flash_crash_chance_bps = 500;
+        assert!(score(&game) > easy);
+        let mut game = base();
+        game.spread_bps = 300;
+        assert!(score(&game) > easy);
+    }
+
+    #[test]
+    fn game_trades_need_a_positive_amount() {
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[10])]);
+        game.player.deposit(100);
+        game.spread_bps = 2000;
+        game.buy_stock(0, 5).unwrap();
+        assert_eq!((game.player.balance(), game.actions_taken_this_turn), (45, 1));
+
+        for amount in [0, -3] {
+            assert_eq!(game.buy_stock(0, amount), Err(TransactionError::InvalidAmount));
+            assert_eq!(game.sell_stock(0, amount), Err(TransactionError::InvalidAmount));
+        }
+        assert_eq!((game.player.balance(), game.actions_taken_this_turn), (45, 1));
+        assert_eq!(game.player.stock_balance(&game.stocks[0]), 5);
+
+        game.sell_stock(0, 5).unwrap();
+        assert_eq!(game.player.balance(), 90);
+    }
+
+    #[test]
+    fn ticking_reports_what_happened() {
+        let mut game = Game::new(250, 100, vec![scripted(0, &[10, 20]), scripted(1, &[5, 5])]);
+        game.player.add_recurring_buy(0, 45);
+        let mut rng = GameRng::seed_from_u64(0);
+
+        let outcome = game.tick(&mut rng);
+        assert_eq!(outcome.income, 100);
+        assert!(!outcome.income_upgraded);
+        assert_eq!(outcome.price_moves, vec![("Stock 0".to_string(), 10, 20)]);
+        assert_eq!(outcome.recurring_buys, vec![("Stock 0".to_string(), 2)]);
+        assert!(outcome.bankruptcies.is_empty() && outcome.splits.is_empty());
+        assert!(!outcome.won && !outcome.bust);
+        assert_eq!((game.turn, game.player.balance()), (1, 160));
+
+        let outcome = game.tick(&mut rng);
+        assert!(outcome.won);
+        assert!(outcome.price_moves.is_empty());
+    }
+
+    #[test]
+    fn news_keeps_the_latest_headlines() {
+        let (mut game, _) = seeded_game(5, 0);
+        game.news_len = 3;
+        for i in 0..5 {
+            game.push_news(format!("Headline {}", i));
+        }
+        assert_eq!(game.news, ["Headline 2", "Headline 3", "Headline 4"]);
+
+        let loaded = without_field(&without_field(&game, "news_len"), "news");
+        assert_eq!((loaded.news.len(), loaded.news_len), (0, 10));
+    }
+
+    #[test]
+    fn spin_offs_split_the_largest_stock_between_holders() {
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[100]), scripted(1, &[50])]);
+        game.spin_off_chance_bps = 10000;
+        game.player.deposit(300);
+        game.player.buy_stock(&game.stocks[0], 3).unwrap();
+        let before = game.total_money_in_play();
+
+        let names = game.process_spin_offs(&mut GameRng::seed_from_u64(0));
+        assert_eq!(names, Some(("Stock 0".to_string(), "Stock 0 Spin-off".to_string())));
+        let values: Vec<i64> = game.stocks.iter().map(|s| s.value()).collect();
+        assert_eq!(values, vec![80, 50, 20]);
+        assert_eq!(game.stocks[2].id(), 2);
+        assert_eq!(game.player.stock_balance(&game.stocks[2]), 3);
+        assert_eq!(game.player.cost_basis(&game.stocks[0]), 240);
+        assert_eq!(game.player.cost_basis(&game.stocks[2]), 60);
+        assert_eq!(game.total_money_in_play(), before);
+    }
+
+    #[test]
+    fn spin_offs_never_take_the_whole_parent() {
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[100])]);
+        game.spin_off_chance_bps = 10000;
+        game.spin_off_fraction_bps = 15000;
+        game.player.deposit(300);
+        game.player.buy_stock(&game.stocks[0], 3).unwrap();
+        let before = game.total_money_in_play();
+
+        game.process_spin_offs(&mut GameRng::seed_from_u64(0)).unwrap();
+        assert_eq!((game.stocks[0].value(), game.stocks[1].value()), (1, 99));
+        assert!(!game.stocks[0].is_bankrupt());
+        assert_eq!(game.total_money_in_play(), before);
+    }
+
+    #[test]
+    fn migrating_moves_saves_and_renames_clashes() {
+        let (from, to) = (temp_dir("migrate_from"), temp_dir("migrate_to"));
+        let mut game = Game::new(1_000_000, 1000, Vec::new());
+        save(&save_file(&from, "a"), &game).unwrap();
+        game.goal = 5;
+        save(&save_file(&from, "a"), &game).unwrap();
+        save(&save_file(&from, "b"), &game).unwrap();
+        save(&save_file(&to, "a"), &game).unwrap();
+
+        assert_eq!(migrate_dir(&from, &to, None).unwrap(), 2);
+        assert!(saves_in_folder(Some(&from), None).unwrap().is_empty());
+        assert!(!save_file(&from, "a").exists() && !save_file(&from, "b").exists());
+        assert!(backups(&save_file(&from, "a")).is_empty());
+        let mut names: Vec<String> =
+            saves_in_folder(Some(&to), None).unwrap().into_iter().map(|s| s.name).collect();
+        names.sort();
+        assert_eq!(names, ["a", "a (2)", "b"]);
+        assert_eq!(backups(&save_file(&to, "a (2)")), vec![0]);
+        assert!(matches!(migrate_dir(&from, &to.join("missing"), None), Err(Error::NotFound(_))));
+    }
+
+    #[test]
+    fn the_action_cap_limits_successful_actions() {
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[10, 10])]);
+        game.action_cap = Some(2);
+        game.income_upgrade_cost = 10;
+        game.player.deposit(100);
+
+        game.buy_stock(0, 5).unwrap();
+        assert_eq!(game.buy_stock(0, 50), Err(TransactionError::InsufficientFunds));
+        assert_eq!(game.actions_left(), Some(1));
+        game.increase_income().unwrap();
+        assert_eq!(game.actions_left(), Some(0));
+        assert_eq!(game.sell_stock(0, 1), Err(TransactionError::ActionBudgetExceeded));
+
+        game.tick(&mut GameRng::seed_from_u64(0));
+        assert_eq!(game.actions_left(), Some(2));
+        game.action_cap = None;
+        assert_eq!(game.actions_left(), None);
+    }
+
+    #[test]
+    fn stock_order_is_saved_without_moving_the_market() {
+        let (mut game, _) = seeded_game(5, 3);
+        game.stock_order = SortKey::Value;
+        let loaded = Game::from_json(&game.to_json().unwrap()).unwrap();
+        assert_eq!(loaded.stock_order, SortKey::Value);
+        assert_eq!(values(&loaded), values(&game));
+        assert_eq!(without_field(&game, "stock_order").stock_order, SortKey::Id);
+    }
+
+    #[test]
+    fn unknown_fields_survive_a_load_and_save() {
+        let (game, _) = seeded_game(5, 1);
+        let mut json: serde_json::Value = serde_json::from_str(&game.to_json().unwrap()).unwrap();
+        json["from_the_future"] = serde_json::json!({ "level": [1, 2] });
+
+        let loaded = Game::from_json(&json.to_string()).unwrap();
+        assert_eq!(loaded.extra["from_the_future"]["level"][1], 2);
+        let saved: serde_json::Value = serde_json::from_str(&loaded.to_json().unwrap()).unwrap();
+        assert_eq!(saved["from_the_future"], json["from_the_future"]);
+    }
+
+    #[test]
+    fn saves_can_only_be_locked_once() {
+        let dir = temp_dir("locks");
+        let (old, new) = (save_file(&dir, "old"), save_file(&dir, "new"));
+        try_lock(&new).unwrap();
+        assert!(matches!(try_lock(&new), Err(Error::Locked(p)) if p == new));
+        unlock(&new).unwrap();
+        unlock(&new).unwrap();
+        try_lock(&new).unwrap();
+
+        try_lock(&old).unwrap();
+        set_modified(&lock_path(&old), 1000);
+        assert_eq!(remove_stale_locks(Some(&dir), None).unwrap(), 1);
+        assert!(!lock_path(&old).exists());
+        assert!(lock_path(&new).exists());
+    }
+
+    #[test]
+    fn bought_shares_settle_before_they_can_be_sold() {
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[10])]);
+        let mut rng = GameRng::seed_from_u64(0);
+        game.settlement_turns = 2;
+        game.player.deposit(100);
+        game.buy_stock(0, 5).unwrap();
+        assert_eq!(game.sellable_shares(0), 0);
+        assert_eq!(game.sell_stock(0, 1), Err(TransactionError::UnsettledShares));
+        assert_eq!(game.sell_stock(0, 6), Err(TransactionError::InsufficientStock));
+
+        game.tick(&mut rng);
+        game.buy_stock(0, 1).unwrap();
+        assert_eq!(game.sellable_shares(0), 0);
+        game.tick(&mut rng);
+        assert_eq!(game.sellable_shares(0), 5);
+        game.sell_stock(0, 5).unwrap();
+        assert_eq!(game.sell_stock(0, 1), Err(TransactionError::UnsettledShares));
+        game.tick(&mut rng);
+        game.sell_stock(0, 1).unwrap();
+    }
+
+    #[test]
+    fn hand_edited_saves_are_repaired_on_load() {
+        let (game, _) = seeded_game(5, 1);
+        let mut json: serde_json::Value = serde_json::from_str(&game.to_json().unwrap()).unwrap();
+        json["goal"] = (-1).into();
+        json["stocks"][0]["value"] = (-5).into();
+        json["player"]["income"] = (-3).into();
+        json["player"]["stock_balances"]["9"] = 4.into();
+        let path = save_file(&temp_dir("sanitize"), "edited");
+        fs::write(&path, json.to_string()).unwrap();
+
+        let loaded = from_path(&path).unwrap();
+        assert_eq!((loaded.goal, loaded.stocks[0].value(), loaded.player.income()), (0, 1, 0));
+        assert_eq!(loaded.news.len(), 4);
+        assert!(loaded.news.iter().all(|n| n.starts_with("Save repaired: ")));
+
+        let (mut clean, _) = seeded_game(5, 1);
+        assert!(clean.sanitize().is_empty());
+    }
+
+    #[test]
+    fn flash_crashes_recover_the_next_turn() {
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[100]), scripted(1, &[50])]);
+        let mut rng = GameRng::seed_from_u64(0);
+        game.flash_crash_chance_bps = 10000;
+
+        assert_eq!(game.roll_events(&mut rng),
+                   vec![Event::FlashCrash { drop_bps: 4000, recovery_bps: 3000 }]);
+        assert_eq!(values(&game)[0].1, 60);
+        assert_eq!(values(&game)[1].1, 30);
+        assert_eq!(game.stocks[0].history().back(), Some(&60));
+
+        assert_eq!(game.roll_events(&mut rng), vec![Event::Recovery { recovery_bps: 3000 }]);
+        assert_eq!(values(&game)[0].1, 78);
+        assert_eq!(game.pending_recovery_bps, None);
+    }
+
+    #[test]
+    fn rebased_upgrades_scale_with_the_income() {
+        let mut game = Game::new(1_000_000, 100, Vec::new());
+        game.player.deposit(10_000);
+        game.income_upgrade_cost = 100;
+        game.increase_income().unwrap();
+        game.increase_income().unwrap();
+        assert_eq!(game.player.income(), 300);
+
+        game.rebase_income_upgrades();
+        assert_eq!((game.initial_income, game.income_upgrade_cost), (100, 3000));
+        game.increase_income().unwrap();
+        assert_eq!(game.player.income(), 600);
+
+        game.reset_player();
+        assert_eq!((game.player.balance(), game.player.income()), (100, 100));
+    }
+
+    #[test]
+    fn journal_notes_are_kept_by_turn() {
+        let (mut game, mut rng) = seeded_game(5, 1);
+        game.add_journal_note("Bought the dip".to_string());
+        game.tick(&mut rng);
+        game.add_journal_note("Sold too early".to_string());
+
+        let loaded = Game::from_json(&game.to_json().unwrap()).unwrap();
+        assert_eq!(loaded.journal, vec![(0, "Bought the dip".to_string()),
+                                        (1, "Sold too early".to_string())]);
+        assert!(without_field(&game, "journal").journal.is_empty());
+    }
+
+    #[test]
+    fn reloaded_games_carry_on_the_same_random_sequence() {
+        let (mut game, mut rng) = seeded_game(11, 4);
+        for _ in 0..3 { game.tick(&mut rng); }
+        game.store_rng(&rng);
+
+        let mut loaded = Game::from_json(&game.to_json().unwrap()).unwrap();
+        let mut loaded_rng = loaded.rng();
+        for _ in 0..5 {
+            game.tick(&mut rng);
+            loaded.tick(&mut loaded_rng);
+        }
+        assert_eq!(values(&loaded), values(&game));
+    }
+
+    #[test]
+    fn falling_to_the_bust_threshold_ends_the_game() {
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[100, 50, 40])]);
+        let mut rng = GameRng::seed_from_u64(0);
+        game.player.deposit(1000);
+        game.buy_stock(0, 10).unwrap();
+        assert!(!game.is_bust() && !game.is_over());
+
+        game.bust_threshold = Some(500);
+        let outcome = game.tick(&mut rng);
+        assert!(outcome.bust && game.is_over());
+        game.bust_threshold = Some(499);
+        assert!(!game.is_bust());
+    }
+
+    #[test]
+    fn diffing_saves_covers_added_and_removed_stocks() {
+        let dir = temp_dir("diff");
+        let mut game = Game::new(1_000_000, 100, vec![scripted(0, &[10]), scripted(1, &[20])]);
+        save(&save_file(&dir, "before"), &game).unwrap();
+
+        game.player.buy_stock(&game.stocks[0], 3).unwrap();
+        game.stocks.remove(1);
+        game.stocks.push(scripted(2, &[5]));
+        save(&save_file(&dir, "after"), &game).unwrap();
+
+        let diff = diff(&save_file(&dir, "before"), &save_file(&dir, "after")).unwrap();
+        assert_eq!((diff.balance, diff.income, diff.net_worth), (-30, 0, 0));
+        let stocks: Vec<_> = diff.stocks.iter().map(|s| (s.name.as_str(), s.shares, s.value))
+            .collect();
+        assert_eq!(stocks, vec![
+            ("Stock 0", (Some(0), Some(3)), (Some(10), Some(10))),
+            ("Stock 1", (Some(0), None), (Some(20), None)),
+            ("Stock 2", (None, Some(0)), (None, Some(5))),
+        ]);
+    }
+
+    #[test]
+    fn controlling_stakes_pay_a_bonus_with_the_income() {
+        let mut game = Game::new(1_000_000, 100, vec![scripted(0, &[10]), scripted(1, &[20])]);
+        game.player.deposit(1000);
+        game.player.buy_stock(&game.stocks[0], 50).unwrap();
+        game.player.buy_stock(&game.stocks[1], 10).unwrap();
+        game.control_bonus_bps = 100;
+        assert_eq!(game.collect_income(), 100);
+
+        game.control_threshold = Some(10);
+        assert_eq!(game.control_bonus(), 5);
+        assert_eq!(game.collect_income(), 105);
+        let last = game.player.cash_flows().last().unwrap();
+        assert_eq!((last.amount, last.reason), (5, CashFlowReason::ControlBonus));
+    }
+
+    #[test]
+    fn an_empty_market_gets_a_free_stock() {
+        let (mut game, mut rng) = seeded_game(5, 0);
+        game.added_stock_range = StockRange::new(30, 30, 3, 3);
+        let name = game.ensure_market(&mut rng).unwrap();
+        assert_eq!(values(&game), vec![(name, 30)]);
+        assert_eq!(game.player.balance(), 1000);
+        assert_eq!(game.ensure_market(&mut rng), None);
+        assert_eq!(game.stocks.len(), 1);
+    }
+
+    #[test]
+    fn stocks_are_found_by_id_in_any_market() {
+        let stocks = vec![scripted(4, &[40]), scripted(0, &[10]), scripted(2, &[20])];
+        let mut game = Game::new(1_000_000, 0, stocks);
+        assert_eq!(game.stocks.iter().map(|s| s.id()).collect::<Vec<_>>(), vec![0, 2, 4]);
+        assert_eq!(game.stock_by_id(4).map(|s| s.value()), Some(40));
+        assert!(game.stock_by_id(3).is_none());
+
+        game.stocks.swap(0, 2);
+        game.sanitize();
+        game.stock_by_id_mut(2).unwrap().shock(5000);
+        assert_eq!(game.stock_by_id(2).map(|s| s.value()), Some(30));
+        assert_eq!(game.stock_by_id(0).map(|s| s.value()), Some(10));
+    }
+
+    #[test]
+    fn hints_follow_the_players_situation() {
+        let starts = |game: &Game| -> Vec<String> {
+            hints(game).iter().map(|h| h.split(['.', ',']).next().unwrap().to_string()).collect()
+        };
+        let mut game = Game::new(1_000_000, 1000, vec![scripted(0, &[10])]);
+        game.income_upgrade_cost = 100_000;
+        assert_eq!(starts(&game), ["Your cash is sitting idle"]);
+
+        game.income_upgrade_cost = 500;
+        assert_eq!(starts(&game), ["Your cash is sitting idle", "You can afford an income increase",
+                                   "Your income is already high compared to your wealth"]);
+
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[10])]);
+        game.player.deposit(1000);
+        game.player.buy_stock(&game.stocks[0], 100).unwrap();
+        game.income_upgrade_cost = 100;
+        assert_eq!(starts(&game), ["Your income is low compared to your wealth"]);
+    }
+
+    #[test]
+    fn reverting_discards_unsaved_changes() {
+        let dir = temp_dir("revert");
+        let path = save_file(&dir, "game");
+        let (mut game, mut rng) = seeded_game(4, 3);
+        save(&path, &game).unwrap();
+        let saved = game.to_json().unwrap();
+
+        game.player.deposit(500);
+        game.tick(&mut rng);
+        assert_ne!(game.to_json().unwrap(), saved);
+        revert(&path, &mut game).unwrap();
+        assert_eq!(game.to_json().unwrap(), saved);
+
+        let missing = dir.join("missing.json");
+        game.player.deposit(500);
+        let changed = game.to_json().unwrap();
+        assert!(revert(&missing, &mut game).is_err());
+        assert_eq!(game.to_json().unwrap(), changed);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn losses_are_hinted_once_profit_is_locked_in() {
+        let stocks = vec![scripted(0, &[10, 20]), scripted(1, &[10, 5])];
+        let mut game = Game::new(1_000_000, 0, stocks);
+        let mut rng = GameRng::seed_from_u64(0);
+        game.player.deposit(100);
+        game.player.buy_stock(&game.stocks[0], 2).unwrap();
+        game.player.buy_stock(&game.stocks[1], 2).unwrap();
+        game.tick(&mut rng);
+        let harvest = |game: &Game| hints(game).into_iter().filter(|h| h.contains("offset"))
+            .collect::<Vec<_>>();
+        assert!(harvest(&game).is_empty());
+
+        game.player.sell_stock(&game.stocks[0], 2).unwrap();
+        assert_eq!(harvest(&game), ["'Stock 1' is down 10. Selling it would offset some of the \
+                                     20 profit you've locked in."]);
+    }
+
+    #[test]
+    fn summary_stats_track_sales_and_the_peak() {
+        let stocks = vec![scripted(0, &[10, 30, 10]), scripted(1, &[10, 5])];
+        let mut game = Game::new(1_000_000, 0, stocks);
+        let mut rng = GameRng::seed_from_u64(0);
+        game.player.deposit(100);
+        game.buy_stock(0, 5).unwrap();
+        game.buy_stock(1, 5).unwrap();
+        let stats = game.summary_stats();
+        assert_eq!((stats.trades, stats.best_trade, stats.worst_trade), (2, None, None));
+
+        game.tick(&mut rng);
+        assert_eq!(game.peak_net_worth, 150 + 25);
+        game.sell_stock(1, 5).unwrap();
+        game.tick(&mut rng);
+        game.sell_stock(0, 5).unwrap();
+
+        let stats = game.summary_stats();
+        assert_eq!((stats.turns_played, stats.trades), (2, 4));
+        assert_eq!((stats.final_net_worth, stats.peak_net_worth), (75, 175));
+        assert_eq!(stats.realized_pl, -25);
+        assert_eq!((stats.best_trade, stats.worst_trade), (Some(0), Some(-25)));
+    }
+
+    #[test]
+    fn listed_stocks_get_unique_names_unless_turned_off() {
+        let name = generate_name(&mut GameRng::seed_from_u64(1));
+        let game = Game::new(1_000_000, 0, vec![Stock::new(0, name.clone(), 10, 0)]);
+        assert_ne!(game.new_stock_name(&mut GameRng::seed_from_u64(1)), name);
+
+        let old = without_field(&game, "unique_stock_names");
+        assert!(!old.unique_stock_names);
+        assert_eq!(old.new_stock_name(&mut GameRng::seed_from_u64(1)), name);
+    }
+
+    #[test]
+    fn alerts_fire_when_net_worth_crosses_them() {
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[10, 30, 4])]);
+        let mut rng = GameRng::seed_from_u64(0);
+        game.alerts = vec![50, 150, 500];
+        game.player.deposit(100);
+        game.player.buy_stock(&game.stocks[0], 10).unwrap();
+        let crossed = |game: &Game, prev| -> Vec<(i64, bool)> {
+            game.check_alerts(prev).iter().map(|a| (a.level, a.up)).collect()
+        };
+        assert!(crossed(&game, 100).is_empty());
+        assert_eq!(crossed(&game, 40), [(50, true)]);
+        // Reaching a level exactly counts as passing it, staying below it doesn't
+        game.alerts = vec![100, 150];
+        assert_eq!(crossed(&game, 99), [(100, true)]);
+        assert!(crossed(&game, 120).is_empty() && crossed(&game, 100).is_empty());
+        game.alerts = vec![50, 150, 500];
+
+        let outcome = game.tick(&mut rng);
+        assert_eq!(outcome.alerts.iter().map(|a| (a.level, a.up)).collect::<Vec<_>>(),
+                   [(150, true)]);
+        assert!(outcome.headlines().contains(&"Your net worth passed 150!".to_string()));
+
+        let outcome = game.tick(&mut rng);
+        assert_eq!(outcome.alerts.iter().map(|a| (a.level, a.up)).collect::<Vec<_>>(),
+                   [(50, false), (150, false)]);
+        assert!(outcome.headlines().contains(&"Your net worth fell below 50.".to_string()));
+    }
+
+    #[test]
+    fn price_series_load_from_csv_next_to_the_stock_file() {
+        let dir = temp_dir("price-series");
+        fs::write(dir.join("prices.csv"), "turn,price\n1,10\n\n2, 12\n3,9\n").unwrap();
+        assert_eq!(load_price_series(&dir.join("prices.csv")).unwrap(), [10, 12, 9]);
+
+        let stocks = dir.join("stocks.json");
+        fs::write(&stocks, r#"[{"name": "A", "value": 5, "variation": 1,
+                                "price_series": "prices.csv"}]"#).unwrap();
+        let loaded = load_stock_defs(&stocks).unwrap();
+        assert_eq!(loaded[0].value(), 10);
+        let series = crate::StockBehavior::Scripted { prices: vec![10, 12, 9], index: 0 };
+        assert_eq!(loaded[0].behavior(), &series);
+
+        fs::write(dir.join("prices.csv"), "10\n12\n0\n").unwrap();
+        assert!(matches!(load_price_series(&dir.join("prices.csv")),
+                         Err(Error::InvalidPriceSeries(_, 3))));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn the_ramp_grows_each_turn_up_to_its_cap() {
+        let mut game = Game::new(1_000_000, 0, Vec::new());
+        game.turn = 50;
+        assert_eq!(game.ramp_scale_bps(), 10000);
+
+        game.ramp_bps = 100;
+        assert_eq!(game.ramp_scale_bps(), 15000);
+        game.turn = 1000;
+        assert_eq!(game.ramp_scale_bps(), 30000);
+        game.ramp_bps = -100;
+        assert_eq!(game.ramp_scale_bps(), 10000);
+        game.ramp_bps = i64::MAX;
+        assert_eq!(game.ramp_scale_bps(), 30000);
+
+        game.ramp_cap_bps = 12000;
+        let old = without_field(&without_field(&game, "ramp_bps"), "ramp_cap_bps");
+        assert_eq!((old.ramp_bps, old.ramp_cap_bps), (0, 30000));
+    }
+
+    #[test]
+    fn favorites_are_marked_listed_first_and_follow_their_save() {
+        let dir = temp_dir("favorites");
+        let game = Game::new(1_000_000, 1000, Vec::new());
+        for name in ["a", "b", "c"] { save(&save_file(&dir, name), &game).unwrap(); }
+        set_favorite(&save_file(&dir, "b"), true).unwrap();
+
+        let mut saves = saves_in_folder(Some(&dir), None).unwrap();
+        assert_eq!(saves.len(), 3);
+        favorites_first(&mut saves);
+        assert_eq!(saves[0].to_string(), "b (favorite)");
+        assert!(saves[1..].iter().all(|s| !s.favorite && !s.to_string().contains("favorite")));
+
+        let renamed = rename(&save_file(&dir, "b"), "d", None).unwrap();
+        assert!(is_favorite(&renamed) && !is_favorite(&save_file(&dir, "b")));
+        delete(&renamed).unwrap();
+        assert!(!favorite_path(&renamed).exists());
+        set_favorite(&save_file(&dir, "a"), false).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn the_loss_circuit_trips_past_its_limit() {
+        assert!(!exceeds_loss_limit(1000, 800, 2000));
+        assert!(exceeds_loss_limit(1000, 799, 2000));
+        assert!(!exceeds_loss_limit(0, -500, 2000));
+        assert!(exceeds_loss_limit(i64::MAX, i64::MIN, 2000));
+
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[100, 70])]);
+        let mut rng = GameRng::seed_from_u64(0);
+        game.player.deposit(100);
+        game.player.buy_stock(&game.stocks[0], 1).unwrap();
+        game.loss_circuit_bps = Some(2500);
+        assert!(!game.loss_circuit_tripped());
+        game.tick(&mut rng);
+        assert!(game.loss_circuit_tripped());
+        game.loss_circuit_bps = Some(3000);
+        assert!(!game.loss_circuit_tripped());
+    }
+
+    #[test]
+    fn sentiment_wanders_within_its_limits_only_when_enabled() {
+        let (mut game, mut rng) = seeded_game(12, 3);
+        for _ in 0..50 { game.advance_market(&mut rng); }
+        assert_eq!(game.sentiment, 0);
+
+        game.sentiment_enabled = true;
+        let mut seen = Vec::new();
+        for _ in 0..200 {
+            game.advance_market(&mut rng);
+            seen.push(game.sentiment);
+        }
+        assert!(seen.iter().all(|s| s.abs() <= SENTIMENT_MAX));
+        assert!(seen.iter().any(|&s| s != 0));
+
+        let old = without_field(&without_field(&game, "sentiment_enabled"), "sentiment");
+        assert_eq!((old.sentiment_enabled, old.sentiment), (false, 0));
+    }
+
+    #[test]
+    fn renaming_onto_another_save_leaves_both_alone() {
+        let dir = temp_dir("rename_taken");
+        let (a, b) = (save_file(&dir, "a"), save_file(&dir, "b"));
+        let mut game = Game::new(1_000_000, 1000, Vec::new());
+        save(&a, &game).unwrap();
+        game.goal = 5;
+        save(&b, &game).unwrap();
+
+        assert!(matches!(rename(&a, " b ", None), Err(Error::AlreadyExists)));
+        assert_eq!(from_path(&a).unwrap().goal, 1_000_000);
+        assert_eq!(from_path(&b).unwrap().goal, 5);
+        let renamed = rename(&a, " c ", None).unwrap();
+        assert_eq!(renamed, save_file(&dir, "c"));
+        assert_eq!(from_path(&renamed).unwrap().goal, 1_000_000);
+        assert!(!a.exists(), "the old save is gone");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn the_cooldown_allows_one_trade_per_stock_per_turn() {
+        let stocks = vec![scripted(0, &[10]), scripted(1, &[10])];
+        let mut game = Game::new(1_000_000, 0, stocks);
+        let mut rng = GameRng::seed_from_u64(0);
+        game.player.deposit(1000);
+        game.buy_stock(0, 2).unwrap();
+        game.buy_stock(0, 2).unwrap();
+
+        game.trade_cooldown = true;
+        assert_eq!(game.sell_stock(0, 1), Err(TransactionError::Cooldown));
+        game.buy_stock(1, 2).unwrap();
+        assert_eq!(game.buy_stock(1, 2), Err(TransactionError::Cooldown));
+        assert_eq!(game.player.stock_balance(&game.stocks[1]), 2);
+
+        game.tick(&mut rng);
+        assert!(!game.on_cooldown(0) && !game.on_cooldown(1));
+        game.sell_stock(0, 1).unwrap();
+        assert!(game.on_cooldown(0));
+    }
+
+    /// Moves every stock by a fixed amount in basis points, ignoring the scale.
+    struct Escalator(i64);
+
+    impl MarketModel for Escalator {
+        fn step(&mut self, stocks: &mut [Stock], _rng: &mut dyn rand::RngCore) {
+            for s in stocks.iter_mut() { s.shock(self.0); }
+        }
+    }
+
+    #[test]
+    fn games_move_their_market_with_a_swappable_model() {
+        let (mut game, mut rng) = seeded_game(5, 2);
+        let grown = |game: &Game| -> Vec<i64> {
+            values(game).into_iter().map(|(_, v)| v + v / 10).collect()
+        };
+        let expected = grown(&game);
+        game.market = Box::new(Escalator(1000));
+        game.advance_market(&mut rng);
+        assert_eq!(values(&game).into_iter().map(|(_, v)| v).collect::<Vec<_>>(), expected);
+
+        // Games can be handed to another thread, custom market and all
+        let expected = grown(&game);
+        let game = std::thread::spawn(move || {
+            game.advance_market(&mut rng);
+            game
+        }).join().unwrap();
+        assert_eq!(values(&game).into_iter().map(|(_, v)| v).collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn stocks_age_with_each_market_move() {
+        let (mut game, mut rng) = seeded_game(6, 2);
+        for _ in 0..3 { game.advance_market(&mut rng); }
+        assert!(game.stocks.iter().all(|s| s.turns_alive() == 3 && s.is_new()));
+    }
+
+    #[test]
+    fn longer_autosave_intervals_write_less_often() {
+        let writes = |interval: u32| {
+            let dir = temp_dir(&format!("autosave-{}", interval));
+            let path = save_file(&dir, "game");
+            let (mut game, mut rng) = seeded_game(9, 2);
+            game.goal = i64::MAX;
+            game.autosave_interval = interval;
+            let mut count = 0;
+            for _ in 0..12 {
+                if game.autosave_due() {
+                    save(&path, &game).unwrap();
+                    count += 1;
+                }
+                game.tick(&mut rng);
+            }
+            fs::remove_dir_all(&dir).unwrap();
+            count
+        };
+        assert_eq!(writes(1), 12);
+        assert_eq!(writes(3), 4);
+        assert_eq!(writes(0), 12);
+    }
+
+    #[test]
+    fn the_tutorial_is_small_and_guides_the_first_turns() {
+        let mut rng = GameRng::seed_from_u64(3);
+        let mut game = tutorial_game(&mut rng, 3);
+        assert!(game.tutorial);
+        assert_eq!((game.seed, game.goal, game.stocks.len() as i64), (3, 5000, TUTORIAL_STOCKS));
+        assert!(game.stocks.iter().all(|s| (10..=30).contains(&s.value())));
+        assert!(tutorial_hints(&game)[0].starts_with("Welcome!"));
+
+        game.player.buy_stock(&game.stocks[0], 1).unwrap();
+        assert_eq!(tutorial_hints(&game), ["Nice! Now choose End turn to see how prices move."]);
+        for _ in 0..3 { game.tick(&mut rng); }
+        let hints = tutorial_hints(&game);
+        assert!(hints.iter().any(|h| h.contains("locks in the profit")));
+        assert!(hints.last().unwrap().starts_with("Print net worth breakdown"));
+    }
+
+    #[test]
+    fn stocks_cant_go_bankrupt_during_the_grace_period() {
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[20, 0, 0])]);
+        let mut rng = GameRng::seed_from_u64(0);
+        game.bankruptcy_grace_turns = 1;
+        assert!(game.in_bankruptcy_grace());
+
+        let outcome = game.tick(&mut rng);
+        assert!(outcome.bankruptcies.is_empty());
+        assert_eq!(game.stocks[0].value(), 1);
+        assert!(game.in_bankruptcy_grace());
+
+        let outcome = game.tick(&mut rng);
+        assert!(!game.in_bankruptcy_grace());
+        assert_eq!(outcome.bankruptcies.len(), 1);
+        assert_eq!(without_field(&game, "bankruptcy_grace_turns").bankruptcy_grace_turns, 0);
+    }
+
+    #[test]
+    fn json_round_trips_without_sanitizing() {
+        let mut game = sample_game();
+        game.stocks.reverse();
+        let json = game.to_json().unwrap();
+        assert_eq!(JsonCodec.encode(&game).unwrap(), json.as_bytes());
+
+        let back = Game::from_json(&json).unwrap();
+        assert_eq!(back.to_json().unwrap(), json);
+        let mut sanitized = Game::from_json(&json).unwrap();
+        sanitized.sanitize();
+        assert_ne!(sanitized.to_json().unwrap(), json);
+        assert!(matches!(Game::from_json("{"), Err(Error::SerdeJsonError(_))));
+    }
+
+    #[test]
+    fn named_paths_number_taken_names() {
+        let dir = temp_dir("named-paths");
+        let mut rng = GameRng::seed_from_u64(2);
+        for _ in 0..50 {
+            let name = generate_save_name(&mut rng);
+            let words: Vec<&str> = name.split(' ').collect();
+            assert_eq!(words.len(), 2);
+            assert!(FIRST_NAME_WORDS.contains(&words[0]), "{}", name);
+            assert!(LAST_NAME_WORDS.contains(&words[1]), "{}", name);
+        }
+        let name = generate_save_name(&mut GameRng::seed_from_u64(2));
+
+        let game = Game::new(1_000_000, 1000, Vec::new());
+        let first = make_named_path(Some(&dir), &name, None).unwrap();
+        assert_eq!(first, save_file(&dir, &name));
+        save(&first, &game).unwrap();
+        let second = make_named_path(Some(&dir), &name, None).unwrap();
+        assert_eq!(second, save_file(&dir, &format!("{} (2)", name)));
+        save(&second, &game).unwrap();
+        assert_eq!(make_named_path(Some(&dir), &name, None).unwrap(),
+                   save_file(&dir, &format!("{} (3)", name)));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn game_trades_are_logged_per_stock() {
+        let stocks = vec![scripted(0, &[10, 12]), scripted(1, &[20])];
+        let mut game = Game::new(1_000_000, 0, stocks);
+        let mut rng = GameRng::seed_from_u64(0);
+        game.player.deposit(1000);
+        game.buy_stock(0, 5).unwrap();
+        game.buy_stock(1, 2).unwrap();
+        assert!(game.buy_stock(1, 1000).is_err());
+        game.tick(&mut rng);
+        game.sell_stock(0, 3).unwrap();
+
+        assert_eq!(game.player.trades_for(0), [
+            TradeRecord { turn: 0, stock_id: 0, price: 10, amount: 5 },
+            TradeRecord { turn: 1, stock_id: 0, price: 12, amount: -3 },
+        ]);
+        assert_eq!(game.player.trades_for(1).len(), 1);
+        assert!(game.player.trades_for(2).is_empty());
+    }
+
+    #[test]
+    fn reaching_the_goal_only_ends_games_that_stop_there() {
+        let mut game = Game::new(100, 0, Vec::new());
+        game.autosave_interval = 5;
+        game.turn = 3;
+        assert!(!game.is_over() && !game.autosave_due());
+
+        game.player.deposit(150);
+        assert!(game.has_won() && game.is_over() && game.autosave_due());
+        game.end_on_goal = false;
+        assert!(!game.is_over() && !game.autosave_due());
+
+        let old = without_field(&without_field(&game, "end_on_goal"), "goal_reached");
+        assert!(old.end_on_goal && !old.goal_reached);
+    }
+
+    #[test]
+    fn drawdowns_track_the_biggest_drops_from_a_peak() {
+        let mut game = Game::new(1_000_000, 0, Vec::new());
+        for net_worth in [1000, 500, 5000, 4000, 4500] { game.record_net_worth(net_worth); }
+        assert_eq!(game.peak_net_worth, 5000);
+        // The biggest drop and the biggest drop in proportion can come from different peaks
+        assert_eq!((game.max_drawdown, game.max_drawdown_bps), (1000, 5000));
+
+        let stats = game.summary_stats();
+        assert_eq!((stats.max_drawdown, stats.max_drawdown_bps), (1000, 5000));
+    }
+
+    #[test]
+    fn the_audit_explains_every_change_in_money() {
+        let (mut game, mut rng) = seeded_game(10, 3);
+        let before = game.audit_snapshot();
+        game.buy_stock(0, 1).unwrap();
+        assert_eq!(game.tick(&mut rng).audit, Ok(()));
+        game.sell_stock(0, 1).unwrap();
+        assert_eq!(game.audit_turn(&before), Ok(()));
+        assert_eq!(game.player.trade_cash(), game.player.realized_pl());
+
+        game.player.deposit(100);
+        assert_eq!(game.audit_turn(&before),
+                   Err(AuditError::UnexpectedCashFlow(CashFlowReason::ManualDeposit)));
+
+        let before = game.audit_snapshot();
+        let mut broken: serde_json::Value = serde_json::from_str(&game.to_json().unwrap())
+            .unwrap();
+        broken["player"]["balance"] = serde_json::json!(game.player.balance() + 7);
+        let broken = Game::from_json(&broken.to_string()).unwrap();
+        assert_eq!(broken.audit_turn(&before), Err(AuditError::Unexplained(7)));
+    }
+
+    #[test]
+    fn trades_come_in_whole_lots() {
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[10])]);
+        game.player.deposit(1000);
+        game.min_trade = 5;
+        assert_eq!((game.round_to_lot(14), game.round_to_lot(15), game.round_to_lot(4)),
+                   (10, 15, 0));
+        assert_eq!(game.buy_stock(0, 7), Err(TransactionError::BelowMinTrade));
+        game.buy_stock(0, 10).unwrap();
+        assert_eq!(game.sell_stock(0, 3), Err(TransactionError::BelowMinTrade));
+        game.sell_stock(0, 5).unwrap();
+        assert_eq!(game.player.stock_balance(&game.stocks[0]), 5);
+
+        game.min_trade = 0;
+        assert_eq!(game.round_to_lot(7), 7);
+        assert_eq!(without_field(&game, "min_trade").min_trade, 1);
+    }
+
+    #[test]
+    fn event_headlines_use_signed_percentages() {
+        let mut game = Game::new(1_000_000, 0, Vec::new());
+        let mut rng = GameRng::seed_from_u64(0);
+        let mut outcome = game.tick(&mut rng);
+        outcome.events = vec![
+            Event::IncomeModifier { multiplier_bps: 20000, turns: 1 },
+            Event::IncomeModifier { multiplier_bps: 5000, turns: 2 },
+            Event::FlashCrash { drop_bps: 4000, recovery_bps: 3000 },
+            Event::Recovery { recovery_bps: 2550 },
+        ];
+        let headlines = outcome.headlines();
+        for expected in ["Bonus payday! Your income changes by +100% for 1 turns.",
+                         "Slow week... Your income changes by -50% for 2 turns.",
+                         "Flash crash! Every stock moved -40%.",
+                         "The market bounced back! Every stock moved +25.50%."] {
+            assert!(headlines.contains(&expected.to_string()), "missing {}", expected);
+        }
+    }
+
+    #[test]
+    fn ownership_lists_the_only_player() {
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[10]), scripted(1, &[10])]);
+        game.player.deposit(100);
+        game.buy_stock(1, 4).unwrap();
+        assert_eq!(game.ownership_of(1), [(0, 4)]);
+        assert_eq!(game.ownership_of(0), [(0, 0)]);
+        assert_eq!(game.ownership_of(9), [(0, 0)]);
+    }
+
+    #[test]
+    fn save_extensions_pick_the_json_codec() {
+        assert_eq!(save_extension(Some("mgame.json")), ".mgame.json");
+        assert_eq!(save_extension(Some(".mgame.json")), ".mgame.json");
+        assert_eq!(save_extension(None), DEFAULT_SAVE_EXTENSION);
+
+        let dir = temp_dir("extensions");
+        assert!(codec_for(&save_file(&dir, "game")).is_ok());
+        assert!(codec_for(&dir.join("game.json")).is_ok());
+        assert!(matches!(codec_for(&dir.join("game.txt")), Err(Error::UnsupportedFormat(_))));
+        fs::write(dir.join("notes.txt"), "").unwrap();
+        save(&save_file(&dir, "game"), &Game::new(1_000_000, 0, Vec::new())).unwrap();
+        let names: Vec<String> = saves_in_folder(Some(&dir), None).unwrap().into_iter()
+            .map(|s| s.name).collect();
+        assert_eq!(names, ["game"]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn other_extensions_are_used_for_naming_and_listing() {
+        let dir = temp_dir("custom_extension");
+        let ext = Some(".mgame.json");
+        save(&save_file(&dir, "default"), &Game::new(1_000_000, 0, Vec::new())).unwrap();
+
+        let path = make_named_path(Some(&dir), "custom", ext).unwrap();
+        assert_eq!(path, dir.join("custom.mgame.json"));
+        save(&path, &Game::new(5, 0, Vec::new())).unwrap();
+        assert!(make_path(Some(&dir), Some("mgame.json")).unwrap().to_string_lossy()
+            .ends_with(".mgame.json"));
+
+        let names = |ext| -> Vec<String> {
+            saves_in_folder(Some(&dir), ext).unwrap().into_iter().map(|s| s.name).collect()
+        };
+        assert_eq!(names(ext), ["custom"]);
+        assert_eq!(names(None), ["default"]);
+
+        let renamed = rename(&path, "renamed", ext).unwrap();
+        assert_eq!(renamed, dir.join("renamed.mgame.json"));
+        assert_eq!(from_path(&renamed).unwrap().goal, 5);
+        assert_eq!(most_recent(Some(&dir), ext).unwrap().unwrap().name, "renamed");
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn new_saves_evict_the_oldest_unprotected_ones() {
+        let dir = temp_dir("evict");
+        let mut game = Game::new(1_000_000, 0, Vec::new());
+        for (secs, name) in [(100, "a"), (200, "b"), (300, "c"), (400, "d")] {
+            save(&save_file(&dir, name), &game).unwrap();
+            set_modified(&save_file(&dir, name), secs);
+        }
+        set_favorite(&save_file(&dir, "c"), true).unwrap();
+        try_lock(&save_file(&dir, "d")).unwrap();
+
+        game.max_saves = Some(3);
+        save(&save_file(&dir, "e"), &game).unwrap();
+        let mut names: Vec<String> = saves_in_folder(Some(&dir), None).unwrap().into_iter()
+            .map(|s| s.name).collect();
+        names.sort();
+        assert_eq!(names, ["c", "d", "e"]);
+
+        game.goal = 5;
+        save(&save_file(&dir, "e"), &game).unwrap();
+        assert_eq!(saves_in_folder(Some(&dir), None).unwrap().len(), 3);
+        unlock(&save_file(&dir, "d")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn notifications_are_filtered_by_verbosity() {
+        let stocks = vec![scripted(0, &[10, 12]), scripted(1, &[10, 0])];
+        let mut game = Game::new(1_000_000, 0, stocks);
+        let mut rng = GameRng::seed_from_u64(0);
+        let outcome = game.tick(&mut rng);
+        assert_eq!(outcome.price_moves, [("Stock 0".to_string(), 10, 12),
+                                         ("Stock 1".to_string(), 10, 0)]);
+
+        let texts = |verbosity| -> Vec<String> {
+            filter_notifications(outcome.notifications(), verbosity).into_iter()
+                .map(|n| n.text).collect()
+        };
+        assert_eq!(texts(Verbosity::Quiet), ["Stock 'Stock 1' went bankrupt!"]);
+        assert_eq!(texts(Verbosity::Normal), outcome.headlines());
+        assert_eq!(texts(Verbosity::Verbose), ["Stock 'Stock 0' moved from 10 to 12.",
+                                               "Stock 'Stock 1' moved from 10 to 0.",
+                                               "Stock 'Stock 1' went bankrupt!"]);
+        assert_eq!(without_field(&game, "verbosity").verbosity, Verbosity::Normal);
+    }
+
+    #[test]
+    fn turn_income_is_what_the_next_turn_pays() {
+        let mut game = Game::new(1_000_000, 100, vec![scripted(0, &[10])]);
+        let mut rng = GameRng::seed_from_u64(0);
+        game.buy_stock(0, 10).unwrap();
+        assert_eq!(game.turn_income(), 100);
+
+        game.control_threshold = Some(5);
+        game.control_bonus_bps = 1000;
+        game.income_modifier = Some((20000, 1));
+        assert_eq!(game.control_bonus(), 10);
+        assert_eq!(game.turn_income(), 210);
+
+        let expected = game.turn_income();
+        let before = game.player.balance();
+        game.tick(&mut rng);
+        assert_eq!(game.player.balance() - before, expected);
+    }
+
+    #[test]
+    fn express_mode_skips_only_routine_confirmations() {
+        let mut game = Game::new(1_000_000, 0, Vec::new());
+        let asked = |game: &Game| [Confirmation::IncreaseIncome, Confirmation::AddStock,
+                                   Confirmation::EndTurn, Confirmation::ResetPortfolio]
+            .map(|c| game.should_confirm(c));
+        assert_eq!(asked(&game), [true, true, false, true]);
+
+        game.express_mode = true;
+        game.confirm_end_turn = true;
+        assert_eq!(asked(&game), [false, false, true, true]);
+        assert!(!without_field(&game, "express_mode").express_mode);
+    }
+
+    #[test]
+    fn actions_with_no_shares_are_rejected_up_front() {
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[10])]);
+        let mut rng = GameRng::seed_from_u64(0);
+        game.player.deposit(100);
+        game.action_cap = Some(1);
+        game.apply_action(Action::Buy { stock_id: 0, amount: 2 }, &mut rng);
+
+        for action in [Action::Buy { stock_id: 0, amount: 0 },
+                       Action::Sell { stock_id: 0, amount: -2 },
+                       Action::Buy { stock_id: 9, amount: -1 }] {
+            assert!(matches!(game.apply_action(action, &mut rng),
+                             ActionResult::Failed(TransactionError::InvalidAmount)));
+        }
+        assert!(matches!(game.apply_action(Action::Sell { stock_id: 0, amount: 1 }, &mut rng),
+                         ActionResult::Failed(TransactionError::ActionBudgetExceeded)));
+        assert_eq!(game.player.stock_balance(&game.stocks[0]), 2);
+        assert_eq!(game.actions_taken_this_turn, 1);
+    }
+
+    #[test]
+    fn start_direction_applies_to_added_stocks() {
+        let direction = |stock: &Stock| serde_json::to_value(stock).unwrap()["direction"].clone();
+        let mut game = Game::new(1_000_000, 0, vec![]);
+        let mut rng = GameRng::seed_from_u64(0);
+        let flat = game.generate_added_stock(&mut rng, String::new());
+        assert_eq!(direction(&flat), 0);
+
+        game.start_direction_bps = 10000;
+        let trending: Vec<_> = (0..20)
+            .map(|_| game.generate_added_stock(&mut rng, String::new()))
+            .collect();
+        assert!(trending.iter().any(|stock| direction(stock) != 0));
+    }
+
+    #[test]
+    fn games_can_be_shared_between_threads() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<Game>();
+
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[10, 30])]);
+        let mut rng = GameRng::seed_from_u64(0);
+        game.player.deposit(100);
+        game.invalidate_net_worth();
+        let game = std::sync::Arc::new(game);
+        let shared = std::sync::Arc::clone(&game);
+        assert_eq!(std::thread::spawn(move || shared.net_worth_cached()).join().unwrap(), 100);
+
+        let mut game = std::sync::Arc::try_unwrap(game).ok().unwrap();
+        game.buy_stock(0, 5).unwrap();
+        assert_eq!(game.net_worth_cached(), game.player.net_worth(&game.stocks));
+        game.tick(&mut rng);
+        assert_eq!(game.net_worth_cached(), 50 + 5 * 30);
+    }
+
+    #[test]
+    fn validating_variables_names_every_problem() {
+        let range = StockRange::default();
+        let free = StockRange::new(0, 10, 1, 2);
+        let problems = |goal, income, balance, stocks, threshold, ratio, starting| {
+            validate_variables(goal, income, balance, stocks, threshold, ratio, starting, range)
+                .err().unwrap_or_default().len()
+        };
+        assert_eq!(problems(1_000_000, 1000, None, 5, Some(5000), 2, range), 0);
+        assert_eq!(problems(1_000_000, 0, Some(10), 0, None, 2, range), 0);
+
+        assert_eq!(problems(0, 1000, None, 5, None, 2, range), 1);
+        assert_eq!(problems(100, 1000, None, 5, None, 2, range), 1);
+        assert_eq!(problems(1_000_000, -1, Some(10), 5, None, 2, range), 1);
+        assert_eq!(problems(1_000_000, 1000, Some(-1), 5, None, 2, range), 1);
+        assert_eq!(problems(1_000_000, 0, None, 5, None, 2, range), 1);
+        assert_eq!(problems(1_000_000, 1000, None, -1, None, 2, range), 1);
+        assert_eq!(problems(1_000_000, 1000, None, MAX_STARTING_STOCKS + 1, None, 2, range), 1);
+        assert_eq!(problems(1_000_000, 1000, None, 5, Some(0), 2, range), 1);
+        assert_eq!(problems(1_000_000, 1000, None, 5, None, 1, range), 1);
+        assert_eq!(problems(1_000_000, 1000, None, 5, None, 2, free), 1);
+        assert_eq!(problems(0, -1, None, -1, Some(0), 0, free), 8);
+    }
+
+    #[test]
+    fn adding_a_stock_charges_exactly_once_or_not_at_all() {
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[10])]);
+        let mut rng = GameRng::seed_from_u64(0);
+        let cost = game.current_add_stock_cost();
+        game.player.deposit(cost - 1);
+        assert_eq!(game.add_random_stock(&mut rng).err(),
+                   Some(TransactionError::InsufficientFunds));
+        assert_eq!(game.player.balance(), cost - 1);
+        assert_eq!(game.stocks.len(), 1);
+
+        game.player.deposit(1);
+        let id = game.add_random_stock(&mut rng).unwrap().id();
+        assert_eq!(id, 1);
+        assert_eq!(game.player.balance(), 0);
+        assert_eq!(game.stocks.len(), 2);
+        assert_eq!(game.turns_since_stock_added, 0);
+
+        game.max_stocks = Some(2);
+        game.player.deposit(cost);
+        assert_eq!(game.add_random_stock(&mut rng).err(), Some(TransactionError::MarketFull));
+        assert_eq!(game.player.balance(), cost);
+        assert_eq!(game.stocks.len(), 2);
+    }
+
+    #[test]
+    fn observation_turns_move_the_market_without_the_player() {
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[10, 20, 30])]);
+        let mut rng = GameRng::seed_from_u64(0);
+        game.player.deposit(100);
+        game.observation_turns = 2;
+        assert_eq!(game.buy_stock(0, 1), Err(TransactionError::Observing));
+
+        assert!(game.observe_turn(&mut rng).unwrap().is_empty());
+        assert!(game.observe_turn(&mut rng).is_some());
+        assert!(game.observe_turn(&mut rng).is_none());
+        assert_eq!(game.stocks[0].value(), 30);
+        assert_eq!(game.player.balance(), 100);
+        assert_eq!(game.observation_turns, 0);
+        assert_eq!(game.buy_stock(0, 1), Ok(()));
+        assert_eq!(without_field(&game, "observation_turns").observation_turns, 0);
+    }
+
+    #[test]
+    fn top_movers_rank_by_percent_change() {
+        let mut game = Game::new(1_000_000, 0, vec![
+            scripted(0, &[10, 20]), scripted(1, &[10, 12]), scripted(2, &[10, 5]),
+            scripted(3, &[10, 9]), scripted(4, &[10, 10]), scripted(5, &[100, 150]),
+        ]);
+        let mut rng = GameRng::seed_from_u64(0);
+        let ids = |stocks: Vec<&Stock>| stocks.iter().map(|s| s.id()).collect::<Vec<_>>();
+        let (gainers, losers) = game.top_movers(3);
+        assert!(gainers.is_empty() && losers.is_empty());
+        game.tick(&mut rng);
+
+        let (gainers, losers) = game.top_movers(2);
+        assert_eq!((ids(gainers), ids(losers)), (vec![0, 5], vec![2, 3]));
+        let (gainers, losers) = game.top_movers(5);
+        assert_eq!((ids(gainers), ids(losers)), (vec![0, 5, 1], vec![2, 3]));
+        let (gainers, losers) = game.top_movers(0);
+        assert!(gainers.is_empty() && losers.is_empty());
+    }
+
+    #[test]
+    fn rerolling_replaces_every_stock_from_the_starting_range() {
+        let stocks = vec![scripted(0, &[10]), scripted(1, &[10]), scripted(2, &[10])];
+        let mut game = Game::new(1_000_000, 0, stocks);
+        game.player.deposit(100);
+        game.starting_stock_range = StockRange::new(1000, 2000, 1, 2);
+        game.unique_stock_names = true;
+        game.net_worth_cached();
+
+        game.reroll_market();
+        let ids: Vec<i64> = game.stocks.iter().map(|s| s.id()).collect();
+        assert_eq!(ids, vec![0, 1, 2]);
+        assert!(game.stocks.iter().all(|s| (1000..=2000).contains(&s.value())));
+        let names: BTreeSet<&str> = game.stocks.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names.len(), 3);
+        assert_eq!(game.player.balance(), 100);
+        assert_eq!(game.net_worth_cached(), 100);
+    }
+
+    #[test]
+    fn rerolls_can_be_rebuilt_from_the_seed_and_count() {
+        let (mut game, _) = seeded_game(8, 4);
+        let (mut same_seed, _) = seeded_game(8, 4);
+        let (mut other_seed, _) = seeded_game(9, 4);
+        let original = values(&game);
+
+        game.reroll_market();
+        let first = values(&game);
+        assert_ne!(first, original);
+        game.reroll_market();
+        assert_ne!(values(&game), first);
+        assert_eq!(game.rerolls, 2);
+
+        same_seed.reroll_market();
+        assert_eq!(values(&same_seed), first);
+        same_seed.reroll_market();
+        assert_eq!(values(&same_seed), values(&game));
+        other_seed.reroll_market();
+        assert_ne!(values(&other_seed), first);
+
+        let reloaded = Game::from_json(&game.to_json().unwrap()).unwrap();
+        assert_eq!(reloaded.rerolls, 2);
+        assert_eq!(without_field(&game, "rerolls").rerolls, 0);
+    }
+
+    #[test]
+    fn share_lines_give_the_seed_and_the_outcome() {
+        let mut game = Game::new(1000, 0, vec![scripted(0, &[10])]);
+        let mut rng = GameRng::seed_from_u64(0);
+        game.seed = 1234;
+        game.player.deposit(400);
+        game.tick(&mut rng);
+        assert_eq!(game.summary_stats().share_line(),
+                   "Seed: 1234 — finished with 400 after 1 turn");
+
+        game.player.deposit(700);
+        game.tick(&mut rng);
+        assert_eq!(game.summary_stats().share_line(), "Seed: 1234 — reached 1000 in 2 turns");
+
+        // Playing on past the goal doesn't change when it was reached
+        game.end_on_goal = false;
+        game.tick(&mut rng);
+        game.tick(&mut rng);
+        assert_eq!(game.summary_stats().turns_played, 4);
+        assert_eq!(game.summary_stats().share_line(), "Seed: 1234 — reached 1000 in 2 turns");
+        assert_eq!(without_field(&game, "goal_reached_turn").goal_reached_turn, None);
+    }
+
+    #[test]
+    fn income_upgrades_are_bought_when_affordable() {
+        let mut game = Game::new(1_000_000, 100, vec![scripted(0, &[10])]);
+        let mut rng = GameRng::seed_from_u64(0);
+        game.income_upgrade_cost = 300;
+        assert!(!game.tick(&mut rng).income_upgraded);
+        assert_eq!(game.player.balance(), 200);
+
+        game.auto_upgrade_income = true;
+        assert!(game.tick(&mut rng).income_upgraded);
+        assert_eq!(game.player.balance(), 0);
+        assert_eq!(game.player.income(), 200);
+        assert!(!game.tick(&mut rng).income_upgraded);
+        assert!(!without_field(&game, "auto_upgrade_income").auto_upgrade_income);
+    }
+
+    #[test]
+    fn exported_markets_import_without_the_player() {
+        let (mut game, mut rng) = seeded_game(3, 4);
+        game.tick(&mut rng);
+        game.player.deposit(500);
+        let json = game.export_market().unwrap();
+        assert!(!json.contains("balance"));
+
+        let fresh = Game::new(1_000_000, 0, import_market(&json).unwrap());
+        assert_eq!(values(&fresh), values(&game));
+        assert_eq!(fresh.player.balance(), 0);
+        assert!(matches!(import_market("{}"), Err(Error::SerdeJsonError(_))));
+    }
+
+    #[test]
+    fn free_trades_skip_the_spread_until_used_up() {
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[100])]);
+        let mut rng = GameRng::seed_from_u64(0);
+        game.player.deposit(1000);
+        game.spread_bps = 1000;
+        game.free_trades_per_turn = 1;
+        let ask = game.ask_price(&game.stocks[0]);
+        let bid = game.bid_price(&game.stocks[0]);
+        assert!(ask > 100 && bid < 100);
+
+        assert!(game.next_trade_free());
+        game.buy_stock(0, 2).unwrap();
+        assert_eq!(game.player.balance(), 800);
+        assert!(!game.next_trade_free());
+        assert_eq!(game.buy_price(&game.stocks[0]), ask);
+        game.buy_stock(0, 1).unwrap();
+        assert_eq!(game.player.balance(), 800 - ask);
+        assert_eq!(game.sell_price(&game.stocks[0]), bid);
+
+        game.tick(&mut rng);
+        assert_eq!(game.trades_this_turn, 0);
+        game.sell_stock(0, 3).unwrap();
+        assert_eq!(game.player.balance(), 1100 - ask);
+        assert_eq!(without_field(&game, "free_trades_per_turn").free_trades_per_turn, 0);
+    }
+
+    #[test]
+    fn listed_stocks_are_named_from_the_name_words() {
+        let mut game = Game::new(1_000_000, 0, vec![]);
+        let mut rng = GameRng::seed_from_u64(0);
+        game.name_words = Some(NameWords {
+            first: vec![("Only".to_string(), 1)],
+            last: vec![("Name".to_string(), 1)],
+        });
+        for _ in 0..3 {
+            let name = game.new_stock_name(&mut rng);
+            let stock = game.generate_added_stock(&mut rng, name);
+            game.stocks.push(stock);
+        }
+        let names: Vec<&str> = game.stocks.iter().map(|s| s.name.as_str()).collect();
+        assert_eq!(names, vec!["Only Name", "Only Name 2", "Only Name 3"]);
+
+        game.reroll_market();
+        assert_eq!(game.stocks[2].name, "Only Name 3");
+        assert_eq!(without_field(&game, "name_words").name_words, None);
+    }
+
+    #[test]
+    fn slow_turns_are_timed_and_reported() {
+        let mut game = Game::new(1_000_000, 0, vec![]);
+        let started = Local::now();
+        let after = |minutes| started + chrono::Duration::minutes(minutes);
+        assert_eq!(minutes_between(started, after(12)), 12);
+        assert_eq!(minutes_between(after(12), started), 0);
+
+        assert_eq!(game.record_turn_time(started, after(30)), None);
+        game.slow_turn_minutes = Some(10);
+        assert_eq!(game.record_turn_time(started, after(10)), None);
+        assert_eq!(game.record_turn_time(started, after(11)), Some(11));
+        assert_eq!(game.longest_turn_minutes, 30);
+        assert_eq!(game.summary_stats().longest_turn_minutes, 30);
+    }
+
+    #[test]
+    fn builders_keep_every_setting_but_none_of_the_progress() {
+        // Every setting changed from what `Game::new` gives
+        let configure = |game: &mut Game| {
+            game.goal = 5000;
+            game.initial_income = 300;
+            game.seed = 5;
+            game.initial_balance = Some(700);
+            game.add_stock_cost = 50;
+            game.income_upgrade_cost = 60;
+            game.split_threshold = Some(400);
+            game.split_ratio = 3;
+            game.starting_stock_range = StockRange::new(1, 2, 3, 4);
+            game.added_stock_range = StockRange::new(5, 6, 7, 8);
+            game.start_direction_bps = 10;
+            game.max_stocks = Some(9);
+            game.spread_bps = 300;
+            game.free_trades_per_turn = 2;
+            game.confirm_end_turn = !game.confirm_end_turn;
+            game.unique_stock_names = !game.unique_stock_names;
+            game.auto_upgrade_income = !game.auto_upgrade_income;
+            game.end_on_goal = !game.end_on_goal;
+            game.observation_turns = 3;
+            game.autosave_interval = 4;
+            game.max_saves = Some(6);
+            game.slow_turn_minutes = Some(7);
+            game.verbosity = Verbosity::Verbose;
+            game.add_stock_cost_decay_bps = 11;
+            game.add_stock_cost_floor = 12;
+            game.delist_below = Some(13);
+            game.delist_after_turns = 14;
+            game.income_event_chance_bps = 15;
+            game.express_mode = !game.express_mode;
+            game.halt_threshold_bps = Some(16);
+            game.news_len = 17;
+            game.spin_off_chance_bps = 18;
+            game.spin_off_fraction_bps = 19;
+            game.bankruptcy_grace_turns = 20;
+            game.tutorial = !game.tutorial;
+            game.min_trade = 5;
+            game.trade_cooldown = !game.trade_cooldown;
+            game.action_cap = Some(21);
+            game.settlement_turns = 2;
+            game.flash_crash_chance_bps = 50;
+            game.flash_crash_drop_bps = 22;
+            game.flash_crash_recovery_bps = 23;
+            game.ramp_bps = 100;
+            game.ramp_cap_bps = 24000;
+            game.loss_circuit_bps = Some(2000);
+            game.sentiment_enabled = !game.sentiment_enabled;
+            game.bust_threshold = Some(25);
+            game.show_stock_ids = !game.show_stock_ids;
+            game.stock_order = SortKey::Value;
+            game.rounding_mode = RoundingMode::Round;
+            game.control_threshold = Some(26);
+            game.control_bonus_bps = 27;
+            game.show_hints = !game.show_hints;
+            game.hide_empty_holdings = !game.hide_empty_holdings;
+            game.name_words = Some(NameWords { first: vec![("A".to_string(), 1)], last: vec![] });
+            game.alerts = vec![28, 29];
+            game.extra.insert("from_the_future".to_string(), serde_json::json!(30));
+        };
+        let (mut game, mut rng) = seeded_game(5, 3);
+        game.player.deposit(100_000);
+        game.buy_stock(0, 5).unwrap();
+        game.tick(&mut rng);
+        configure(&mut game);
+
+        let mut expected = Game::new(1, 1, Vec::new());
+        configure(&mut expected);
+        expected.reset_player();
+        let built = game.to_builder().build();
+        assert_eq!(built.to_json().unwrap(), expected.to_json().unwrap());
+        assert_eq!(built.to_builder(), game.to_builder());
+
+        assert!(built.stocks.is_empty());
+        assert_eq!(built.turn, 0);
+        assert_eq!(built.trades, 0);
+        assert_eq!(built.player.balance(), 700);
+
+        let mut builder = game.to_builder();
+        builder.spread_bps = 0;
+        builder.goal = 42;
+        let changed = builder.build();
+        assert_eq!((changed.spread_bps, changed.goal), (0, 42));
+        assert_eq!(changed.settlement_turns, 2);
+    }
+
+    #[test]
+    fn splits_never_raise_the_players_position() {
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[3]), scripted(1, &[103])]);
+        game.player.deposit(2000);
+        game.buy_stock(0, 20).unwrap();
+        game.buy_stock(1, 10).unwrap();
+        game.split_threshold = Some(1);
+        game.split_ratio = 5;
+
+        let mut worth = game.player.net_worth(&game.stocks);
+        for _ in 0..5 {
+            game.process_splits();
+            let now = game.player.net_worth(&game.stocks);
+            assert!(now <= worth);
+            worth = now;
+        }
+        assert_eq!(game.player.stock_balance(&game.stocks[0]), 20);
+        assert_eq!(game.stocks[1].value(), 4);
+        assert_eq!(game.player.stock_balance(&game.stocks[1]), 250);
+    }
+
+    #[test]
+    fn saves_are_kept_when_eviction_cant_make_room() {
+        let dir = temp_dir("evict_protected");
+        let mut game = Game::new(1_000_000, 0, Vec::new());
+        for (secs, name) in [(100, "a"), (200, "b"), (300, "c")] {
+            save(&save_file(&dir, name), &game).unwrap();
+            set_modified(&save_file(&dir, name), secs);
+        }
+        set_favorite(&save_file(&dir, "b"), true).unwrap();
+        set_favorite(&save_file(&dir, "c"), true).unwrap();
+
+        game.max_saves = Some(2);
+        save(&save_file(&dir, "d"), &game).unwrap();
+        assert_eq!(saves_in_folder(Some(&dir), None).unwrap().len(), 4);
+        assert_eq!(evict_oldest(&dir, 2, &save_file(&dir, "d"), None).unwrap(), 0);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn halted_stocks_cant_be_traded() {
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[10, 20, 20])]);
+        let mut rng = GameRng::seed_from_u64(0);
+        game.player.deposit(100);
+        game.halt_threshold_bps = Some(5000);
+        game.buy_stock(0, 2).unwrap();
+        game.tick(&mut rng);
+
+        assert!(game.stocks[0].is_halted());
+        assert_eq!(game.buy_stock(0, 1), Err(TransactionError::Halted));
+        assert_eq!(game.sell_stock(0, 1), Err(TransactionError::Halted));
+        assert!(matches!(game.apply_action(Action::Sell { stock_id: 0, amount: 2 }, &mut rng),
+                         ActionResult::Failed(TransactionError::Halted)));
+        assert_eq!(game.player.stock_balance(&game.stocks[0]), 2);
+
+        game.tick(&mut rng);
+        assert_eq!(game.sell_stock(0, 2), Ok(()));
+    }
+
+    #[test]
+    fn strong_sentiment_raises_default_range_stocks() {
+        let average = |sentiment_enabled: bool| {
+            let stocks = (0..10).map(|id| Stock::new(id, String::new(), 50, 2)).collect();
+            let mut game = Game::new(i64::MAX, 0, stocks);
+            let mut rng = GameRng::seed_from_u64(8);
+            game.sentiment_enabled = sentiment_enabled;
+            for _ in 0..100 {
+                game.sentiment = SENTIMENT_MAX;
+                game.tick(&mut rng);
+            }
+            game.stocks.iter().map(|s| s.value()).sum::<i64>() / 10
+        };
+        assert!(average(true) > average(false) + 30);
+    }
+
+    #[test]
+    fn delisted_stocks_leave_nothing_behind_for_new_ones() {
+        let stocks = vec![scripted(0, &[100]), scripted(1, &[100, 5, 5])];
+        let mut game = Game::new(1_000_000, 0, stocks);
+        let mut rng = GameRng::seed_from_u64(0);
+        game.delist_below = Some(10);
+        game.delist_after_turns = 2;
+        game.player.toggle_watch(1);
+        game.player.toggle_rebuy_after_bankruptcy(&game.stocks[1]);
+        game.player.add_recurring_buy(1, 50);
+        game.tick(&mut rng);
+        assert_eq!(game.tick(&mut rng).delistings.len(), 1);
+
+        assert!(!game.player.is_watched(1));
+        assert!(game.player.recurring_buys().is_empty());
+        assert_eq!(game.next_stock_id(), 2);
+        assert_eq!(without_field(&game, "stock_id_floor").next_stock_id(), 1);
+
+        game.player.deposit(game.current_add_stock_cost());
+        let added = game.add_random_stock(&mut rng).unwrap();
+        assert_eq!(added.id(), 2);
+        assert!(!game.player.rebuys_after_bankruptcy(&game.stocks[1]));
+    }
+
+    #[test]
+    fn automatic_buys_come_in_whole_lots() {
+        let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[20, 10, 0])]);
+        game.player.deposit(500);
+        game.buy_stock(0, 10).unwrap();
+        game.player.toggle_rebuy_after_bankruptcy(&game.stocks[0]);
+        game.min_trade = 3;
+        assert_eq!(game.buy_at(0, 20, 4), Err(TransactionError::BelowMinTrade));
+        let mut rng = GameRng::seed_from_u64(0);
+        game.tick(&mut rng);
+
+        let outcome = game.tick(&mut rng);
+        // 10 shares were worth 100 before the crash, enough for 5 at 20 but only 3 in lots
+        assert_eq!(outcome.bankruptcies[0].rebought, Some(3));
+        assert_eq!(game.player.stock_balance(&game.stocks[0]), 3);
+    }
+
+    #[test]
+    fn the_ramp_makes_later_turns_more_volatile() {
+        let stocks = (0..5).map(|id| Stock::new(id, format!("Stock {}", id), 100_000, 20))
+            .collect();
+        let mut game = Game::new(i64::MAX, 0, stocks);
+        game.ramp_bps = 200;
+        let mut rng = GameRng::seed_from_u64(27);
+        let mut moves = Vec::new();
+        for _ in 0..120 {
+            let before = values(&game);
+            game.tick(&mut rng);
+            moves.push(before.iter().zip(values(&game))
+                .map(|((_, a), (_, b))| (b - a).abs())
+                .sum::<i64>());
+        }
+
+        let early: i64 = moves[..20].iter().sum();
+        let late: i64 = moves[100..].iter().sum();
+        assert!(late > early * 2, "early {} late {}", early, late);
+    }
+
+    #[test]
+    fn liquidation_values_match_what_selling_pays() {
+        for mode in [RoundingMode::Truncate, RoundingMode::Round, RoundingMode::Ceil] {
+            let mut game = Game::new(1_000_000, 0, vec![scripted(0, &[15])]);
+            game.rounding_mode = mode;
+            game.spread_bps = 1000;
+            game.player.deposit(100);
+            game.buy_stock(0, 3).unwrap();
+
+            let liquidation = game.player.net_liquidation_value(&game.stocks, 0, game.spread_bps,
+                                                                game.rounding_mode);
+            game.sell_stock(0, 3).unwrap();
+            assert_eq!(liquidation, game.player.balance());
+        }
+    }
 }